@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use crate::OmagariProject;
+use crate::controller::{ExportedProject, export_filename};
+use crate::validate::Severity;
+
+const USAGE: &str = "usage:
+  omagari                                     start the editor
+  omagari check <in.omagari.ron>...           validate project files
+  omagari export <in.omagari.ron>... [-o out.hanabi.ron]
+                                              validate and export project files";
+
+/// Runs a headless subcommand when one is given.
+///
+/// Returns the process exit code, or `None` when the arguments do not name a
+/// subcommand and the editor should start instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let code = match command.as_str() {
+        "check" => check(rest),
+        "export" => export(rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            0
+        }
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Loads and validates a project, printing any problems. Returns the project
+/// only if it has no errors.
+fn load_checked(path: &str) -> Option<OmagariProject> {
+    let project = match OmagariProject::load(path) {
+        Ok(project) => project,
+        Err(e) => {
+            eprintln!("error: {}: {}", path, e);
+            return None;
+        }
+    };
+    let diagnostics = project.validate();
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}: {}", path, diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        None
+    } else {
+        Some(project)
+    }
+}
+
+fn check(files: &[String]) -> i32 {
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut failed = 0;
+    for file in files {
+        if load_checked(file).is_none() {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} of {} project(s) failed", failed, files.len());
+        1
+    } else {
+        0
+    }
+}
+
+fn export(args: &[String]) -> i32 {
+    let mut inputs = Vec::new();
+    let mut output: Option<PathBuf> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-o" || arg == "--output" {
+            match args.next() {
+                Some(o) => output = Some(PathBuf::from(o)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            }
+        } else {
+            inputs.push(arg.clone());
+        }
+    }
+    if inputs.is_empty() || (output.is_some() && inputs.len() > 1) {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut failed = false;
+    for input in inputs.iter() {
+        let Some(target) = output.clone().or_else(|| export_filename(input)) else {
+            eprintln!("error: {}: expected a .omagari.ron file or -o", input);
+            failed = true;
+            continue;
+        };
+        let Some(project) = load_checked(input) else {
+            failed = true;
+            continue;
+        };
+        match ExportedProject::from(&project).save(&target) {
+            Ok(()) => println!("{} -> {}", input, target.display()),
+            Err(e) => {
+                eprintln!("error: {}: {}", target.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed { 1 } else { 0 }
+}
@@ -1,75 +1,195 @@
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
 use bevy::{platform::collections::HashMap, prelude::*};
 use bevy_hanabi::prelude::*;
-use ron::from_str;
-use std::fs::File;
 use std::io;
-use std::io::Read;
 
-use omagari::controller::ExportedProject;
+use crate::OmagariBundle;
+use crate::controller::ExportedProject;
+use crate::properties::EffectProperty;
 
-struct PreparedEffect {
-    name: String,
-    parent: Option<String>,
-    texture_index: Option<usize>,
-    effect_handle: Handle<EffectAsset>,
+/// A single effect of an [`EffectComplex`], with its asset handles resolved.
+#[derive(Clone)]
+pub struct PreparedEffect {
+    pub name: String,
+    pub parent: Option<String>,
+    pub texture: Option<Handle<Image>>,
+    /// Properties that can be set through the `EffectProperties` component
+    /// of the spawned effect.
+    pub properties: Vec<EffectProperty>,
+    pub effect_handle: Handle<EffectAsset>,
 }
 
-struct EffectComplex {
-    omagari_project: ExportedProject,
-    prepared_effects: Vec<PreparedEffect>,
+/// A set of related effects loaded from an exported `{project_name}.hanabi.ron` file.
+#[derive(Asset, TypePath, Clone)]
+pub struct EffectComplex {
+    pub prepared_effects: Vec<PreparedEffect>,
 }
 
 impl EffectComplex {
-    pub fn setup(
-        filename: &str,
-        mut effects: ResMut<Assets<EffectAsset>>,
-    ) -> Result<Self, io::Error> {
-        let mut file = File::open(filename)?;
-        let mut ron_string = String::new();
-        file.read_to_string(&mut ron_string)?;
-        let omagari_project: ExportedProject =
-            from_str(&ron_string).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-        let mut prepared_effects: Vec<PreparedEffect> = Vec::new();
-        for effect in omagari_project.effects.iter() {
-            let h = effects.add(effect.effect_asset.clone());
-            prepared_effects.push(PreparedEffect {
-                name: effect.name.to_string(),
-                parent: effect.parent.clone(),
-                texture_index: effect.texture_index.clone(),
-                effect_handle: h.clone(),
-            });
+    /// Spawns every effect of the complex as a child of `root`.
+    pub fn spawn_children(&self, commands: &mut Commands, root: Entity) -> Vec<Entity> {
+        let spawned: Vec<Entity> = self
+            .prepared_effects
+            .iter()
+            .map(|prepared_effect| {
+                let mut e = commands.spawn((
+                    Name::new(prepared_effect.name.clone()),
+                    ParticleEffect::new(prepared_effect.effect_handle.clone()),
+                    Transform::IDENTITY,
+                    ChildOf(root),
+                ));
+                if let Some(texture) = &prepared_effect.texture {
+                    e.insert(EffectMaterial {
+                        images: vec![texture.clone()],
+                    });
+                }
+                e.id()
+            })
+            .collect();
+
+        let parents = resolve_parents(
+            self.prepared_effects
+                .iter()
+                .map(|e| (e.name.as_str(), e.parent.as_deref())),
+        );
+        for (index, parent) in parents.into_iter().enumerate() {
+            if let Some(parent) = parent {
+                commands
+                    .entity(spawned[index])
+                    .insert(EffectParent::new(spawned[parent]));
+            }
         }
-        Ok(Self {
-            omagari_project,
-            prepared_effects,
-        })
+        spawned
+    }
+
+    /// Spawns the complex under a new root entity and returns the root.
+    pub fn spawn(&self, commands: &mut Commands) -> Entity {
+        let root = commands
+            .spawn((Transform::IDENTITY, Visibility::default()))
+            .id();
+        let effects = self.spawn_children(commands, root);
+        commands.entity(root).insert(OmagariComplexEffects(effects));
+        root
     }
+}
 
-    pub fn spawn(&self, commands: &mut Commands, textures: Vec<Handle<Image>>) {
-        let mut refs: HashMap<String, Entity> = HashMap::new();
-        for prepared_effect in self.prepared_effects.iter() {
-            let mut e = commands.spawn((
-                Name::new(prepared_effect.name.clone()),
-                ParticleEffect::new(prepared_effect.effect_handle.clone()),
-                Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-            ));
-
-            if let Some(texture_index) = prepared_effect.texture_index {
-                e.insert(EffectMaterial {
-                    images: vec![textures[texture_index].clone()],
-                });
+/// Resolves each effect's parent name to the index of the parent effect.
+///
+/// All names are collected before any parent is looked up, so the order of
+/// effects does not matter. Unknown parent names are reported and ignored.
+pub fn resolve_parents<'a>(
+    effects: impl Iterator<Item = (&'a str, Option<&'a str>)> + Clone,
+) -> Vec<Option<usize>> {
+    let refs: HashMap<&str, usize> = effects
+        .clone()
+        .enumerate()
+        .map(|(index, (name, _))| (name, index))
+        .collect();
+    effects
+        .map(|(name, parent)| {
+            let parent = parent?;
+            let index = refs.get(parent).copied();
+            if index.is_none() {
+                warn!("Effect '{}' refers to an unknown parent '{}'", name, parent);
             }
+            index
+        })
+        .collect()
+}
 
-            refs.insert(prepared_effect.name.clone(), e.id());
+/// Spawns an [`EffectComplex`] under this entity once the asset is loaded.
+///
+/// Every effect becomes a child of this entity, so the whole complex can be
+/// moved, hidden or despawned as a unit. The effects are respawned when the
+/// asset is reloaded or the handle is replaced.
+#[derive(Component, Clone)]
+#[require(Transform, Visibility)]
+pub struct SpawnOmagariComplex(pub Handle<EffectComplex>);
 
-            if let Some(parent) = &prepared_effect.parent {
-                if let Some(entity) = refs.get(parent) {
-                    e.insert(EffectParent::new(*entity));
-                } else {
-                    // Error
-                }
+/// The effect entities spawned for an [`EffectComplex`].
+#[derive(Component, Default)]
+pub struct OmagariComplexEffects(pub Vec<Entity>);
+
+pub fn spawn_omagari_complexes(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<EffectComplex>>,
+    complexes: Res<Assets<EffectComplex>>,
+    roots: Query<(
+        Entity,
+        Ref<SpawnOmagariComplex>,
+        Option<&OmagariComplexEffects>,
+    )>,
+) {
+    let modified: Vec<AssetId<EffectComplex>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (root, spawner, spawned) in roots.iter() {
+        let id = spawner.0.id();
+        let respawn = match spawned {
+            None => true,
+            Some(_) => spawner.is_changed() || modified.contains(&id),
+        };
+        if !respawn {
+            continue;
+        }
+        if let Some(spawned) = spawned {
+            for effect in spawned.0.iter() {
+                commands.entity(*effect).despawn();
             }
+            commands.entity(root).remove::<OmagariComplexEffects>();
+        }
+        if let Some(complex) = complexes.get(id) {
+            let effects = complex.spawn_children(&mut commands, root);
+            commands.entity(root).insert(OmagariComplexEffects(effects));
         }
     }
 }
+
+/// Loads `.hanabi.ron` files exported by Omagari as [`EffectComplex`] assets.
+///
+/// Every effect is added as a labeled sub-asset named after the effect, e.g.
+/// `fireworks.hanabi.ron#Rocket`.
+#[derive(Default)]
+pub struct EffectComplexLoader;
+
+impl AssetLoader for EffectComplexLoader {
+    type Asset = EffectComplex;
+    type Settings = ();
+    type Error = io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let exported: ExportedProject = ron::de::from_bytes(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let bundle = OmagariBundle::from(exported);
+        let prepared_effects = bundle
+            .effects
+            .into_iter()
+            .map(|effect| PreparedEffect {
+                texture: effect.texture_asset.map(|t| load_context.load(t)),
+                effect_handle: load_context.add_labeled_asset(effect.name.clone(), effect.effect),
+                name: effect.name,
+                parent: effect.parent,
+                properties: effect.properties,
+            })
+            .collect();
+
+        Ok(EffectComplex { prepared_effects })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hanabi.ron"]
+    }
+}
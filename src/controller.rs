@@ -1,59 +1,296 @@
 use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_egui::egui;
 use bevy_hanabi::prelude::*;
-use ron::de::from_str;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
     fs::File,
-    io::{self, Read},
+    io::{self, Write},
     rc::Rc,
 };
 
 use crate::OmagariProject;
+use crate::complex::resolve_parents;
 use crate::editor_prelude::AppContext;
+use crate::effect::EffectEditor;
+use crate::helpers::ui_for_list_item;
+use crate::history::EditGeneration;
+use crate::properties::EffectProperty;
+use crate::validate::Diagnostic;
 
 #[derive(Resource)]
 pub struct EffectResource {
-    pub effect_handles: Vec<Handle<EffectAsset>>,
-    pub textures: Vec<Handle<Image>>,
+    pub previews: Vec<PreviewedEffect>,
+    pub textures: HashMap<String, Handle<Image>>,
+    pub auto_preview: bool,
+    /// Edit generation the previews were last updated at.
+    pub previewed: Option<EditGeneration>,
+    /// Problems of the project, found at the edit generation `validated`.
+    pub diagnostics: Vec<Diagnostic>,
+    pub validated: Option<EditGeneration>,
     pub context: AppContext,
 }
 
+/// An effect currently spawned in the editor preview.
+pub struct PreviewedEffect {
+    pub name: String,
+    pub handle: Handle<EffectAsset>,
+    pub entity: Entity,
+    snapshot: String,
+}
+
+/// Serialized state of an effect and of the definitions it may inline, used
+/// to detect which effects changed since they were last spawned.
+fn preview_snapshot(project: &OmagariProject, effect: &EffectEditor) -> String {
+    let texture_path = effect.texture().and_then(|t| project.texture_path(t));
+    format!(
+        "{}{:?}{}",
+        ron::ser::to_string(effect).unwrap_or_default(),
+        texture_path,
+        ron::ser::to_string(&project.definitions).unwrap_or_default()
+    )
+}
+
+/// Despawns every previewed effect and spawns the whole project again.
 pub fn spawn_particle_effects(
     commands: &mut Commands,
     res: &mut EffectResource,
     clone: Rc<RefCell<&mut OmagariProject>>,
-    mut effects: ResMut<Assets<EffectAsset>>,
-    curr: Query<Entity, With<ParticleEffect>>,
+    effects: &mut Assets<EffectAsset>,
+    curr: &Query<Entity, With<ParticleEffect>>,
 ) {
-    for h in res.effect_handles.iter() {
-        effects.remove(h);
+    for preview in res.previews.drain(..) {
+        effects.remove(&preview.handle);
     }
     for e in curr.iter() {
         commands.entity(e).despawn();
     }
-    let mut refs: HashMap<String, Entity> = HashMap::new();
-    for effect in clone.borrow().effects.iter() {
-        let h = effects.add(effect.produce());
-        res.effect_handles.push(h.clone());
+    update_particle_effects(commands, res, &clone.borrow(), effects);
+}
+
+/// Respawns only the effects that changed since the last update.
+///
+/// An effect is also respawned when its parent effect is, since the parent
+/// link is bound to the parent's entity.
+pub fn update_particle_effects(
+    commands: &mut Commands,
+    res: &mut EffectResource,
+    project: &OmagariProject,
+    effects: &mut Assets<EffectAsset>,
+) {
+    let snapshots: Vec<String> = project
+        .effects
+        .iter()
+        .map(|effect| preview_snapshot(project, effect))
+        .collect();
+    let parents = resolve_parents(project.effects.iter().map(|e| (e.name(), e.parent_name())));
+
+    let mut previous = std::mem::take(&mut res.previews);
+    let mut kept: Vec<Option<PreviewedEffect>> = project
+        .effects
+        .iter()
+        .zip(snapshots.iter())
+        .map(|(effect, snapshot)| {
+            let index = previous
+                .iter()
+                .position(|p| p.name == effect.name() && p.snapshot == *snapshot)?;
+            Some(previous.swap_remove(index))
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, parent) in parents.iter().enumerate() {
+            if let Some(parent) = parent
+                && kept[*parent].is_none()
+                && let Some(preview) = kept[index].take()
+            {
+                previous.push(preview);
+                changed = true;
+            }
+        }
+    }
+
+    for preview in previous {
+        effects.remove(&preview.handle);
+        commands.entity(preview.entity).despawn();
+    }
+
+    let respawned: Vec<bool> = kept.iter().map(|k| k.is_none()).collect();
+    for (index, effect) in project.effects.iter().enumerate() {
+        if kept[index].is_some() {
+            continue;
+        }
+        let h = effects.add(effect.produce(&project.definitions));
         let mut e = commands.spawn((
             ParticleEffect::new(h.clone()),
-            EffectMaterial {
-                images: vec![res.textures[effect.texture_index().unwrap_or(0)].clone()],
-            },
             Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
+            res.context
+                .preview_properties
+                .effect_properties(effect.name(), effect.properties()),
         ));
-        refs.insert(effect.name().to_string(), e.id());
+        if let Some(texture) = effect
+            .texture()
+            .and_then(|t| project.texture_path(t))
+            .and_then(|path| res.textures.get(path))
+        {
+            e.insert(EffectMaterial {
+                images: vec![texture.clone()],
+            });
+        }
+        kept[index] = Some(PreviewedEffect {
+            name: effect.name().to_string(),
+            handle: h,
+            entity: e.id(),
+            snapshot: snapshots[index].clone(),
+        });
+    }
 
-        if let Some(parent) = &effect.parent() {
-            if let Some(entity) = refs.get(parent) {
-                e.insert(EffectParent::new(*entity));
-            } else {
-                // Error
-            }
+    let previews: Vec<PreviewedEffect> = kept.into_iter().flatten().collect();
+    for (index, parent) in parents.into_iter().enumerate() {
+        if let Some(parent) = parent
+            && respawned[index]
+        {
+            commands
+                .entity(previews[index].entity)
+                .insert(EffectParent::new(previews[parent].entity));
+        }
+    }
+    res.previews = previews;
+}
+
+/// Pushes the preview property values edited since the last call to the
+/// previewed effects.
+pub fn update_preview_properties(
+    commands: &mut Commands,
+    res: &mut EffectResource,
+    project: &OmagariProject,
+) {
+    if !res.context.preview_properties.take_changed() {
+        return;
+    }
+    for preview in res.previews.iter() {
+        if let Some(effect) = project.effects.iter().find(|e| e.name() == preview.name) {
+            commands.entity(preview.entity).insert(
+                res.context
+                    .preview_properties
+                    .effect_properties(effect.name(), effect.properties()),
+            );
         }
     }
 }
 
 pub fn validate_project_filename<P: AsRef<std::path::Path>>(p: P) -> bool {
-    p.as_ref().ends_with("omagari.ron")
+    p.as_ref().to_string_lossy().ends_with(".omagari.ron")
+}
+
+/// Maps `{project_name}.omagari.ron` to `{project_name}.hanabi.ron`.
+pub fn export_filename<P: AsRef<std::path::Path>>(p: P) -> Option<std::path::PathBuf> {
+    let filename = p.as_ref().to_string_lossy();
+    filename
+        .strip_suffix(".omagari.ron")
+        .map(|stem| std::path::PathBuf::from(format!("{}.hanabi.ron", stem)))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExportedEffect {
+    pub name: String,
+    pub parent: Option<String>,
+    pub texture: Option<String>,
+    /// Properties the game can set on spawned instances of the effect.
+    #[serde(default)]
+    pub properties: Vec<EffectProperty>,
+    pub effect_asset: EffectAsset,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ExportedProject {
+    pub effects: Vec<ExportedEffect>,
+}
+
+impl From<&OmagariProject> for ExportedProject {
+    fn from(project: &OmagariProject) -> Self {
+        Self {
+            effects: project
+                .effects
+                .iter()
+                .map(|effect| ExportedEffect {
+                    name: effect.name().to_string(),
+                    parent: effect.parent(),
+                    texture: effect
+                        .texture()
+                        .and_then(|t| project.texture_path(t))
+                        .map(str::to_string),
+                    properties: effect.properties().to_vec(),
+                    effect_asset: effect.produce(&project.definitions),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ExportedProject {
+    pub fn save<P: AsRef<std::path::Path>>(&self, p: P) -> Result<(), std::io::Error> {
+        let ron_string =
+            ron::ser::to_string_pretty(self, PrettyConfig::new().new_line("\n".to_string()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut file = File::create(p)?;
+        file.write_all(ron_string.as_bytes())
+    }
+}
+
+/// Turns a picked file into an asset path, relative to the asset folder when
+/// the file lives inside it.
+pub fn texture_asset_path(path: &std::path::Path) -> String {
+    let base = bevy::asset::io::file::FileAssetReader::get_base_path().join("assets");
+    path.strip_prefix(&base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+pub fn ui_for_texture_library(project: &mut OmagariProject, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new("Textures")
+        .id_salt("texture library")
+        .show(ui, |ui| {
+            let n_textures = project.textures.len();
+            let mut rename = None;
+            let mut command = None;
+            for index in 0..n_textures {
+                ui.horizontal(|ui| {
+                    if let Some(list_command) = ui_for_list_item(ui, index, n_textures) {
+                        command = Some(list_command);
+                    }
+                    let texture = &project.textures[index];
+                    let mut name = texture.name.clone();
+                    ui.add(egui::TextEdit::singleline(&mut name).desired_width(80.0));
+                    ui.label(&texture.path);
+                    if name != texture.name && !name.is_empty() {
+                        rename = Some((texture.name.clone(), name));
+                    }
+                });
+            }
+            if let Some((from, to)) = rename
+                && !project.textures.iter().any(|t| t.name == to)
+            {
+                project.rename_texture(&from, &to);
+            }
+            if let Some(command) = command {
+                command.apply(&mut project.textures);
+            }
+            if ui.button("⮉ IMPORT").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("image", &["png", "jpg", "jpeg", "ktx2", "dds"])
+                    .set_directory(".")
+                    .pick_file()
+            {
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or("Texture".to_string());
+                project.add_texture(&name, &texture_asset_path(&path));
+            }
+        });
 }
@@ -0,0 +1,257 @@
+use bevy_egui::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::OmagariProject;
+use crate::editor_prelude::AppContext;
+use crate::expr::ExprWriterEditor;
+use crate::helpers::*;
+use crate::modifiers::ExprSlots;
+use crate::syntax::is_identifier;
+use crate::validate::modifier_label;
+
+/// A named project-level expression. Expressions refer to it with
+/// [`ExprWriterEditor::Ref`] and it is inlined wherever it is used, so editing
+/// it changes every effect using it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExprDefinition {
+    pub name: String,
+    pub expr: ExprWriterEditor,
+}
+
+impl ExprDefinition {
+    pub fn new(name: &str, expr: ExprWriterEditor) -> Self {
+        Self {
+            name: name.to_string(),
+            expr,
+        }
+    }
+}
+
+pub fn find_definition<'a>(
+    definitions: &'a [ExprDefinition],
+    name: &str,
+) -> Option<&'a ExprDefinition> {
+    definitions.iter().find(|d| d.name == name)
+}
+
+/// Calls `f` with the names of the definitions referenced by `expr`,
+/// directly and not through other definitions.
+pub fn for_each_ref(expr: &ExprWriterEditor, f: &mut impl FnMut(&str)) {
+    match expr {
+        ExprWriterEditor::Ref(name) => f(name),
+        ExprWriterEditor::Operator(o, _) => {
+            for operand in o.operands() {
+                for_each_ref(operand, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Calls `f` with the names of the definitions referenced by `expr`, to
+/// change them.
+pub fn for_each_ref_mut(expr: &mut ExprWriterEditor, f: &mut impl FnMut(&mut String)) {
+    match expr {
+        ExprWriterEditor::Ref(name) => f(name),
+        ExprWriterEditor::Operator(o, _) => {
+            for operand in o.operands_mut() {
+                for_each_ref_mut(operand, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the chain of names leading from `name` back to itself, e.g.
+/// `["a", "b", "a"]`, when the definition refers to itself.
+pub fn definition_cycle(definitions: &[ExprDefinition], name: &str) -> Option<Vec<String>> {
+    fn visit(
+        definitions: &[ExprDefinition],
+        name: &str,
+        chain: &mut Vec<String>,
+        visited: &mut Vec<String>,
+    ) -> bool {
+        if chain.first().is_some_and(|first| first == name) {
+            chain.push(name.to_string());
+            return true;
+        }
+        if visited.iter().any(|v| v == name) {
+            return false;
+        }
+        visited.push(name.to_string());
+        let Some(definition) = find_definition(definitions, name) else {
+            return false;
+        };
+        chain.push(name.to_string());
+        let mut found = false;
+        for_each_ref(&definition.expr, &mut |next| {
+            found = found || visit(definitions, next, chain, visited);
+        });
+        if !found {
+            chain.pop();
+        }
+        found
+    }
+
+    let definition = find_definition(definitions, name)?;
+    let mut chain = vec![name.to_string()];
+    let mut visited = Vec::new();
+    let mut found = false;
+    for_each_ref(&definition.expr, &mut |next| {
+        found = found || visit(definitions, next, &mut chain, &mut visited);
+    });
+    found.then_some(chain)
+}
+
+/// The expression `expr` stands for, following references to definitions.
+/// Unknown and cyclic references are returned as they are.
+pub fn resolve_refs<'a>(
+    definitions: &'a [ExprDefinition],
+    mut expr: &'a ExprWriterEditor,
+) -> &'a ExprWriterEditor {
+    for _ in 0..definitions.len() {
+        match expr {
+            ExprWriterEditor::Ref(name) => match find_definition(definitions, name) {
+                Some(definition) => expr = &definition.expr,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    expr
+}
+
+impl OmagariProject {
+    /// Renames a definition and every reference to it, in definitions,
+    /// effects and project snippets.
+    pub fn rename_definition(&mut self, from: &str, to: &str) {
+        for definition in self.definitions.iter_mut().filter(|d| d.name == from) {
+            definition.name = to.to_string();
+        }
+        let mut rename = |expr: &mut ExprWriterEditor| {
+            for_each_ref_mut(expr, &mut |name| {
+                if name == from {
+                    *name = to.to_string();
+                }
+            })
+        };
+        for definition in self.definitions.iter_mut() {
+            rename(&mut definition.expr);
+        }
+        for snippet in self.snippets.iter_mut() {
+            rename(&mut snippet.expr);
+        }
+        for effect in self.effects.iter_mut() {
+            effect.for_each_expr_mut(&mut rename);
+        }
+    }
+
+    /// Locations of every expression referring to the definition `name`, in
+    /// the same format as diagnostic locations.
+    pub fn definition_usages(&self, name: &str) -> Vec<String> {
+        let mut usages = Vec::new();
+        let mut check = |location: String, expr: &ExprWriterEditor| {
+            let mut uses = false;
+            for_each_ref(expr, &mut |r| uses |= r == name);
+            if uses {
+                usages.push(location);
+            }
+        };
+        for definition in self.definitions.iter() {
+            check(
+                format!("Definition '{}'", definition.name),
+                &definition.expr,
+            );
+        }
+        for effect in self.effects.iter() {
+            let lists = [
+                ("Init", effect.init_modifiers()),
+                ("Update", effect.update_modifiers()),
+            ];
+            for (list, modifiers) in lists {
+                for (index, modifier) in modifiers.iter().enumerate() {
+                    for (slot, expr, _) in modifier.exprs() {
+                        check(
+                            format!(
+                                "{} › {} › {}",
+                                effect.name(),
+                                modifier_label(list, index, modifier),
+                                slot
+                            ),
+                            expr,
+                        );
+                    }
+                }
+            }
+        }
+        usages
+    }
+}
+
+/// Returns `name`, suffixed if it is already taken by one of `definitions`.
+fn unique_definition_name(definitions: &[ExprDefinition], name: &str) -> String {
+    let mut unique = name.to_string();
+    let mut n = 1;
+    while find_definition(definitions, &unique).is_some() {
+        n += 1;
+        unique = format!("{}{}", name, n);
+    }
+    unique
+}
+
+pub fn ui_for_definitions(project: &mut OmagariProject, app: &mut AppContext, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new("Definitions")
+        .id_salt("definitions")
+        .show(ui, |ui| {
+            let n_definitions = project.definitions.len();
+            let mut command = None;
+            let mut rename = None;
+            for index in 0..n_definitions {
+                ui.push_id(("definition", index), |ui| {
+                    ui.horizontal(|ui| {
+                        if let Some(list_command) = ui_for_list_item(ui, index, n_definitions) {
+                            command = Some(list_command);
+                        }
+                        let name = project.definitions[index].name.clone();
+                        let mut new_name = name.clone();
+                        ui.add(egui::TextEdit::singleline(&mut new_name).desired_width(120.0))
+                            .on_hover_text("Definition names are identifiers, e.g. 'spark_color'");
+                        if new_name != name {
+                            rename = Some((name.clone(), new_name));
+                        }
+                        ui.menu_button("🔍", |ui| {
+                            let usages = project.definition_usages(&name);
+                            if usages.is_empty() {
+                                ui.label("Not used");
+                            }
+                            for usage in usages {
+                                ui.label(usage);
+                            }
+                        })
+                        .response
+                        .on_hover_text("Find usages");
+                    });
+                    project.definitions[index]
+                        .expr
+                        .draw_ui(app, ui, index as u64, FieldRange::ANY);
+                    ui.separator();
+                });
+            }
+            if let Some((from, to)) = rename
+                && is_identifier(&to)
+                && find_definition(&project.definitions, &to).is_none()
+            {
+                project.rename_definition(&from, &to);
+            }
+            if let Some(command) = command {
+                command.apply(&mut project.definitions);
+            }
+            if ui.button("➕ Definition").clicked() {
+                let name = unique_definition_name(&project.definitions, "definition");
+                project
+                    .definitions
+                    .push(ExprDefinition::new(&name, ExprWriterEditor::Placeholder));
+            }
+        });
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+
+/// A syntax error in a RON document, located by line and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentError {
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based character column.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+/// A node of an untyped RON document.
+///
+/// Unlike `ron::Value`, it keeps the names of structs and enum variants, so a
+/// document edited by migrations can still be deserialized into the typed
+/// project.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// A number, string or char, as written in the source.
+    Atom(String),
+    /// A bare identifier: a unit variant, `None`, `true` or `false`.
+    Ident(String),
+    /// `Name(a, b)` or `(a, b)`.
+    Tuple(Option<String>, Vec<Node>),
+    /// `Name(a: x, b: y)` or `(a: x, b: y)`.
+    Struct(Option<String>, Vec<(String, Node)>),
+    /// `[a, b]`.
+    List(Vec<Node>),
+    /// `{k: v}`.
+    Map(Vec<(Node, Node)>),
+}
+
+impl Node {
+    /// A string atom.
+    pub fn string(s: &str) -> Node {
+        Node::Atom(format!("{:?}", s))
+    }
+
+    /// Name of a struct, tuple or unit variant.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Node::Ident(name) => Some(name),
+            Node::Tuple(name, _) | Node::Struct(name, _) => name.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Node> {
+        match self {
+            Node::Struct(_, fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Node> {
+        match self {
+            Node::Struct(_, fields) => fields.iter_mut().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Sets a field of a struct, appending it if it is missing. An empty
+    /// tuple `()` becomes a struct.
+    pub fn set_field(&mut self, name: &str, value: Node) {
+        if let Node::Tuple(struct_name, items) = self
+            && items.is_empty()
+        {
+            *self = Node::Struct(struct_name.take(), Vec::new());
+        }
+        if let Node::Struct(_, fields) = self {
+            match fields.iter_mut().find(|(n, _)| n == name) {
+                Some((_, v)) => *v = value,
+                None => fields.push((name.to_string(), value)),
+            }
+        }
+    }
+
+    /// Sets a field only if the struct does not have it yet.
+    pub fn set_default_field(&mut self, name: &str, value: Node) {
+        if self.field(name).is_none() {
+            self.set_field(name, value);
+        }
+    }
+
+    pub fn remove_field(&mut self, name: &str) -> Option<Node> {
+        match self {
+            Node::Struct(_, fields) => {
+                let index = fields.iter().position(|(n, _)| n == name)?;
+                Some(fields.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Items of a list or tuple, empty for other nodes.
+    pub fn items_mut(&mut self) -> &mut [Node] {
+        match self {
+            Node::List(items) | Node::Tuple(_, items) => items,
+            _ => &mut [],
+        }
+    }
+
+    /// Calls `f` on this node, then on all nodes below it.
+    pub fn for_each_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        f(self);
+        match self {
+            Node::Tuple(_, items) | Node::List(items) => {
+                for item in items.iter_mut() {
+                    item.for_each_mut(f);
+                }
+            }
+            Node::Struct(_, fields) => {
+                for (_, value) in fields.iter_mut() {
+                    value.for_each_mut(f);
+                }
+            }
+            Node::Map(entries) => {
+                for (key, value) in entries.iter_mut() {
+                    key.for_each_mut(f);
+                    value.for_each_mut(f);
+                }
+            }
+            Node::Atom(_) | Node::Ident(_) => {}
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Node::Atom(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value of a string atom without escapes, the only ones Omagari
+    /// writes in names.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Node::Atom(text) => text.strip_prefix('"')?.strip_suffix('"'),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T>(
+            f: &mut fmt::Formatter<'_>,
+            items: &[T],
+            write: impl Fn(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+        ) -> fmt::Result {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write(f, item)?;
+            }
+            Ok(())
+        }
+        match self {
+            Node::Atom(text) | Node::Ident(text) => write!(f, "{}", text),
+            Node::Tuple(name, items) => {
+                write!(f, "{}(", name.as_deref().unwrap_or(""))?;
+                list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, ")")
+            }
+            Node::Struct(name, fields) => {
+                write!(f, "{}(", name.as_deref().unwrap_or(""))?;
+                list(f, fields, |f, (name, value)| {
+                    write!(f, "{}: {}", name, value)
+                })?;
+                write!(f, ")")
+            }
+            Node::List(items) => {
+                write!(f, "[")?;
+                list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, "]")
+            }
+            Node::Map(entries) => {
+                write!(f, "{{")?;
+                list(f, entries, |f, (key, value)| {
+                    write!(f, "{}: {}", key, value)
+                })?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// A RON document: its `#![enable(..)]` attributes and its value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    attributes: Vec<String>,
+    pub root: Node,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Self, DocumentError> {
+        let mut parser = Parser { text, offset: 0 };
+        let mut attributes = Vec::new();
+        parser.skip_blank()?;
+        while parser.rest().starts_with("#!") {
+            let Some(end) = parser.rest().find(']') else {
+                return parser.error("unterminated attribute");
+            };
+            attributes.push(parser.rest()[..=end].to_string());
+            parser.offset += end + 1;
+            parser.skip_blank()?;
+        }
+        let root = parser.value()?;
+        parser.skip_blank()?;
+        if !parser.rest().is_empty() {
+            return parser.error("unexpected text after the document");
+        }
+        Ok(Document { attributes, root })
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attribute in self.attributes.iter() {
+            writeln!(f, "{}", attribute)?;
+        }
+        write!(f, "{}", self.root)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.offset..]
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, DocumentError> {
+        let before = &self.text[..self.offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Err(DocumentError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into(),
+        })
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_blank(&mut self) -> Result<(), DocumentError> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.offset += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.offset += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                let Some(end) = trimmed.find("*/") else {
+                    return self.error("unterminated comment");
+                };
+                self.offset += end + 2;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> Result<bool, DocumentError> {
+        self.skip_blank()?;
+        if self.rest().starts_with(c) {
+            self.offset += c.len_utf8();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DocumentError> {
+        if self.eat(c)? {
+            Ok(())
+        } else {
+            self.error(format!("expected '{}'", c))
+        }
+    }
+
+    /// Takes the longest prefix of characters matching `f`.
+    fn take_while(&mut self, mut f: impl FnMut(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.offset += len;
+        &rest[..len]
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        Some(
+            self.take_while(|c| c.is_alphanumeric() || c == '_')
+                .to_string(),
+        )
+    }
+
+    fn value(&mut self) -> Result<Node, DocumentError> {
+        self.skip_blank()?;
+        let rest = self.rest();
+        match rest.chars().next() {
+            None => self.error("unexpected end of the document"),
+            Some('[') => {
+                self.offset += 1;
+                let mut items = Vec::new();
+                while !self.eat(']')? {
+                    items.push(self.value()?);
+                    if !self.eat(',')? {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Ok(Node::List(items))
+            }
+            Some('{') => {
+                self.offset += 1;
+                let mut entries = Vec::new();
+                while !self.eat('}')? {
+                    let key = self.value()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    if !self.eat(',')? {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                Ok(Node::Map(entries))
+            }
+            Some('(') => self.parenthesized(None),
+            Some('"') => self.quoted('"'),
+            Some('\'') => self.quoted('\''),
+            Some('r') if rest[1..].starts_with(['"', '#']) => self.raw_string(),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let start = self.offset;
+                self.offset += 1;
+                let mut previous = c;
+                self.take_while(|c| {
+                    let part = c.is_alphanumeric()
+                        || c == '.'
+                        || c == '_'
+                        || ((c == '-' || c == '+') && matches!(previous, 'e' | 'E'));
+                    previous = c;
+                    part
+                });
+                Ok(Node::Atom(self.text[start..self.offset].to_string()))
+            }
+            Some(_) => {
+                let Some(name) = self.ident() else {
+                    return self.error("expected a value");
+                };
+                if self.rest().trim_start().starts_with('(') {
+                    self.skip_blank()?;
+                    self.parenthesized(Some(name))
+                } else {
+                    Ok(Node::Ident(name))
+                }
+            }
+        }
+    }
+
+    /// A tuple or a struct, whose opening parenthesis is next.
+    fn parenthesized(&mut self, name: Option<String>) -> Result<Node, DocumentError> {
+        self.expect('(')?;
+        self.skip_blank()?;
+        let start = self.offset;
+        let is_struct = self.ident().is_some() && self.eat(':')?;
+        self.offset = start;
+        if is_struct {
+            let mut fields = Vec::new();
+            while !self.eat(')')? {
+                self.skip_blank()?;
+                let Some(field) = self.ident() else {
+                    return self.error("expected a field name");
+                };
+                self.expect(':')?;
+                fields.push((field, self.value()?));
+                if !self.eat(',')? {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            Ok(Node::Struct(name, fields))
+        } else {
+            let mut items = Vec::new();
+            while !self.eat(')')? {
+                items.push(self.value()?);
+                if !self.eat(',')? {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            Ok(Node::Tuple(name, items))
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<Node, DocumentError> {
+        let start = self.offset;
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in self.rest().char_indices().skip(1) {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                c if c == quote => {
+                    end = Some(i + c.len_utf8());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            return self.error("unterminated string");
+        };
+        self.offset += end;
+        Ok(Node::Atom(self.text[start..self.offset].to_string()))
+    }
+
+    fn raw_string(&mut self) -> Result<Node, DocumentError> {
+        let start = self.offset;
+        self.offset += 1;
+        let hashes = self.take_while(|c| c == '#').len();
+        if !self.rest().starts_with('"') {
+            return self.error("expected '\"'");
+        }
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let Some(end) = self.rest()[1..].find(&terminator) else {
+            return self.error("unterminated string");
+        };
+        self.offset += 1 + end + terminator.len();
+        Ok(Node::Atom(self.text[start..self.offset].to_string()))
+    }
+}
@@ -5,34 +5,44 @@ use bevy_hanabi::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::definitions::ExprDefinition;
 use crate::editor_prelude::AppContext;
+use crate::expr::{EffectWriter, ExprWriterEditor};
 use crate::helpers::*;
+use crate::ids::{Identified, NodeId};
 use crate::modifiers::ModifierProducer;
 use crate::modifiers::RenderModifierProducer;
 use crate::modifiers::*;
+use crate::optimize::NodeCounts;
+use crate::properties::*;
 
 fn ui_for_modifiers_list<T, R>(
     app: &mut AppContext,
     ui: &mut egui::Ui,
-    mut modifiers: &mut Vec<T>,
+    modifiers: &mut Vec<T>,
     label: &str,
     id: egui::Id,
     add_contents: impl FnOnce(&mut egui::Ui, &mut Vec<T>) -> R,
 ) where
-    T: UiProvider,
+    T: UiProvider + Identified,
 {
     let id = id.with(label);
-    unique_collapsing(id.value(), label, ui).show(ui, |ui| {
+    unique_collapsing(id.value(), label, label, ui).show(ui, |ui| {
         let n_modifiers = modifiers.len();
         for (index, n) in modifiers.iter_mut().enumerate() {
+            // Keyed on the modifier, so that its UI state follows it when the
+            // list is reordered.
             let swap = ui
-                .horizontal(|ui| {
-                    if let Some(list_command) = ui_for_list_item(ui, index, n_modifiers) {
-                        return Some(list_command);
-                    } else {
-                        n.draw_ui(app, ui, index as u64);
-                    }
-                    None
+                .push_id(n.id(), |ui| {
+                    ui.horizontal(|ui| {
+                        if let Some(list_command) = ui_for_list_item(ui, index, n_modifiers) {
+                            return Some(list_command);
+                        } else {
+                            n.draw_ui(app, ui, index as u64);
+                        }
+                        None
+                    })
+                    .inner
                 })
                 .inner;
             if let Some(swap) = swap {
@@ -48,7 +58,7 @@ fn ui_for_modifiers_list<T, R>(
             }
         }
 
-        ui.menu_button("+", |ui| add_contents(ui, &mut modifiers));
+        ui.menu_button("+", |ui| add_contents(ui, modifiers));
     });
 }
 
@@ -68,7 +78,7 @@ pub enum ModifierEditor {
 }
 
 impl ModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> ProducedModifier {
+    fn produce(&self, writer: &EffectWriter) -> ProducedModifier {
         match self {
             ModifierEditor::SetPositionCircle(n) => {
                 ProducedModifier::SetPositionCircle(n.produce(writer))
@@ -103,6 +113,92 @@ impl ModifierEditor {
     }
 }
 
+impl ModifierEditor {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ModifierEditor::SetPositionCircle(_) => SetPositionCircleModifierEditor::label(),
+            ModifierEditor::SetPositionSphere(_) => SetPositionSphereModifierEditor::label(),
+            ModifierEditor::SetVelocityCircle(_) => SetVelocityCircleModifierEditor::label(),
+            ModifierEditor::SetVelocitySphere(_) => SetVelocitySphereModifierEditor::label(),
+            ModifierEditor::SetVelocityTangent(_) => SetVelocityTangentModifierEditor::label(),
+            ModifierEditor::SetAttribute(_) => SetAttributeModifierEditor::label(),
+            ModifierEditor::InheritAttribute(_) => InheritAttributeModifierEditor::label(),
+            ModifierEditor::AccelModifier(_) => AccelModifierEditor::label(),
+            ModifierEditor::LinearDragModifier(_) => LinearDragModifierEditor::label(),
+            ModifierEditor::EmitSpawnEventModifier(_) => EmitSpawnEventModifierEditor::label(),
+            ModifierEditor::ConformToSphereModifier(_) => ConformToSphereModifierEditor::label(),
+        }
+    }
+}
+
+impl ExprSlots for ModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        match self {
+            ModifierEditor::SetPositionCircle(n) => n.exprs(),
+            ModifierEditor::SetPositionSphere(n) => n.exprs(),
+            ModifierEditor::SetVelocityCircle(n) => n.exprs(),
+            ModifierEditor::SetVelocitySphere(n) => n.exprs(),
+            ModifierEditor::SetVelocityTangent(n) => n.exprs(),
+            ModifierEditor::SetAttribute(n) => n.exprs(),
+            ModifierEditor::InheritAttribute(n) => n.exprs(),
+            ModifierEditor::AccelModifier(n) => n.exprs(),
+            ModifierEditor::LinearDragModifier(n) => n.exprs(),
+            ModifierEditor::EmitSpawnEventModifier(n) => n.exprs(),
+            ModifierEditor::ConformToSphereModifier(n) => n.exprs(),
+        }
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        match self {
+            ModifierEditor::SetPositionCircle(n) => n.exprs_mut(),
+            ModifierEditor::SetPositionSphere(n) => n.exprs_mut(),
+            ModifierEditor::SetVelocityCircle(n) => n.exprs_mut(),
+            ModifierEditor::SetVelocitySphere(n) => n.exprs_mut(),
+            ModifierEditor::SetVelocityTangent(n) => n.exprs_mut(),
+            ModifierEditor::SetAttribute(n) => n.exprs_mut(),
+            ModifierEditor::InheritAttribute(n) => n.exprs_mut(),
+            ModifierEditor::AccelModifier(n) => n.exprs_mut(),
+            ModifierEditor::LinearDragModifier(n) => n.exprs_mut(),
+            ModifierEditor::EmitSpawnEventModifier(n) => n.exprs_mut(),
+            ModifierEditor::ConformToSphereModifier(n) => n.exprs_mut(),
+        }
+    }
+}
+
+impl Identified for ModifierEditor {
+    fn id(&self) -> NodeId {
+        match self {
+            ModifierEditor::SetPositionCircle(n) => n.id,
+            ModifierEditor::SetPositionSphere(n) => n.id,
+            ModifierEditor::SetVelocityCircle(n) => n.id,
+            ModifierEditor::SetVelocitySphere(n) => n.id,
+            ModifierEditor::SetVelocityTangent(n) => n.id,
+            ModifierEditor::SetAttribute(n) => n.id,
+            ModifierEditor::InheritAttribute(n) => n.id,
+            ModifierEditor::AccelModifier(n) => n.id,
+            ModifierEditor::LinearDragModifier(n) => n.id,
+            ModifierEditor::EmitSpawnEventModifier(n) => n.id,
+            ModifierEditor::ConformToSphereModifier(n) => n.id,
+        }
+    }
+
+    fn id_mut(&mut self) -> &mut NodeId {
+        match self {
+            ModifierEditor::SetPositionCircle(n) => &mut n.id,
+            ModifierEditor::SetPositionSphere(n) => &mut n.id,
+            ModifierEditor::SetVelocityCircle(n) => &mut n.id,
+            ModifierEditor::SetVelocitySphere(n) => &mut n.id,
+            ModifierEditor::SetVelocityTangent(n) => &mut n.id,
+            ModifierEditor::SetAttribute(n) => &mut n.id,
+            ModifierEditor::InheritAttribute(n) => &mut n.id,
+            ModifierEditor::AccelModifier(n) => &mut n.id,
+            ModifierEditor::LinearDragModifier(n) => &mut n.id,
+            ModifierEditor::EmitSpawnEventModifier(n) => &mut n.id,
+            ModifierEditor::ConformToSphereModifier(n) => &mut n.id,
+        }
+    }
+}
+
 impl UiProvider for ModifierEditor {
     fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
         match self {
@@ -136,6 +232,22 @@ impl UiProvider for RenderModifierEditor {
     }
 }
 
+impl Identified for RenderModifierEditor {
+    fn id(&self) -> NodeId {
+        match self {
+            RenderModifierEditor::SizeOverLifetime(n) => n.id,
+            RenderModifierEditor::ColorOverLifetime(n) => n.id,
+        }
+    }
+
+    fn id_mut(&mut self) -> &mut NodeId {
+        match self {
+            RenderModifierEditor::SizeOverLifetime(n) => &mut n.id,
+            RenderModifierEditor::ColorOverLifetime(n) => &mut n.id,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 enum ProducedModifier {
     SetVelocityTangent(SetVelocityTangentModifier),
@@ -153,19 +265,38 @@ enum ProducedModifier {
 
 #[derive(Serialize, Deserialize)]
 pub struct EffectEditor {
+    #[serde(default)]
+    id: NodeId,
     name: String,
     parent: Option<String>,
     capacity: u32,
     spawner_settings: SpawnerSettings,
-    texture_index: Option<usize>,
+    #[serde(default)]
+    texture: Option<String>,
     init_modifiers: Vec<ModifierEditor>,
     update_modifiers: Vec<ModifierEditor>,
     render_modifiers: Vec<RenderModifierEditor>,
+    #[serde(default)]
+    properties: Vec<EffectProperty>,
+    /// Seed of the random values drawn on the GPU. The same seed gives the
+    /// same particles on every run.
+    #[serde(default)]
+    seed: u32,
+}
+
+impl Identified for EffectEditor {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn id_mut(&mut self) -> &mut NodeId {
+        &mut self.id
+    }
 }
 
 impl UiProvider for EffectEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        let id = ui.make_persistent_id(format!("effect {}{}", self.name, index));
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        let id = ui.make_persistent_id(self.id);
         ui.vertical(|ui| {
             egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
                 .show_header(ui, |ui| {
@@ -179,58 +310,79 @@ impl UiProvider for EffectEditor {
                     ui.horizontal(|ui| {
                         ui.label("Capacity:");
                         self.capacity = ui_for_u32_ex(ui, self.capacity, 0, 16384, 1);
+                        ui.label("Seed:");
+                        self.seed = ui_for_u32_ex(ui, self.seed, 0, u32::MAX, 1);
                         ui.label("Texture:");
-                        let mut curr = self.texture_index.unwrap_or(0);
-                        let options = PARTICLE_TEXTURES
-                            .iter()
-                            .map(|v| v.ui_label)
-                            .collect::<Vec<&str>>();
-                        egui::ComboBox::from_id_salt(99)
-                            .selected_text(options[curr])
+                        let selected = self.texture.clone().unwrap_or("NONE".to_string());
+                        egui::ComboBox::from_id_salt(id.with("texture"))
+                            .selected_text(selected)
                             .show_ui(ui, |ui| {
-                                for (i, o) in options.iter().enumerate() {
-                                    ui.selectable_value(&mut curr, i, *o);
+                                ui.selectable_value(&mut self.texture, None, "NONE");
+                                for name in app.texture_names.iter() {
+                                    ui.selectable_value(
+                                        &mut self.texture,
+                                        Some(name.clone()),
+                                        name,
+                                    );
                                 }
                             });
-
-                        self.texture_index = Some(curr);
                     });
                     ui.horizontal(|ui| {
                         ui.label("Parent Effect:");
                         let parent = self.parent.as_ref().unwrap_or(&"NONE".to_string()).clone();
                         ui.menu_button(parent, |ui| {
                             for effect in app.visible_effects.iter() {
-                                if *effect != self.name {
-                                    if ui.button(effect).clicked() {
-                                        self.parent = Some(effect.clone());
-                                        ui.close_menu();
-                                    }
+                                if *effect != self.name && ui.button(effect).clicked() {
+                                    self.parent = Some(effect.clone());
+                                    ui.close_menu();
                                 }
                             }
                         })
                     });
 
-                    unique_collapsing(1, "Spawner", ui).show(ui, |ui| {
+                    app.properties = self.properties.clone();
+                    app.eval_inputs.properties = self
+                        .properties
+                        .iter()
+                        .map(|p| {
+                            let value = app.preview_properties.value(&self.name, p);
+                            (p.name.clone(), value.into())
+                        })
+                        .collect();
+                    let rename = ui_for_properties(
+                        ui,
+                        self.id,
+                        &self.name,
+                        &mut self.properties,
+                        &mut app.preview_properties,
+                    );
+                    if let Some((from, to)) = rename
+                        && is_valid_property_name(&to)
+                        && !self.properties.iter().any(|p| p.name == to)
+                    {
+                        self.rename_property(&from, &to);
+                        app.preview_properties.rename(&self.name, &from, &to);
+                    }
+
+                    unique_collapsing(self.id.value(), "Spawner", "Spawner", ui).show(ui, |ui| {
                         ui.horizontal(|ui| {
                             let rate: [f32; 2] = self.spawner_settings.count().range();
                             ui.label("Count:");
                             let rate = ui_for_f32_ex(ui, rate[0], 0.0, 10000.0, 1.0);
-                            self.spawner_settings
-                                .set_count(CpuValue::Single(rate.into()));
+                            self.spawner_settings.set_count(CpuValue::Single(rate));
                         });
                         ui.horizontal(|ui| {
                             let duration: [f32; 2] = self.spawner_settings.spawn_duration().range();
                             ui.label("Duration:");
                             let duration = ui_for_f32_ex(ui, duration[0], 0.0, 10000.0, 1.0);
                             self.spawner_settings
-                                .set_spawn_duration(CpuValue::Single(duration.into()));
+                                .set_spawn_duration(CpuValue::Single(duration));
                         });
                         ui.horizontal(|ui| {
                             let period: [f32; 2] = self.spawner_settings.period().range();
                             ui.label("Period:");
                             let period = ui_for_f32_ex(ui, period[0], 0.0, 10000.0, 1.0);
-                            self.spawner_settings
-                                .set_period(CpuValue::Single(period.into()));
+                            self.spawner_settings.set_period(CpuValue::Single(period));
                         });
 
                         ui.horizontal(|ui| {
@@ -351,6 +503,14 @@ impl UiProvider for EffectEditor {
                             }
                         },
                     );
+
+                    unique_collapsing(self.id.value(), "Debug", "Debug", ui).show(ui, |ui| {
+                        let counts = self.node_counts(&app.eval_inputs.definitions);
+                        ui.label(format!(
+                            "Expression nodes: {} written, {} after folding constants and sharing subtrees",
+                            counts.before, counts.after
+                        ));
+                    });
                 });
         });
     }
@@ -363,11 +523,91 @@ impl EffectEditor {
     pub fn parent(&self) -> Option<String> {
         self.parent.clone()
     }
-    pub fn texture_index(&self) -> Option<usize> {
-        self.texture_index
+    pub fn parent_name(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+    pub fn init_modifiers(&self) -> &[ModifierEditor] {
+        &self.init_modifiers
+    }
+    pub fn update_modifiers(&self) -> &[ModifierEditor] {
+        &self.update_modifiers
+    }
+    pub fn properties(&self) -> &[EffectProperty] {
+        &self.properties
+    }
+    pub fn texture(&self) -> Option<&str> {
+        self.texture.as_deref()
+    }
+    pub fn rename_texture(&mut self, from: &str, to: &str) {
+        if self.texture.as_deref() == Some(from) {
+            self.texture = Some(to.to_string());
+        }
+    }
+    /// Calls `f` with the id of the effect and of every modifier and
+    /// expression node of it.
+    pub fn for_each_id_mut(&mut self, f: &mut impl FnMut(&mut NodeId)) {
+        f(&mut self.id);
+        let modifiers = self
+            .init_modifiers
+            .iter_mut()
+            .chain(self.update_modifiers.iter_mut());
+        for modifier in modifiers {
+            f(modifier.id_mut());
+            for expr in modifier.exprs_mut() {
+                expr.for_each_id_mut(f);
+            }
+        }
+        for modifier in self.render_modifiers.iter_mut() {
+            f(modifier.id_mut());
+        }
+    }
+
+    /// Renames a property and every `Property` leaf of the effect reading it.
+    pub fn rename_property(&mut self, from: &str, to: &str) {
+        for property in self.properties.iter_mut().filter(|p| p.name == from) {
+            property.name = to.to_string();
+        }
+        self.for_each_expr_mut(&mut |expr| {
+            for_each_property_mut(expr, &mut |name| {
+                if name == from {
+                    *name = to.to_string();
+                }
+            })
+        });
+    }
+
+    /// Calls `f` with every expression of the init and update modifiers.
+    pub fn for_each_expr_mut(&mut self, f: &mut impl FnMut(&mut ExprWriterEditor)) {
+        let modifiers = self
+            .init_modifiers
+            .iter_mut()
+            .chain(self.update_modifiers.iter_mut());
+        for modifier in modifiers {
+            for expr in modifier.exprs_mut() {
+                f(expr);
+            }
+        }
     }
-    pub fn produce(&self) -> EffectAsset {
-        let writer = ExprWriter::new();
+
+    /// Expression node counts of the init and update modifiers, before and
+    /// after [`EffectEditor::produce`] optimizes them.
+    pub fn node_counts(&self, definitions: &[ExprDefinition]) -> NodeCounts {
+        let modifiers = self
+            .init_modifiers
+            .iter()
+            .chain(self.update_modifiers.iter());
+        let exprs: Vec<&ExprWriterEditor> = modifiers
+            .flat_map(|m| m.exprs().into_iter().map(|(_, expr, _)| expr))
+            .collect();
+        NodeCounts::of(exprs, definitions)
+    }
+
+    /// Builds the effect, inlining the project `definitions` it refers to.
+    pub fn produce(&self, definitions: &[ExprDefinition]) -> EffectAsset {
+        let mut writer = EffectWriter::with_definitions(definitions).with_optimization(true);
+        for property in self.properties.iter() {
+            writer.add_property(property);
+        }
 
         let mut init_modifiers: Vec<ProducedModifier> = Vec::new();
         let mut update_modifiers: Vec<ProducedModifier> = Vec::new();
@@ -386,6 +626,7 @@ impl EffectEditor {
         let mut e = EffectAsset::new(self.capacity, self.spawner_settings, module)
             .with_alpha_mode(bevy_hanabi::AlphaMode::Blend)
             .with_name(&self.name);
+        e.prng_seed = self.seed;
 
         for modifier_wrapper in init_modifiers {
             match modifier_wrapper {
@@ -450,50 +691,50 @@ impl EffectEditor {
 impl Default for EffectEditor {
     fn default() -> Self {
         EffectEditor {
+            id: NodeId::new(),
             name: "Name your effect".to_string(),
             parent: None,
             capacity: 16384,
             spawner_settings: SpawnerSettings::rate(500.0.into()),
-            texture_index: Some(0),
+            texture: Some("Cloud1".to_string()),
             init_modifiers: Vec::new(),
             update_modifiers: Vec::new(),
             render_modifiers: Vec::new(),
+            properties: Vec::new(),
+            seed: 0,
         }
     }
 }
 
+/// A named entry of the project texture library.
+///
+/// Effects refer to textures by `name`, so the library can be reordered
+/// without affecting saved projects. `path` is a Bevy asset path.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ParticleTexture {
-    pub filename: &'static str,
-    pub ui_label: &'static str,
+    pub name: String,
+    pub path: String,
 }
 
-pub const PARTICLE_TEXTURES: [ParticleTexture; 7] = [
-    ParticleTexture {
-        filename: "cloud.png",
-        ui_label: "Cloud1",
-    },
-    ParticleTexture {
-        filename: "cloud2.png",
-        ui_label: "Cloud2",
-    },
-    ParticleTexture {
-        filename: "spark1.png",
-        ui_label: "Spark1",
-    },
-    ParticleTexture {
-        filename: "spark2.png",
-        ui_label: "Spark2",
-    },
-    ParticleTexture {
-        filename: "spark3.png",
-        ui_label: "Spark3",
-    },
-    ParticleTexture {
-        filename: "glow1.png",
-        ui_label: "Glow1",
-    },
-    ParticleTexture {
-        filename: "splat1.png",
-        ui_label: "Splat1",
-    },
-];
+impl ParticleTexture {
+    pub fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+}
+
+/// The textures bundled with Omagari, used for new projects and for projects
+/// saved before the texture library existed.
+pub fn default_textures() -> Vec<ParticleTexture> {
+    vec![
+        ParticleTexture::new("Cloud1", "cloud.png"),
+        ParticleTexture::new("Cloud2", "cloud2.png"),
+        ParticleTexture::new("Spark1", "spark1.png"),
+        ParticleTexture::new("Spark2", "spark2.png"),
+        ParticleTexture::new("Spark3", "spark3.png"),
+        ParticleTexture::new("Glow1", "glow1.png"),
+        ParticleTexture::new("Splat1", "splat1.png"),
+    ]
+}
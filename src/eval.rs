@@ -0,0 +1,794 @@
+use bevy::prelude::*;
+use bevy_egui::*;
+use bevy_hanabi::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::definitions::{ExprDefinition, find_definition};
+use crate::expr::{ExprOperatorEditor, ExprWriterEditor, integer_exponent};
+use crate::helpers::apply_intensity;
+use crate::properties::EffectPropertyValue;
+
+/// A scalar or vector computed on the CPU. Scalars have one component.
+#[derive(Clone, PartialEq, Debug)]
+pub enum EvalValue {
+    Float(Vec<f32>),
+    Uint(Vec<u32>),
+    Int(Vec<i32>),
+    Bool(Vec<bool>),
+}
+
+impl EvalValue {
+    /// Number of components, 1 for scalars.
+    pub fn count(&self) -> usize {
+        match self {
+            EvalValue::Float(v) => v.len(),
+            EvalValue::Uint(v) => v.len(),
+            EvalValue::Int(v) => v.len(),
+            EvalValue::Bool(v) => v.len(),
+        }
+    }
+
+    pub fn elem_type(&self) -> ScalarType {
+        match self {
+            EvalValue::Float(_) => ScalarType::Float,
+            EvalValue::Uint(_) => ScalarType::Uint,
+            EvalValue::Int(_) => ScalarType::Int,
+            EvalValue::Bool(_) => ScalarType::Bool,
+        }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match self.count() {
+            1 => ValueType::Scalar(self.elem_type()),
+            n => ValueType::Vector(VectorType::new(self.elem_type(), n as u8)),
+        }
+    }
+
+    /// Every component converted to `f32`, booleans as 0 or 1.
+    pub fn to_f32s(&self) -> Vec<f32> {
+        match self.cast(ScalarType::Float) {
+            EvalValue::Float(v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The value of a float scalar.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            EvalValue::Float(v) if v.len() == 1 => Some(v[0]),
+            _ => None,
+        }
+    }
+
+    /// Converts every component, like the WGSL conversion functions.
+    pub fn cast(&self, elem: ScalarType) -> EvalValue {
+        let floats: Vec<f64> = match self {
+            EvalValue::Float(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Uint(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Int(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Bool(v) => v.iter().map(|x| *x as u8 as f64).collect(),
+        };
+        match (self, elem) {
+            // Integer conversions reinterpret the bits instead of clamping.
+            (EvalValue::Int(v), ScalarType::Uint) => {
+                EvalValue::Uint(v.iter().map(|x| *x as u32).collect())
+            }
+            (EvalValue::Uint(v), ScalarType::Int) => {
+                EvalValue::Int(v.iter().map(|x| *x as i32).collect())
+            }
+            (_, ScalarType::Float) => EvalValue::Float(floats.iter().map(|x| *x as f32).collect()),
+            (_, ScalarType::Uint) => EvalValue::Uint(floats.iter().map(|x| *x as u32).collect()),
+            (_, ScalarType::Int) => EvalValue::Int(floats.iter().map(|x| *x as i32).collect()),
+            _ => EvalValue::Bool(floats.iter().map(|x| *x != 0.0).collect()),
+        }
+    }
+
+    /// Components as `f64`, which represents every supported value exactly.
+    fn to_f64s(&self) -> Vec<f64> {
+        match self {
+            EvalValue::Float(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Uint(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Int(v) => v.iter().map(|x| *x as f64).collect(),
+            EvalValue::Bool(v) => v.iter().map(|x| *x as u8 as f64).collect(),
+        }
+    }
+
+    /// The components picked by a swizzle such as `zx`.
+    fn swizzle(&self, components: &str) -> Result<EvalValue, String> {
+        let mut indices = Vec::new();
+        for c in components.chars() {
+            match "xyzw".find(c) {
+                Some(index) if index < self.count() => indices.push(index),
+                _ => return Err(format!("{} has no {} component", self.value_type(), c)),
+            }
+        }
+        fn pick<T: Copy>(v: &[T], indices: &[usize]) -> Vec<T> {
+            indices.iter().map(|i| v[*i]).collect()
+        }
+        Ok(match self {
+            EvalValue::Float(v) => EvalValue::Float(pick(v, &indices)),
+            EvalValue::Uint(v) => EvalValue::Uint(pick(v, &indices)),
+            EvalValue::Int(v) => EvalValue::Int(pick(v, &indices)),
+            EvalValue::Bool(v) => EvalValue::Bool(pick(v, &indices)),
+        })
+    }
+}
+
+impl std::fmt::Display for EvalValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components: Vec<String> = match self {
+            EvalValue::Float(v) => v.iter().map(|x| format!("{:.3}", x)).collect(),
+            EvalValue::Uint(v) => v.iter().map(|x| format!("{}u", x)).collect(),
+            EvalValue::Int(v) => v.iter().map(|x| format!("{}i", x)).collect(),
+            EvalValue::Bool(v) => v.iter().map(|x| x.to_string()).collect(),
+        };
+        if components.len() == 1 {
+            write!(f, "{}", components[0])
+        } else {
+            write!(f, "({})", components.join(", "))
+        }
+    }
+}
+
+impl From<Value> for EvalValue {
+    fn from(value: Value) -> Self {
+        let scalars: Vec<ScalarValue> = match &value {
+            Value::Scalar(s) => vec![*s],
+            Value::Vector(v) => (0..v.vector_type().count()).map(|i| v.value(i)).collect(),
+            // No expression produces matrices.
+            _ => vec![ScalarValue::Float(0.0)],
+        };
+        match scalars[0].scalar_type() {
+            ScalarType::Float => EvalValue::Float(scalars.iter().map(|s| s.as_f32()).collect()),
+            ScalarType::Uint => EvalValue::Uint(scalars.iter().map(|s| s.as_u32()).collect()),
+            ScalarType::Int => EvalValue::Int(scalars.iter().map(|s| s.as_i32()).collect()),
+            _ => EvalValue::Bool(scalars.iter().map(|s| s.as_bool()).collect()),
+        }
+    }
+}
+
+impl From<EffectPropertyValue> for EvalValue {
+    fn from(value: EffectPropertyValue) -> Self {
+        Value::from(value).into()
+    }
+}
+
+/// Everything an expression may read besides random numbers: simulation
+/// times, attributes of the particle and of its parent, effect properties
+/// and project definitions.
+#[derive(Clone)]
+pub struct EvalInputs {
+    pub time: f32,
+    pub delta_time: f32,
+    pub real_time: f32,
+    pub real_delta_time: f32,
+    /// Attributes of the particle. Missing ones have their default value.
+    pub attributes: Vec<(Attribute, EvalValue)>,
+    pub parent_attributes: Vec<(Attribute, EvalValue)>,
+    pub properties: Vec<(String, EvalValue)>,
+    pub definitions: Vec<ExprDefinition>,
+}
+
+impl Default for EvalInputs {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            delta_time: 1.0 / 60.0,
+            real_time: 0.0,
+            real_delta_time: 1.0 / 60.0,
+            attributes: Vec::new(),
+            parent_attributes: Vec::new(),
+            properties: Vec::new(),
+            definitions: Vec::new(),
+        }
+    }
+}
+
+impl EvalInputs {
+    pub fn with_attribute(mut self, attr: Attribute, value: EvalValue) -> Self {
+        self.set_attribute(attr, value);
+        self
+    }
+
+    pub fn set_attribute(&mut self, attr: Attribute, value: EvalValue) {
+        match self.attributes.iter_mut().find(|(a, _)| *a == attr) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((attr, value)),
+        }
+    }
+
+    /// The same inputs at `t` seconds into the simulation, for a particle
+    /// born at its start.
+    pub fn at_time(&self, t: f32) -> Self {
+        let mut inputs = self.clone();
+        inputs.time = t;
+        inputs.real_time = t;
+        inputs.set_attribute(Attribute::AGE, EvalValue::Float(vec![t]));
+        inputs
+    }
+}
+
+fn attribute_value(attributes: &[(Attribute, EvalValue)], attr: Attribute) -> EvalValue {
+    attributes
+        .iter()
+        .find(|(a, _)| *a == attr)
+        .map(|(_, v)| v.clone())
+        .unwrap_or_else(|| attr.default_value().into())
+}
+
+/// Evaluates expressions on the CPU, with the same semantics as the WGSL
+/// code they produce. Random numbers come from a seeded generator, so the
+/// same seed gives the same values.
+pub struct ExprEvaluator<'a> {
+    inputs: &'a EvalInputs,
+    rng: StdRng,
+    inlining: Vec<String>,
+}
+
+impl<'a> ExprEvaluator<'a> {
+    pub fn new(inputs: &'a EvalInputs, seed: u64) -> Self {
+        Self {
+            inputs,
+            rng: StdRng::seed_from_u64(seed),
+            inlining: Vec::new(),
+        }
+    }
+
+    pub fn eval(&mut self, expr: &ExprWriterEditor) -> Result<EvalValue, String> {
+        let inputs = self.inputs;
+        Ok(match expr {
+            ExprWriterEditor::Placeholder => return Err("Expression is incomplete".to_string()),
+            ExprWriterEditor::Operator(o, _) => return self.eval_operator(o),
+            ExprWriterEditor::RandU32 => EvalValue::Uint(vec![self.rng.random()]),
+            ExprWriterEditor::RandFloat => EvalValue::Float(vec![self.rng.random()]),
+            ExprWriterEditor::RandVec3 => EvalValue::Float(self.random_floats(3)),
+            ExprWriterEditor::RandomRange(range) => EvalValue::Float(range.sample(&mut self.rng)),
+            ExprWriterEditor::Float(f) => EvalValue::Float(vec![*f]),
+            ExprWriterEditor::U32(v) => EvalValue::Uint(vec![*v]),
+            ExprWriterEditor::Bool(b) => EvalValue::Bool(vec![*b]),
+            ExprWriterEditor::Vec2(v) => EvalValue::Float(v.to_array().to_vec()),
+            ExprWriterEditor::Vec3(v) => EvalValue::Float(v.to_array().to_vec()),
+            ExprWriterEditor::Vec4(v) => EvalValue::Float(v.to_array().to_vec()),
+            ExprWriterEditor::Color(color, intensity) => {
+                EvalValue::Float(apply_intensity(*color, *intensity).to_array().to_vec())
+            }
+            ExprWriterEditor::Time => EvalValue::Float(vec![inputs.time]),
+            ExprWriterEditor::DeltaTime => EvalValue::Float(vec![inputs.delta_time]),
+            ExprWriterEditor::RealTime => EvalValue::Float(vec![inputs.real_time]),
+            ExprWriterEditor::RealDeltaTime => EvalValue::Float(vec![inputs.real_delta_time]),
+            ExprWriterEditor::Age => attribute_value(&inputs.attributes, Attribute::AGE),
+            ExprWriterEditor::ParticleId => attribute_value(&inputs.attributes, Attribute::ID),
+            ExprWriterEditor::Property(name) => inputs
+                .properties
+                .iter()
+                .find(|(p, _)| p == name)
+                .map(|(_, v)| v.clone())
+                .ok_or_else(|| format!("Property '{}' does not exist", name))?,
+            ExprWriterEditor::Ref(name) => {
+                if self.inlining.contains(name) {
+                    return Err(format!("Definition '{}' refers to itself", name));
+                }
+                let definition = find_definition(&inputs.definitions, name)
+                    .ok_or_else(|| format!("Definition '{}' does not exist", name))?;
+                self.inlining.push(name.clone());
+                let value = self.eval(&definition.expr);
+                self.inlining.pop();
+                value?
+            }
+        })
+    }
+
+    fn random_floats(&mut self, count: usize) -> Vec<f32> {
+        (0..count).map(|_| self.rng.random()).collect()
+    }
+
+    fn eval_operator(&mut self, o: &ExprOperatorEditor) -> Result<EvalValue, String> {
+        use EvalValue as V;
+        use ExprOperatorEditor as Op;
+
+        let name = o.name();
+        let mut args = Vec::new();
+        for operand in o.operands() {
+            args.push(self.eval(operand)?);
+        }
+        let arg = |i: usize| &args[i];
+        let float_arg = |i: usize| floats(name, arg(i));
+        let map = |f: fn(f32) -> f32| -> Result<EvalValue, String> {
+            Ok(V::Float(float_arg(0)?.iter().map(|x| f(*x)).collect()))
+        };
+        let cmp = |f: fn(f64, f64) -> bool| compare(name, arg(0), arg(1), f);
+        let mismatch = |a: usize, b: usize| cannot_combine(name, arg(a), arg(b));
+
+        match o {
+            Op::Uniform(..) => {
+                let t = V::Float(self.random_floats(arg(0).count()));
+                mix(name, arg(0), arg(1), &t)
+            }
+            Op::Add(..) => zip(
+                name,
+                arg(0),
+                arg(1),
+                |a, b| a + b,
+                u32::wrapping_add,
+                i32::wrapping_add,
+            ),
+            Op::Subtract(..) => zip(
+                name,
+                arg(0),
+                arg(1),
+                |a, b| a - b,
+                u32::wrapping_sub,
+                i32::wrapping_sub,
+            ),
+            Op::Multiply(..) => zip(
+                name,
+                arg(0),
+                arg(1),
+                |a, b| a * b,
+                u32::wrapping_mul,
+                i32::wrapping_mul,
+            ),
+            // Integer division by zero gives the dividend in WGSL.
+            Op::Div(..) => zip(
+                name,
+                arg(0),
+                arg(1),
+                |a, b| a / b,
+                |a, b| a.checked_div(b).unwrap_or(a),
+                |a, b| a.checked_div(b).unwrap_or(a),
+            ),
+            Op::Rem(..) => zip(
+                name,
+                arg(0),
+                arg(1),
+                |a, b| a % b,
+                |a, b| a.checked_rem(b).unwrap_or(0),
+                |a, b| a.checked_rem(b).unwrap_or(0),
+            ),
+            Op::Neg(..) => map(|x| -x),
+            Op::Min(..) => zip(name, arg(0), arg(1), f32::min, u32::min, i32::min),
+            Op::Max(..) => zip(name, arg(0), arg(1), f32::max, u32::max, i32::max),
+            Op::Clamp(..) => {
+                let low = zip(name, arg(0), arg(1), f32::max, u32::max, i32::max)?;
+                zip(name, &low, arg(2), f32::min, u32::min, i32::min)
+            }
+            Op::Mix(..) => mix(name, arg(0), arg(1), arg(2)),
+            Op::Abs(..) => Ok(match arg(0) {
+                V::Int(v) => V::Int(v.iter().map(|x| x.wrapping_abs()).collect()),
+                V::Uint(v) => V::Uint(v.clone()),
+                _ => map(f32::abs)?,
+            }),
+            Op::Sign(..) => map(|x| {
+                if x > 0.0 {
+                    1.0
+                } else if x < 0.0 {
+                    -1.0
+                } else {
+                    0.0
+                }
+            }),
+            Op::Floor(..) => map(f32::floor),
+            Op::Ceil(..) => map(f32::ceil),
+            Op::Fract(..) => map(|x| x - x.floor()),
+            Op::Step(..) => Ok(V::Float(
+                zip_with(float_arg(0)?, float_arg(1)?, |edge, x| {
+                    if x >= edge { 1.0 } else { 0.0 }
+                })
+                .ok_or_else(|| mismatch(0, 1))?,
+            )),
+            Op::Smoothstep(..) => {
+                let (low, high, x) = (float_arg(0)?, float_arg(1)?, float_arg(2)?);
+                let range = zip_with(high, low, |h, l| h - l).ok_or_else(|| mismatch(1, 0))?;
+                let offset = zip_with(x, low, |x, l| x - l).ok_or_else(|| mismatch(2, 0))?;
+                Ok(V::Float(
+                    zip_with(&offset, &range, |o, r| {
+                        let t = (o / r).clamp(0.0, 1.0);
+                        t * t * (3.0 - 2.0 * t)
+                    })
+                    .ok_or_else(|| mismatch(2, 1))?,
+                ))
+            }
+            // Same formulas as the generated code, see `produce_pow`.
+            Op::Pow(_, exponent) => match integer_exponent(exponent) {
+                Some(n) => Ok(V::Float(float_arg(0)?.iter().map(|b| b.powi(n)).collect())),
+                None => Ok(V::Float(
+                    zip_with(float_arg(0)?, float_arg(1)?, |b, e| {
+                        let odd = (e * 0.5).rem_euclid(1.0) * 2.0;
+                        let sign = if b == 0.0 { 0.0 } else { b.signum() };
+                        (e * b.abs().ln()).exp() * (1.0 + (sign - 1.0) * odd)
+                    })
+                    .ok_or_else(|| mismatch(0, 1))?,
+                )),
+            },
+            Op::Sqrt(..) => map(f32::sqrt),
+            Op::Exp(..) => map(f32::exp),
+            Op::Log(..) => map(f32::ln),
+            Op::Sin(..) => map(f32::sin),
+            Op::Cos(..) => map(f32::cos),
+            Op::Tan(..) => map(f32::tan),
+            Op::Atan2(..) => Ok(V::Float(
+                zip_with(float_arg(0)?, float_arg(1)?, f32::atan2).ok_or_else(|| mismatch(0, 1))?,
+            )),
+            Op::Lt(..) => cmp(|a, b| a < b),
+            Op::Le(..) => cmp(|a, b| a <= b),
+            Op::Gt(..) => cmp(|a, b| a > b),
+            Op::Ge(..) => cmp(|a, b| a >= b),
+            Op::Eq(..) => Ok(V::Bool(vec![all(&cmp(|a, b| a == b)?)])),
+            Op::Neq(..) => Ok(V::Bool(vec![!all(&cmp(|a, b| a == b)?)])),
+            Op::And(..) => Ok(V::Bool(vec![
+                bools(name, arg(0))?[0] && bools(name, arg(1))?[0],
+            ])),
+            Op::Or(..) => Ok(V::Bool(vec![
+                bools(name, arg(0))?[0] || bools(name, arg(1))?[0],
+            ])),
+            Op::Not(..) => Ok(V::Bool(vec![!bools(name, arg(0))?[0]])),
+            Op::All(..) => Ok(V::Bool(vec![all(arg(0))])),
+            Op::Any(..) => Ok(V::Bool(vec![bools(name, arg(0))?.iter().any(|b| *b)])),
+            Op::Select(..) => Ok(if bools(name, arg(0))?[0] {
+                arg(1).clone()
+            } else {
+                arg(2).clone()
+            }),
+            Op::Distance(..) => {
+                let difference = zip_with(float_arg(0)?, float_arg(1)?, |a, b| a - b)
+                    .ok_or_else(|| mismatch(0, 1))?;
+                Ok(V::Float(vec![length(&difference)]))
+            }
+            Op::Dot(..) => Ok(V::Float(vec![
+                zip_with(float_arg(0)?, float_arg(1)?, |a, b| a * b)
+                    .ok_or_else(|| mismatch(0, 1))?
+                    .iter()
+                    .sum(),
+            ])),
+            Op::Cross(..) => {
+                let (a, b) = (float_arg(0)?, float_arg(1)?);
+                if a.len() != 3 || b.len() != 3 {
+                    return Err(mismatch(0, 1));
+                }
+                let (a, b) = (Vec3::from_slice(a), Vec3::from_slice(b));
+                Ok(V::Float(a.cross(b).to_array().to_vec()))
+            }
+            Op::Length(..) => Ok(V::Float(vec![length(float_arg(0)?)])),
+            Op::Normalized(..) => {
+                let v = float_arg(0)?;
+                let length = length(v);
+                Ok(V::Float(v.iter().map(|x| x / length).collect()))
+            }
+            Op::Vec2(..) | Op::Vec3(..) | Op::Vec4(..) | Op::Vec4XyzW(..) => concat(name, &args),
+            Op::Swizzle(_, components) => arg(0).swizzle(components),
+            Op::AsF32(..) => Ok(arg(0).cast(ScalarType::Float)),
+            Op::AsU32(..) => Ok(arg(0).cast(ScalarType::Uint)),
+            Op::AsI32(..) => Ok(arg(0).cast(ScalarType::Int)),
+            Op::Pack4x8UNorm(..) => {
+                let packed = float_arg(0)?.iter().enumerate().fold(0, |packed, (i, x)| {
+                    packed | (((x.clamp(0.0, 1.0) * 255.0).round() as u32) << (8 * i))
+                });
+                Ok(V::Uint(vec![packed]))
+            }
+            Op::Attr(attr) => Ok(attribute_value(&self.inputs.attributes, *attr)),
+            Op::ParentAttr(attr) => Ok(attribute_value(&self.inputs.parent_attributes, *attr)),
+        }
+    }
+}
+
+impl ExprWriterEditor {
+    /// Evaluates the expression on the CPU, see [`ExprEvaluator`].
+    pub fn eval(&self, inputs: &EvalInputs, seed: u64) -> Result<EvalValue, String> {
+        ExprEvaluator::new(inputs, seed).eval(self)
+    }
+}
+
+/// Component `index` of `v`, where a scalar stands for all its components.
+fn at<T: Copy>(v: &[T], index: usize) -> T {
+    v[if v.len() == 1 { 0 } else { index }]
+}
+
+/// Applies `f` component-wise, or returns `None` when the lengths differ
+/// and neither side is a scalar.
+fn zip_with<T: Copy, R>(a: &[T], b: &[T], f: impl Fn(T, T) -> R) -> Option<Vec<R>> {
+    if a.len() != b.len() && a.len() != 1 && b.len() != 1 {
+        return None;
+    }
+    Some(
+        (0..a.len().max(b.len()))
+            .map(|i| f(at(a, i), at(b, i)))
+            .collect(),
+    )
+}
+
+fn cannot_combine(name: &str, a: &EvalValue, b: &EvalValue) -> String {
+    format!(
+        "{} cannot combine {} and {}",
+        name,
+        a.value_type(),
+        b.value_type()
+    )
+}
+
+fn floats<'v>(name: &str, value: &'v EvalValue) -> Result<&'v [f32], String> {
+    match value {
+        EvalValue::Float(v) => Ok(v),
+        _ => Err(format!(
+            "{} expects float operands, got {}",
+            name,
+            value.value_type()
+        )),
+    }
+}
+
+fn bools<'v>(name: &str, value: &'v EvalValue) -> Result<&'v [bool], String> {
+    match value {
+        EvalValue::Bool(v) => Ok(v),
+        _ => Err(format!(
+            "{} expects bool operands, got {}",
+            name,
+            value.value_type()
+        )),
+    }
+}
+
+fn all(value: &EvalValue) -> bool {
+    matches!(value, EvalValue::Bool(v) if v.iter().all(|b| *b))
+}
+
+fn length(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Component-wise arithmetic, where a scalar applies to every component.
+fn zip(
+    name: &str,
+    a: &EvalValue,
+    b: &EvalValue,
+    f: fn(f32, f32) -> f32,
+    u: fn(u32, u32) -> u32,
+    i: fn(i32, i32) -> i32,
+) -> Result<EvalValue, String> {
+    match (a, b) {
+        (EvalValue::Float(x), EvalValue::Float(y)) => zip_with(x, y, f).map(EvalValue::Float),
+        (EvalValue::Uint(x), EvalValue::Uint(y)) => zip_with(x, y, u).map(EvalValue::Uint),
+        (EvalValue::Int(x), EvalValue::Int(y)) => zip_with(x, y, i).map(EvalValue::Int),
+        _ => None,
+    }
+    .ok_or_else(|| cannot_combine(name, a, b))
+}
+
+/// `a + (b - a) * t`, component-wise.
+fn mix(name: &str, a: &EvalValue, b: &EvalValue, t: &EvalValue) -> Result<EvalValue, String> {
+    let (x, y, z) = (floats(name, a)?, floats(name, b)?, floats(name, t)?);
+    let difference = zip_with(y, x, |b, a| b - a).ok_or_else(|| cannot_combine(name, a, b))?;
+    let scaled =
+        zip_with(&difference, z, |d, t| d * t).ok_or_else(|| cannot_combine(name, b, t))?;
+    zip_with(x, &scaled, |a, s| a + s)
+        .map(EvalValue::Float)
+        .ok_or_else(|| cannot_combine(name, a, t))
+}
+
+fn compare(
+    name: &str,
+    a: &EvalValue,
+    b: &EvalValue,
+    f: fn(f64, f64) -> bool,
+) -> Result<EvalValue, String> {
+    if a.value_type() != b.value_type() {
+        return Err(cannot_combine(name, a, b));
+    }
+    zip_with(&a.to_f64s(), &b.to_f64s(), f)
+        .map(EvalValue::Bool)
+        .ok_or_else(|| cannot_combine(name, a, b))
+}
+
+/// Joins scalars and vectors of the same scalar type into one vector.
+fn concat(name: &str, parts: &[EvalValue]) -> Result<EvalValue, String> {
+    let mut result = parts[0].clone();
+    for part in &parts[1..] {
+        match (&mut result, part) {
+            (EvalValue::Float(v), EvalValue::Float(w)) => v.extend(w),
+            (EvalValue::Uint(v), EvalValue::Uint(w)) => v.extend(w),
+            (EvalValue::Int(v), EvalValue::Int(w)) => v.extend(w),
+            (EvalValue::Bool(v), EvalValue::Bool(w)) => v.extend(w),
+            _ => return Err(cannot_combine(name, &parts[0], part)),
+        }
+    }
+    Ok(result)
+}
+
+/// Whether `expr`, or a definition it refers to, contains a leaf or operator
+/// for which `f` is true.
+pub fn expr_uses(
+    expr: &ExprWriterEditor,
+    definitions: &[ExprDefinition],
+    f: &impl Fn(&ExprWriterEditor) -> bool,
+) -> bool {
+    fn visit(
+        expr: &ExprWriterEditor,
+        definitions: &[ExprDefinition],
+        f: &impl Fn(&ExprWriterEditor) -> bool,
+        visited: &mut Vec<String>,
+    ) -> bool {
+        if f(expr) {
+            return true;
+        }
+        match expr {
+            ExprWriterEditor::Operator(o, _) => o
+                .operands()
+                .into_iter()
+                .any(|operand| visit(operand, definitions, f, visited)),
+            ExprWriterEditor::Ref(name) if !visited.contains(name) => {
+                visited.push(name.clone());
+                find_definition(definitions, name)
+                    .is_some_and(|definition| visit(&definition.expr, definitions, f, visited))
+            }
+            _ => false,
+        }
+    }
+    visit(expr, definitions, f, &mut Vec::new())
+}
+
+pub fn is_random(expr: &ExprWriterEditor) -> bool {
+    match expr {
+        ExprWriterEditor::RandU32
+        | ExprWriterEditor::RandFloat
+        | ExprWriterEditor::RandVec3
+        | ExprWriterEditor::RandomRange(_) => true,
+        ExprWriterEditor::Operator(o, _) => matches!(**o, ExprOperatorEditor::Uniform(..)),
+        _ => false,
+    }
+}
+
+pub fn is_time_dependent(expr: &ExprWriterEditor) -> bool {
+    match expr {
+        ExprWriterEditor::Time | ExprWriterEditor::RealTime | ExprWriterEditor::Age => true,
+        ExprWriterEditor::Operator(o, _) => {
+            matches!(**o, ExprOperatorEditor::Attr(attr) if attr == Attribute::AGE)
+        }
+        _ => false,
+    }
+}
+
+/// Number of values drawn for a histogram.
+const HISTOGRAM_SAMPLES: u64 = 1000;
+const HISTOGRAM_BINS: usize = 24;
+/// Seconds covered by the plot of a time-dependent expression.
+const PLOT_DURATION: f32 = 4.0;
+const PLOT_POINTS: usize = 64;
+const PLOT_SIZE: egui::Vec2 = egui::vec2(240.0, 64.0);
+
+fn component_color(index: usize) -> egui::Color32 {
+    [
+        egui::Color32::from_rgb(230, 90, 80),
+        egui::Color32::from_rgb(110, 200, 90),
+        egui::Color32::from_rgb(90, 140, 240),
+        egui::Color32::GRAY,
+    ][index.min(3)]
+}
+
+/// Range of the finite values, widened when they are all equal.
+fn value_range(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    }
+}
+
+/// Lines of each component of `series`, where every point of the outer
+/// vector is one sample.
+fn draw_plot(ui: &mut egui::Ui, series: &[Vec<f32>]) {
+    let (rect, _) = ui.allocate_exact_size(PLOT_SIZE, egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let (min, max) = value_range(series.iter().flatten().copied());
+    let count = series.first().map_or(0, |s| s.len());
+    for component in 0..count {
+        let points: Vec<egui::Pos2> = series
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s[component].is_finite())
+            .map(|(i, s)| {
+                let x = i as f32 / (series.len() - 1).max(1) as f32;
+                let y = (s[component] - min) / (max - min);
+                egui::pos2(
+                    rect.left() + x * rect.width(),
+                    rect.bottom() - y * rect.height(),
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, component_color(component)),
+        ));
+    }
+    ui.label(format!(
+        "{:.3} … {:.3} over 0 … {} s of time and age",
+        min, max, PLOT_DURATION
+    ));
+}
+
+/// Histograms of each component of `samples`, side by side.
+fn draw_histograms(ui: &mut egui::Ui, samples: &[Vec<f32>]) {
+    let count = samples.first().map_or(0, |s| s.len());
+    let width = (PLOT_SIZE.x - 4.0 * (count as f32 - 1.0)) / count as f32;
+    ui.horizontal(|ui| {
+        for component in 0..count {
+            let values = || samples.iter().map(|s| s[component]);
+            let (min, max) = value_range(values());
+            let mut bins = [0u32; HISTOGRAM_BINS];
+            for v in values().filter(|v| v.is_finite()) {
+                let bin = ((v - min) / (max - min) * HISTOGRAM_BINS as f32) as usize;
+                bins[bin.min(HISTOGRAM_BINS - 1)] += 1;
+            }
+            let highest = bins.iter().copied().max().unwrap_or(1).max(1);
+            ui.vertical(|ui| {
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::vec2(width, PLOT_SIZE.y), egui::Sense::hover());
+                let painter = ui.painter();
+                painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+                let bin_width = rect.width() / HISTOGRAM_BINS as f32;
+                for (i, n) in bins.iter().enumerate() {
+                    let height = rect.height() * *n as f32 / highest as f32;
+                    let left = rect.left() + i as f32 * bin_width;
+                    painter.rect_filled(
+                        egui::Rect::from_min_max(
+                            egui::pos2(left, rect.bottom() - height),
+                            egui::pos2(left + bin_width - 1.0, rect.bottom()),
+                        ),
+                        0.0,
+                        component_color(component),
+                    );
+                }
+                ui.label(format!("{:.2} … {:.2}", min, max));
+            });
+        }
+    });
+}
+
+/// Sample value of an expression, with a plot over time and age when it
+/// depends on them, or a histogram of its values when it is random.
+pub fn ui_for_expr_preview(ui: &mut egui::Ui, expr: &ExprWriterEditor, inputs: &EvalInputs) {
+    let error_color = ui.visuals().error_fg_color;
+    let value = match expr.eval(inputs, 0) {
+        Ok(value) => value,
+        Err(error) => {
+            ui.colored_label(error_color, format!("⚠ {}", error));
+            return;
+        }
+    };
+    ui.label(format!("= {}", value)).on_hover_text(
+        "Value for the first particle, at the start of the simulation and with default attributes",
+    );
+    let definitions = &inputs.definitions;
+    if expr_uses(expr, definitions, &is_time_dependent) {
+        // The same seed at every point follows a single particle.
+        let series: Result<Vec<Vec<f32>>, String> = (0..PLOT_POINTS)
+            .map(|i| {
+                let t = PLOT_DURATION * i as f32 / (PLOT_POINTS - 1) as f32;
+                expr.eval(&inputs.at_time(t), 0).map(|v| v.to_f32s())
+            })
+            .collect();
+        match series {
+            Ok(series) => draw_plot(ui, &series),
+            Err(error) => {
+                ui.colored_label(error_color, format!("⚠ {}", error));
+            }
+        }
+    } else if expr_uses(expr, definitions, &is_random) {
+        let samples: Result<Vec<Vec<f32>>, String> = (0..HISTOGRAM_SAMPLES)
+            .map(|seed| expr.eval(inputs, seed).map(|v| v.to_f32s()))
+            .collect();
+        match samples {
+            Ok(samples) => draw_histograms(ui, &samples),
+            Err(error) => {
+                ui.colored_label(error_color, format!("⚠ {}", error));
+            }
+        }
+    }
+}
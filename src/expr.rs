@@ -1,12 +1,24 @@
 use bevy::prelude::*;
 
 use bevy_egui::*;
+use bevy_hanabi::graph::expr::PropertyHandle;
 use bevy_hanabi::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 
+use crate::definitions::{ExprDefinition, find_definition};
 use crate::editor_prelude::AppContext;
+use crate::eval::ui_for_expr_preview;
+use crate::graph::ui_for_expr_graph;
 use crate::helpers::*;
+use crate::ids::NodeId;
+use crate::optimize::{fold_constants, is_shareable};
+use crate::properties::EffectProperty;
+use crate::random::RandomRange;
+use crate::syntax::{ParseError, parse_expr};
+use crate::typecheck::{TypeContext, elem_type, with_elem_type};
 
 pub const ALL_ATTRS: [(Attribute, &str); 39] = [
     (Attribute::ID, "ID"),
@@ -51,26 +63,194 @@ pub const ALL_ATTRS: [(Attribute, &str); 39] = [
 ];
 
 pub fn attr_to_label(attr: Attribute) -> &'static str {
-    if let Some(result) = ALL_ATTRS
-        .iter()
-        .filter(|(attr_opt, _)| attr == *attr_opt)
-        .next()
-    {
+    if let Some(result) = ALL_ATTRS.iter().find(|(attr_opt, _)| attr == *attr_opt) {
         result.1
     } else {
         "None"
     }
 }
 
+/// An [`ExprWriter`] along with the declarations of the effect being
+/// produced that expressions can refer to by name.
+pub struct EffectWriter {
+    writer: ExprWriter,
+    properties: Vec<(String, PropertyHandle)>,
+    definitions: Vec<ExprDefinition>,
+    /// The declared properties, for [`EffectWriter::type_context`].
+    declared: Vec<EffectProperty>,
+    /// Definitions being inlined, innermost last.
+    inlining: RefCell<Vec<String>>,
+    optimize: bool,
+    /// Whether the tree being produced already went through
+    /// [`fold_constants`].
+    folded: Cell<bool>,
+    /// Expressions produced so far, by the text of their subtree.
+    shared: RefCell<HashMap<String, WriterExpr>>,
+}
+
+impl EffectWriter {
+    pub fn new() -> Self {
+        Self::with_definitions(&[])
+    }
+
+    pub fn with_definitions(definitions: &[ExprDefinition]) -> Self {
+        Self {
+            writer: ExprWriter::new(),
+            properties: Vec::new(),
+            definitions: definitions.to_vec(),
+            declared: Vec::new(),
+            inlining: Default::default(),
+            optimize: false,
+            folded: Cell::new(false),
+            shared: Default::default(),
+        }
+    }
+
+    /// Folds constant subtrees into literals and produces identical subtrees
+    /// only once, see [`fold_constants`] and [`is_shareable`].
+    pub fn with_optimization(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Produces `expr`, optimized if enabled.
+    pub fn produce(&self, expr: &ExprWriterEditor) -> WriterExpr {
+        if !self.optimize {
+            return expr.produce_node(self);
+        }
+        if !self.folded.get() {
+            let folded = fold_constants(expr, &self.definitions);
+            self.folded.set(true);
+            let produced = self.produce(&folded);
+            self.folded.set(false);
+            return produced;
+        }
+        if !is_shareable(expr, &self.definitions) {
+            return expr.produce_node(self);
+        }
+        let key = expr.to_string();
+        if let Some(produced) = self.shared.borrow().get(&key) {
+            return produced.clone();
+        }
+        let produced = expr.produce_node(self);
+        self.shared.borrow_mut().insert(key, produced.clone());
+        produced
+    }
+
+    /// Declares a property. Later declarations of the same name are ignored.
+    pub fn add_property(&mut self, property: &EffectProperty) {
+        if self.property(&property.name).is_none() {
+            let handle = self
+                .writer
+                .add_property(&property.name, property.default.into());
+            self.properties.push((property.name.clone(), handle));
+            self.declared.push(property.clone());
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<PropertyHandle> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, handle)| *handle)
+    }
+
+    /// Types of the definitions and properties expressions can refer to.
+    pub fn type_context(&self) -> TypeContext<'_> {
+        TypeContext::new(&self.definitions).with_properties(&self.declared)
+    }
+
+    /// Produces the expression of the definition `name` in place.
+    /// Unknown and cyclic references produce `0.0`.
+    pub fn inline_definition(&self, name: &str) -> WriterExpr {
+        if self.inlining.borrow().iter().any(|n| n == name) {
+            warn!("Definition {} refers to itself", name);
+            return self.lit(0.0);
+        }
+        let Some(definition) = find_definition(&self.definitions, name) else {
+            warn!("Unknown definition {}", name);
+            return self.lit(0.0);
+        };
+        self.inlining.borrow_mut().push(name.to_string());
+        let expr = definition.expr.produce(self);
+        self.inlining.borrow_mut().pop();
+        expr
+    }
+
+    pub fn finish(self) -> Module {
+        self.writer.finish()
+    }
+}
+
+impl Default for EffectWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::ops::Deref for EffectWriter {
+    type Target = ExprWriter;
+
+    fn deref(&self) -> &ExprWriter {
+        &self.writer
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum ExprOperatorEditor {
     Uniform(ExprWriterEditor, ExprWriterEditor),
     Multiply(ExprWriterEditor, ExprWriterEditor),
     Subtract(ExprWriterEditor, ExprWriterEditor),
     Add(ExprWriterEditor, ExprWriterEditor),
+    Div(ExprWriterEditor, ExprWriterEditor),
+    Rem(ExprWriterEditor, ExprWriterEditor),
+    Neg(ExprWriterEditor),
+    Min(ExprWriterEditor, ExprWriterEditor),
+    Max(ExprWriterEditor, ExprWriterEditor),
+    /// Value, lower bound, upper bound.
+    Clamp(ExprWriterEditor, ExprWriterEditor, ExprWriterEditor),
+    /// Linear interpolation from the first to the second operand by the third.
+    Mix(ExprWriterEditor, ExprWriterEditor, ExprWriterEditor),
+    Abs(ExprWriterEditor),
+    Sign(ExprWriterEditor),
+    Floor(ExprWriterEditor),
+    Ceil(ExprWriterEditor),
+    Fract(ExprWriterEditor),
+    /// Edge, value. 1.0 when the value is at or above the edge, 0.0 otherwise.
+    Step(ExprWriterEditor, ExprWriterEditor),
+    /// Lower edge, upper edge, value.
+    Smoothstep(ExprWriterEditor, ExprWriterEditor, ExprWriterEditor),
+    /// Base, exponent.
+    Pow(ExprWriterEditor, ExprWriterEditor),
+    Sqrt(ExprWriterEditor),
+    Exp(ExprWriterEditor),
+    Log(ExprWriterEditor),
     Sin(ExprWriterEditor),
     Cos(ExprWriterEditor),
+    Tan(ExprWriterEditor),
+    /// Y, x.
+    Atan2(ExprWriterEditor, ExprWriterEditor),
+    Lt(ExprWriterEditor, ExprWriterEditor),
+    Le(ExprWriterEditor, ExprWriterEditor),
+    Gt(ExprWriterEditor, ExprWriterEditor),
+    Ge(ExprWriterEditor, ExprWriterEditor),
+    /// True when all components are equal.
+    Eq(ExprWriterEditor, ExprWriterEditor),
+    /// True when any component differs.
+    Neq(ExprWriterEditor, ExprWriterEditor),
+    And(ExprWriterEditor, ExprWriterEditor),
+    Or(ExprWriterEditor, ExprWriterEditor),
+    Not(ExprWriterEditor),
+    /// True when all components of a boolean vector are true.
+    All(ExprWriterEditor),
+    /// True when any component of a boolean vector is true.
+    Any(ExprWriterEditor),
+    /// Condition, value if true, value if false.
+    Select(ExprWriterEditor, ExprWriterEditor, ExprWriterEditor),
     Distance(ExprWriterEditor, ExprWriterEditor),
+    Dot(ExprWriterEditor, ExprWriterEditor),
+    Cross(ExprWriterEditor, ExprWriterEditor),
+    Length(ExprWriterEditor),
     Vec3(ExprWriterEditor, ExprWriterEditor, ExprWriterEditor),
     Vec4(
         ExprWriterEditor,
@@ -78,6 +258,14 @@ pub enum ExprOperatorEditor {
         ExprWriterEditor,
         ExprWriterEditor,
     ),
+    Vec2(ExprWriterEditor, ExprWriterEditor),
+    /// A vec4 from the xyz of a vec3 and a w component.
+    Vec4XyzW(ExprWriterEditor, ExprWriterEditor),
+    /// Components of a vector picked by a swizzle such as `x` or `zxy`.
+    Swizzle(ExprWriterEditor, String),
+    AsF32(ExprWriterEditor),
+    AsU32(ExprWriterEditor),
+    AsI32(ExprWriterEditor),
     Pack4x8UNorm(ExprWriterEditor),
     Attr(Attribute),
     ParentAttr(Attribute),
@@ -85,7 +273,7 @@ pub enum ExprOperatorEditor {
 }
 
 impl ExprOperatorEditor {
-    pub fn produce(&self, writer: &ExprWriter) -> WriterExpr {
+    pub fn produce(&self, writer: &EffectWriter) -> WriterExpr {
         match self {
             ExprOperatorEditor::Uniform(lit1, lit2) => {
                 lit1.produce(writer).uniform(lit2.produce(writer))
@@ -97,11 +285,82 @@ impl ExprOperatorEditor {
                 lit1.produce(writer).sub(lit2.produce(writer))
             }
             ExprOperatorEditor::Add(lit1, lit2) => lit1.produce(writer).add(lit2.produce(writer)),
+            ExprOperatorEditor::Div(lit1, lit2) => lit1.produce(writer).div(lit2.produce(writer)),
+            ExprOperatorEditor::Rem(lit1, lit2) => lit1.produce(writer).rem(lit2.produce(writer)),
+            // Hanabi has no negation operator.
+            ExprOperatorEditor::Neg(lit) => lit.produce(writer).mul(writer.lit(-1.0)),
+            ExprOperatorEditor::Min(lit1, lit2) => lit1.produce(writer).min(lit2.produce(writer)),
+            ExprOperatorEditor::Max(lit1, lit2) => lit1.produce(writer).max(lit2.produce(writer)),
+            ExprOperatorEditor::Clamp(lit, low, high) => lit
+                .produce(writer)
+                .max(low.produce(writer))
+                .min(high.produce(writer)),
+            ExprOperatorEditor::Mix(lit1, lit2, t) => lit1
+                .produce(writer)
+                .mix(lit2.produce(writer), t.produce(writer)),
+            ExprOperatorEditor::Abs(lit) => lit.produce(writer).abs(),
+            ExprOperatorEditor::Sign(lit) => lit.produce(writer).sign(),
+            ExprOperatorEditor::Floor(lit) => lit.produce(writer).floor(),
+            ExprOperatorEditor::Ceil(lit) => lit.produce(writer).ceil(),
+            ExprOperatorEditor::Fract(lit) => lit.produce(writer).fract(),
+            ExprOperatorEditor::Step(edge, lit) => lit.produce(writer).step(edge.produce(writer)),
+            ExprOperatorEditor::Smoothstep(low, high, lit) => lit
+                .produce(writer)
+                .smoothstep(low.produce(writer), high.produce(writer)),
+            ExprOperatorEditor::Pow(base, exponent) => produce_pow(writer, base, exponent),
+            ExprOperatorEditor::Sqrt(lit) => lit.produce(writer).sqrt(),
+            ExprOperatorEditor::Exp(lit) => lit.produce(writer).exp(),
+            ExprOperatorEditor::Log(lit) => lit.produce(writer).log(),
+            ExprOperatorEditor::Sin(lit) => lit.produce(writer).sin(),
+            ExprOperatorEditor::Cos(lit) => lit.produce(writer).cos(),
+            ExprOperatorEditor::Tan(lit) => lit.produce(writer).tan(),
+            ExprOperatorEditor::Atan2(y, x) => y.produce(writer).atan2(x.produce(writer)),
+            ExprOperatorEditor::Lt(lit1, lit2) => lit1.produce(writer).lt(lit2.produce(writer)),
+            ExprOperatorEditor::Le(lit1, lit2) => lit1.produce(writer).le(lit2.produce(writer)),
+            ExprOperatorEditor::Gt(lit1, lit2) => lit1.produce(writer).gt(lit2.produce(writer)),
+            ExprOperatorEditor::Ge(lit1, lit2) => lit1.produce(writer).ge(lit2.produce(writer)),
+            // Hanabi has no equality, boolean or select operators, they are
+            // built from comparisons, `all`/`any` and casts instead.
+            ExprOperatorEditor::Eq(lit1, lit2) => produce_eq(writer, lit1, lit2),
+            ExprOperatorEditor::Neq(lit1, lit2) => {
+                produce_not(writer, produce_eq(writer, lit1, lit2))
+            }
+            ExprOperatorEditor::And(lit1, lit2) => {
+                lit1.produce(writer).vec2(lit2.produce(writer)).all()
+            }
+            ExprOperatorEditor::Or(lit1, lit2) => {
+                lit1.produce(writer).vec2(lit2.produce(writer)).any()
+            }
+            ExprOperatorEditor::Not(lit) => produce_not(writer, lit.produce(writer)),
+            ExprOperatorEditor::All(lit) => lit.produce(writer).all(),
+            ExprOperatorEditor::Any(lit) => lit.produce(writer).any(),
+            ExprOperatorEditor::Select(condition, if_true, if_false) => {
+                let value_type = self.check_types_in(&writer.type_context()).ok().flatten();
+                let if_true = if_true.produce(writer);
+                let if_false = if_false.produce(writer);
+                let condition = condition.produce(writer);
+                // Hanabi has no select, so integers use f + (t - f) * c, which
+                // wrapping arithmetic makes exact. Floats use mix(), so an inf
+                // or NaN in the branch not taken still gives NaN, unlike the
+                // preview and the constant folder.
+                match value_type {
+                    Some(value_type)
+                        if matches!(elem_type(value_type), ScalarType::Uint | ScalarType::Int) =>
+                    {
+                        let condition = condition.cast(value_type);
+                        if_false.clone().add(if_true.sub(if_false).mul(condition))
+                    }
+                    _ => if_false.mix(if_true, condition.cast(ScalarType::Float)),
+                }
+            }
             ExprOperatorEditor::Distance(lit1, lit2) => {
                 lit1.produce(writer).distance(lit2.produce(writer))
             }
-            ExprOperatorEditor::Sin(lit) => lit.produce(writer).sin(),
-            ExprOperatorEditor::Cos(lit) => lit.produce(writer).cos(),
+            ExprOperatorEditor::Dot(lit1, lit2) => lit1.produce(writer).dot(lit2.produce(writer)),
+            ExprOperatorEditor::Cross(lit1, lit2) => {
+                lit1.produce(writer).cross(lit2.produce(writer))
+            }
+            ExprOperatorEditor::Length(lit) => lit.produce(writer).length(),
             ExprOperatorEditor::Vec3(lit1, lit2, lit3) => lit1
                 .produce(writer)
                 .vec3(lit2.produce(writer), lit3.produce(writer)),
@@ -109,6 +368,16 @@ impl ExprOperatorEditor {
                 .produce(writer)
                 .vec3(lit2.produce(writer), lit3.produce(writer))
                 .vec4_xyz_w(lit4.produce(writer)),
+            ExprOperatorEditor::Vec2(lit1, lit2) => lit1.produce(writer).vec2(lit2.produce(writer)),
+            ExprOperatorEditor::Vec4XyzW(xyz, w) => {
+                xyz.produce(writer).vec4_xyz_w(w.produce(writer))
+            }
+            ExprOperatorEditor::Swizzle(lit, components) => {
+                produce_swizzle(writer, lit.produce(writer), components)
+            }
+            ExprOperatorEditor::AsF32(lit) => produce_cast(writer, lit, ScalarType::Float),
+            ExprOperatorEditor::AsU32(lit) => produce_cast(writer, lit, ScalarType::Uint),
+            ExprOperatorEditor::AsI32(lit) => produce_cast(writer, lit, ScalarType::Int),
             ExprOperatorEditor::Pack4x8UNorm(lit) => lit.produce(writer).pack4x8unorm(),
             ExprOperatorEditor::Attr(attr) => writer.attr(*attr),
             ExprOperatorEditor::ParentAttr(attr) => writer.parent_attr(*attr),
@@ -116,85 +385,224 @@ impl ExprOperatorEditor {
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExprOperatorEditor::Uniform(..) => "Uniform",
+            ExprOperatorEditor::Multiply(..) => "Multiply",
+            ExprOperatorEditor::Subtract(..) => "Subtract",
+            ExprOperatorEditor::Add(..) => "Add",
+            ExprOperatorEditor::Div(..) => "Div",
+            ExprOperatorEditor::Rem(..) => "Rem",
+            ExprOperatorEditor::Neg(..) => "Neg",
+            ExprOperatorEditor::Min(..) => "Min",
+            ExprOperatorEditor::Max(..) => "Max",
+            ExprOperatorEditor::Clamp(..) => "Clamp",
+            ExprOperatorEditor::Mix(..) => "Mix",
+            ExprOperatorEditor::Abs(..) => "Abs",
+            ExprOperatorEditor::Sign(..) => "Sign",
+            ExprOperatorEditor::Floor(..) => "Floor",
+            ExprOperatorEditor::Ceil(..) => "Ceil",
+            ExprOperatorEditor::Fract(..) => "Fract",
+            ExprOperatorEditor::Step(..) => "Step",
+            ExprOperatorEditor::Smoothstep(..) => "Smoothstep",
+            ExprOperatorEditor::Pow(..) => "Pow",
+            ExprOperatorEditor::Sqrt(..) => "Sqrt",
+            ExprOperatorEditor::Exp(..) => "Exp",
+            ExprOperatorEditor::Log(..) => "Log",
+            ExprOperatorEditor::Sin(..) => "Sin",
+            ExprOperatorEditor::Cos(..) => "Cos",
+            ExprOperatorEditor::Tan(..) => "Tan",
+            ExprOperatorEditor::Atan2(..) => "Atan2",
+            ExprOperatorEditor::Lt(..) => "Lt",
+            ExprOperatorEditor::Le(..) => "Le",
+            ExprOperatorEditor::Gt(..) => "Gt",
+            ExprOperatorEditor::Ge(..) => "Ge",
+            ExprOperatorEditor::Eq(..) => "Eq",
+            ExprOperatorEditor::Neq(..) => "Neq",
+            ExprOperatorEditor::And(..) => "And",
+            ExprOperatorEditor::Or(..) => "Or",
+            ExprOperatorEditor::Not(..) => "Not",
+            ExprOperatorEditor::All(..) => "All",
+            ExprOperatorEditor::Any(..) => "Any",
+            ExprOperatorEditor::Select(..) => "Select",
+            ExprOperatorEditor::Distance(..) => "Distance",
+            ExprOperatorEditor::Dot(..) => "Dot",
+            ExprOperatorEditor::Cross(..) => "Cross",
+            ExprOperatorEditor::Length(..) => "Length",
+            ExprOperatorEditor::Vec3(..) => "Vec3",
+            ExprOperatorEditor::Vec4(..) => "Vec4",
+            ExprOperatorEditor::Vec2(..) => "Vec2",
+            ExprOperatorEditor::Vec4XyzW(..) => "Vec4XyzW",
+            ExprOperatorEditor::Swizzle(..) => "Swizzle",
+            ExprOperatorEditor::AsF32(..) => "AsF32",
+            ExprOperatorEditor::AsU32(..) => "AsU32",
+            ExprOperatorEditor::AsI32(..) => "AsI32",
+            ExprOperatorEditor::Pack4x8UNorm(..) => "Pack4x8UNorm",
+            ExprOperatorEditor::Attr(..) => "Attr",
+            ExprOperatorEditor::ParentAttr(..) => "ParentAttr",
+            ExprOperatorEditor::Normalized(..) => "Normalized",
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&ExprWriterEditor> {
+        match self {
+            ExprOperatorEditor::Uniform(a, b)
+            | ExprOperatorEditor::Multiply(a, b)
+            | ExprOperatorEditor::Subtract(a, b)
+            | ExprOperatorEditor::Add(a, b)
+            | ExprOperatorEditor::Div(a, b)
+            | ExprOperatorEditor::Rem(a, b)
+            | ExprOperatorEditor::Min(a, b)
+            | ExprOperatorEditor::Max(a, b)
+            | ExprOperatorEditor::Step(a, b)
+            | ExprOperatorEditor::Pow(a, b)
+            | ExprOperatorEditor::Atan2(a, b)
+            | ExprOperatorEditor::Lt(a, b)
+            | ExprOperatorEditor::Le(a, b)
+            | ExprOperatorEditor::Gt(a, b)
+            | ExprOperatorEditor::Ge(a, b)
+            | ExprOperatorEditor::Eq(a, b)
+            | ExprOperatorEditor::Neq(a, b)
+            | ExprOperatorEditor::And(a, b)
+            | ExprOperatorEditor::Or(a, b)
+            | ExprOperatorEditor::Distance(a, b)
+            | ExprOperatorEditor::Dot(a, b)
+            | ExprOperatorEditor::Cross(a, b)
+            | ExprOperatorEditor::Vec2(a, b)
+            | ExprOperatorEditor::Vec4XyzW(a, b) => vec![a, b],
+            ExprOperatorEditor::Neg(a)
+            | ExprOperatorEditor::Abs(a)
+            | ExprOperatorEditor::Sign(a)
+            | ExprOperatorEditor::Floor(a)
+            | ExprOperatorEditor::Ceil(a)
+            | ExprOperatorEditor::Fract(a)
+            | ExprOperatorEditor::Sqrt(a)
+            | ExprOperatorEditor::Exp(a)
+            | ExprOperatorEditor::Log(a)
+            | ExprOperatorEditor::Sin(a)
+            | ExprOperatorEditor::Cos(a)
+            | ExprOperatorEditor::Tan(a)
+            | ExprOperatorEditor::Length(a)
+            | ExprOperatorEditor::Not(a)
+            | ExprOperatorEditor::All(a)
+            | ExprOperatorEditor::Any(a)
+            | ExprOperatorEditor::Swizzle(a, _)
+            | ExprOperatorEditor::AsF32(a)
+            | ExprOperatorEditor::AsU32(a)
+            | ExprOperatorEditor::AsI32(a)
+            | ExprOperatorEditor::Pack4x8UNorm(a)
+            | ExprOperatorEditor::Normalized(a) => vec![a],
+            ExprOperatorEditor::Clamp(a, b, c)
+            | ExprOperatorEditor::Mix(a, b, c)
+            | ExprOperatorEditor::Smoothstep(a, b, c)
+            | ExprOperatorEditor::Select(a, b, c)
+            | ExprOperatorEditor::Vec3(a, b, c) => vec![a, b, c],
+            ExprOperatorEditor::Vec4(a, b, c, d) => vec![a, b, c, d],
+            ExprOperatorEditor::Attr(_) | ExprOperatorEditor::ParentAttr(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        match self {
+            ExprOperatorEditor::Uniform(a, b)
+            | ExprOperatorEditor::Multiply(a, b)
+            | ExprOperatorEditor::Subtract(a, b)
+            | ExprOperatorEditor::Add(a, b)
+            | ExprOperatorEditor::Div(a, b)
+            | ExprOperatorEditor::Rem(a, b)
+            | ExprOperatorEditor::Min(a, b)
+            | ExprOperatorEditor::Max(a, b)
+            | ExprOperatorEditor::Step(a, b)
+            | ExprOperatorEditor::Pow(a, b)
+            | ExprOperatorEditor::Atan2(a, b)
+            | ExprOperatorEditor::Lt(a, b)
+            | ExprOperatorEditor::Le(a, b)
+            | ExprOperatorEditor::Gt(a, b)
+            | ExprOperatorEditor::Ge(a, b)
+            | ExprOperatorEditor::Eq(a, b)
+            | ExprOperatorEditor::Neq(a, b)
+            | ExprOperatorEditor::And(a, b)
+            | ExprOperatorEditor::Or(a, b)
+            | ExprOperatorEditor::Distance(a, b)
+            | ExprOperatorEditor::Dot(a, b)
+            | ExprOperatorEditor::Cross(a, b)
+            | ExprOperatorEditor::Vec2(a, b)
+            | ExprOperatorEditor::Vec4XyzW(a, b) => vec![a, b],
+            ExprOperatorEditor::Neg(a)
+            | ExprOperatorEditor::Abs(a)
+            | ExprOperatorEditor::Sign(a)
+            | ExprOperatorEditor::Floor(a)
+            | ExprOperatorEditor::Ceil(a)
+            | ExprOperatorEditor::Fract(a)
+            | ExprOperatorEditor::Sqrt(a)
+            | ExprOperatorEditor::Exp(a)
+            | ExprOperatorEditor::Log(a)
+            | ExprOperatorEditor::Sin(a)
+            | ExprOperatorEditor::Cos(a)
+            | ExprOperatorEditor::Tan(a)
+            | ExprOperatorEditor::Length(a)
+            | ExprOperatorEditor::Not(a)
+            | ExprOperatorEditor::All(a)
+            | ExprOperatorEditor::Any(a)
+            | ExprOperatorEditor::Swizzle(a, _)
+            | ExprOperatorEditor::AsF32(a)
+            | ExprOperatorEditor::AsU32(a)
+            | ExprOperatorEditor::AsI32(a)
+            | ExprOperatorEditor::Pack4x8UNorm(a)
+            | ExprOperatorEditor::Normalized(a) => vec![a],
+            ExprOperatorEditor::Clamp(a, b, c)
+            | ExprOperatorEditor::Mix(a, b, c)
+            | ExprOperatorEditor::Smoothstep(a, b, c)
+            | ExprOperatorEditor::Select(a, b, c)
+            | ExprOperatorEditor::Vec3(a, b, c) => vec![a, b, c],
+            ExprOperatorEditor::Vec4(a, b, c, d) => vec![a, b, c, d],
+            ExprOperatorEditor::Attr(_) | ExprOperatorEditor::ParentAttr(_) => Vec::new(),
+        }
+    }
+
+    /// Name shown in menus and node headers, with the parameters that are
+    /// not operands.
+    pub(crate) fn display_name(&self) -> String {
+        match self {
+            ExprOperatorEditor::Swizzle(_, components) => format!("Swizzle .{}", components),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /// Labels shown next to the operands of operators whose argument order
+    /// is not obvious. Empty for the others.
+    pub(crate) fn operand_labels(&self) -> &'static [&'static str] {
+        match self {
+            ExprOperatorEditor::Uniform(..) => &["min", "max"],
+            ExprOperatorEditor::Clamp(..) => &["value", "min", "max"],
+            ExprOperatorEditor::Mix(..) => &["from", "to", "t"],
+            ExprOperatorEditor::Step(..) => &["edge", "value"],
+            ExprOperatorEditor::Smoothstep(..) => &["low", "high", "value"],
+            ExprOperatorEditor::Pow(..) => &["base", "exponent"],
+            ExprOperatorEditor::Atan2(..) => &["y", "x"],
+            ExprOperatorEditor::Select(..) => &["condition", "if true", "if false"],
+            ExprOperatorEditor::Vec4XyzW(..) => &["xyz", "w"],
+            _ => &[],
+        }
+    }
+
+    /// Operands are drawn with [`FieldRange::ANY`]: the range of a slot
+    /// applies to the value it holds, not to the operands it is computed
+    /// from.
     pub fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
         let unique_id = index;
         match self {
-            ExprOperatorEditor::Uniform(lit1, lit2) => {
-                unique_collapsing(unique_id, "🖩 Uniform", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                });
-            }
-            ExprOperatorEditor::Multiply(lit1, lit2) => {
-                unique_collapsing(unique_id, "🖩 Multiply", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                });
-            }
-            ExprOperatorEditor::Subtract(lit1, lit2) => {
-                unique_collapsing(unique_id, "🖩 Subtract", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                });
-            }
-            ExprOperatorEditor::Add(lit1, lit2) => {
-                unique_collapsing(unique_id, "🖩 Add", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                });
-            }
-            ExprOperatorEditor::Sin(lit) => {
-                unique_collapsing(unique_id, "🖩 Sin", ui).show(ui, |ui| {
-                    lit.draw_ui(app, ui, 1);
-                });
-            }
-            ExprOperatorEditor::Cos(lit) => {
-                unique_collapsing(unique_id, "🖩 Cos", ui).show(ui, |ui| {
-                    lit.draw_ui(app, ui, 1);
-                });
-            }
-            ExprOperatorEditor::Distance(lit1, lit2) => {
-                unique_collapsing(unique_id, "🖩 Distance", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                });
-            }
-            ExprOperatorEditor::Vec3(lit1, lit2, lit3) => {
-                unique_collapsing(unique_id, "🖩 Vec3", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                    lit3.draw_ui(app, ui, 3);
-                });
-            }
-            ExprOperatorEditor::Vec4(lit1, lit2, lit3, lit4) => {
-                unique_collapsing(unique_id, "🖩 Vec4", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                    lit2.draw_ui(app, ui, 2);
-                    lit3.draw_ui(app, ui, 3);
-                    lit4.draw_ui(app, ui, 4);
-                });
-            }
-            ExprOperatorEditor::Normalized(lit1) => {
-                unique_collapsing(unique_id, "🖩 Normalized", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                });
-            }
-            ExprOperatorEditor::Pack4x8UNorm(lit1) => {
-                unique_collapsing(unique_id, "🖩 Pack4x8UNorm", ui).show(ui, |ui| {
-                    lit1.draw_ui(app, ui, 1);
-                });
-            }
             ExprOperatorEditor::Attr(attr) | ExprOperatorEditor::ParentAttr(attr) => {
-                let mut selected_attr: Attribute = attr.clone();
+                let mut selected_attr: Attribute = *attr;
                 let label = match self {
                     ExprOperatorEditor::Attr(_) => "🖩 Attr",
                     ExprOperatorEditor::ParentAttr(_) => "🖩 ParentAttr",
                     _ => unreachable!(),
                 };
-                unique_collapsing(unique_id, label, ui).show(ui, |ui| {
+                unique_collapsing(unique_id, label, label, ui).show(ui, |ui| {
                     let selected_text = attr_to_label(selected_attr);
 
-                    let id = ui.make_persistent_id(format!("header"));
+                    let id = ui.make_persistent_id("header");
                     ui.vertical(|ui| {
                         egui::collapsing_header::CollapsingState::load_with_default_open(
                             ui.ctx(),
@@ -219,83 +627,267 @@ impl ExprOperatorEditor {
                     ExprOperatorEditor::ParentAttr(selected_attr)
                 };
             }
+            _ => {
+                let label = format!("🖩 {}", self.name());
+                let display_label = format!("🖩 {}", self.display_name());
+                let checked = self.check_types_in(&app.type_context());
+                let error_color = ui.visuals().error_fg_color;
+                let header = match &checked {
+                    Ok(Some(value_type)) => {
+                        egui::RichText::new(format!("{} : {}", display_label, value_type))
+                    }
+                    Ok(None) => egui::RichText::new(&display_label),
+                    Err(_) => {
+                        egui::RichText::new(format!("{} ⚠", display_label)).color(error_color)
+                    }
+                };
+                let operand_labels = self.operand_labels();
+                let response = unique_collapsing(unique_id, &label, header, ui).show(ui, |ui| {
+                    if let Err(error) = &checked {
+                        ui.colored_label(error_color, error);
+                    }
+                    if let ExprOperatorEditor::Swizzle(_, components) = self {
+                        ui.horizontal(|ui| {
+                            ui.label("components");
+                            ui.add(
+                                egui::TextEdit::singleline(components)
+                                    .font(egui::TextStyle::Monospace)
+                                    .desired_width(40.0),
+                            );
+                        });
+                    }
+                    for (i, operand) in self.operands_mut().into_iter().enumerate() {
+                        if let Some(operand_label) = operand_labels.get(i) {
+                            ui.label(*operand_label);
+                        }
+                        operand.draw_ui(app, ui, i as u64 + 1, FieldRange::ANY);
+                    }
+                });
+                if let Err(error) = checked {
+                    response.header_response.on_hover_text(error);
+                }
+            }
         }
     }
 
+    /// Operators offered by the "+" menu, grouped into submenus.
+    pub(crate) fn menu_groups() -> Vec<(&'static str, Vec<ExprOperatorEditor>)> {
+        use ExprOperatorEditor as Op;
+        use ExprWriterEditor::Placeholder as P;
+        vec![
+            (
+                "Arithmetic",
+                vec![
+                    Op::Add(P, P),
+                    Op::Subtract(P, P),
+                    Op::Multiply(P, P),
+                    Op::Div(P, P),
+                    Op::Rem(P, P),
+                    Op::Neg(P),
+                    Op::Abs(P),
+                    Op::Sign(P),
+                    Op::Min(P, P),
+                    Op::Max(P, P),
+                    Op::Clamp(P, P, P),
+                    Op::Mix(P, P, P),
+                ],
+            ),
+            (
+                "Rounding",
+                vec![
+                    Op::Floor(P),
+                    Op::Ceil(P),
+                    Op::Fract(P),
+                    Op::Step(P, P),
+                    Op::Smoothstep(P, P, P),
+                ],
+            ),
+            (
+                "Exponential",
+                vec![Op::Pow(P, P), Op::Sqrt(P), Op::Exp(P), Op::Log(P)],
+            ),
+            (
+                "Trigonometry",
+                vec![Op::Sin(P), Op::Cos(P), Op::Tan(P), Op::Atan2(P, P)],
+            ),
+            (
+                "Vector",
+                vec![
+                    Op::Vec2(P, P),
+                    Op::Vec3(P, P, P),
+                    Op::Vec4(P, P, P, P),
+                    Op::Vec4XyzW(P, P),
+                    Op::Dot(P, P),
+                    Op::Cross(P, P),
+                    Op::Length(P),
+                    Op::Distance(P, P),
+                    Op::Normalized(P),
+                    Op::Pack4x8UNorm(P),
+                ],
+            ),
+            (
+                "Logic",
+                vec![
+                    Op::Lt(P, P),
+                    Op::Le(P, P),
+                    Op::Gt(P, P),
+                    Op::Ge(P, P),
+                    Op::Eq(P, P),
+                    Op::Neq(P, P),
+                    Op::And(P, P),
+                    Op::Or(P, P),
+                    Op::Not(P),
+                    Op::All(P),
+                    Op::Any(P),
+                    Op::Select(P, P, P),
+                ],
+            ),
+            (
+                "Components",
+                ["x", "y", "z", "w", "xy", "xz", "xyz"]
+                    .into_iter()
+                    .map(|c| Op::Swizzle(P, c.to_string()))
+                    .collect(),
+            ),
+            ("Conversion", vec![Op::AsF32(P), Op::AsU32(P), Op::AsI32(P)]),
+            ("Random", vec![Op::Uniform(P, P)]),
+            (
+                "Attributes",
+                vec![Op::Attr(Attribute::ID), Op::ParentAttr(Attribute::ID)],
+            ),
+        ]
+    }
+
     pub fn menu_ui(ui: &mut egui::Ui) -> Option<Box<ExprOperatorEditor>> {
-        if ui.button("🖩 Uniform").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Uniform(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Multiply").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Multiply(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Subtract").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Subtract(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Add").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Add(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Sin").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Sin(
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Cos").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Cos(
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Distance").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Distance(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Vec3").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Vec3(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Normalized").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Normalized(
-                ExprWriterEditor::Placeholder,
-            )));
-        }
-        if ui.button("🖩 Vec4").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Vec4(
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-                ExprWriterEditor::Placeholder,
-            )));
+        let mut result = None;
+        for (group, operators) in Self::menu_groups() {
+            ui.menu_button(group, |ui| {
+                for operator in operators {
+                    if ui
+                        .button(format!("🖩 {}", operator.display_name()))
+                        .clicked()
+                    {
+                        result = Some(Box::new(operator));
+                        ui.close_menu();
+                    }
+                }
+            });
         }
-        if ui.button("🖩 Pack4x8UNorm").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Pack4x8UNorm(
-                ExprWriterEditor::Placeholder,
-            )));
+        result
+    }
+}
+
+/// `a == b` for scalars, or all components equal for vectors.
+fn produce_eq(
+    writer: &EffectWriter,
+    lit1: &ExprWriterEditor,
+    lit2: &ExprWriterEditor,
+) -> WriterExpr {
+    let a = lit1.produce(writer);
+    let b = lit2.produce(writer);
+    a.clone().ge(b.clone()).all().vec2(a.le(b).all()).all()
+}
+
+/// Negates a scalar boolean.
+fn produce_not(writer: &EffectWriter, expr: WriterExpr) -> WriterExpr {
+    expr.cast(ScalarType::Float).lt(writer.lit(0.5))
+}
+
+/// Builds a swizzle from single component accesses, as Hanabi only offers
+/// `x()` to `w()`. Invalid swizzles produce `0.0`.
+fn produce_swizzle(writer: &EffectWriter, expr: WriterExpr, components: &str) -> WriterExpr {
+    let picked: Option<Vec<WriterExpr>> = components
+        .chars()
+        .map(|c| match c {
+            'x' => Some(expr.clone().x()),
+            'y' => Some(expr.clone().y()),
+            'z' => Some(expr.clone().z()),
+            'w' => Some(expr.clone().w()),
+            _ => None,
+        })
+        .collect();
+    match picked.as_deref() {
+        Some([x]) => x.clone(),
+        Some([x, y]) => x.clone().vec2(y.clone()),
+        Some([x, y, z]) => x.clone().vec3(y.clone(), z.clone()),
+        Some([x, y, z, w]) => x.clone().vec3(y.clone(), z.clone()).vec4_xyz_w(w.clone()),
+        _ => {
+            warn!("Invalid swizzle .{}", components);
+            writer.lit(0.0)
         }
-        if ui.button("🖩 Attr").clicked() {
-            return Some(Box::new(ExprOperatorEditor::Attr(Attribute::ID)));
+    }
+}
+
+/// Largest literal exponent [`produce_pow`] expands into multiplications.
+const MAX_EXPANDED_EXPONENT: u32 = 16;
+
+/// The exponent of a `Pow` as an integer, when it is a whole literal small
+/// enough to be expanded into multiplications.
+pub(crate) fn integer_exponent(exponent: &ExprWriterEditor) -> Option<i32> {
+    match exponent {
+        ExprWriterEditor::Float(e)
+            if e.fract() == 0.0 && (1.0..=MAX_EXPANDED_EXPONENT as f32).contains(&e.abs()) =>
+        {
+            Some(*e as i32)
         }
-        if ui.button("🖩 Parent Attr").clicked() {
-            return Some(Box::new(ExprOperatorEditor::ParentAttr(Attribute::ID)));
+        _ => None,
+    }
+}
+
+/// Hanabi has no power operator, so whole literal exponents are expanded
+/// into multiplications, which are exact for negative bases, and others use
+/// `exp(e * log(abs(b)))`. The sign of the base is put back for odd
+/// exponents, as `fract(e / 2) * 2` is 1 for them and 0 for even ones.
+fn produce_pow(
+    writer: &EffectWriter,
+    base: &ExprWriterEditor,
+    exponent: &ExprWriterEditor,
+) -> WriterExpr {
+    let b = base.produce(writer);
+    if let Some(n) = integer_exponent(exponent) {
+        // Square and multiply, so that `b` is produced once.
+        let (mut result, mut square, mut rest) = (None::<WriterExpr>, b, n.unsigned_abs());
+        loop {
+            if rest & 1 == 1 {
+                result = Some(match result {
+                    Some(r) => r.mul(square.clone()),
+                    None => square.clone(),
+                });
+            }
+            rest >>= 1;
+            if rest == 0 {
+                break;
+            }
+            square = square.clone().mul(square);
         }
-        None
+        let result = result.unwrap();
+        return if n < 0 {
+            writer.lit(1.0).div(result)
+        } else {
+            result
+        };
     }
+    let e = exponent.produce(writer);
+    let odd = e.clone().mul(writer.lit(0.5)).fract().mul(writer.lit(2.0));
+    let sign = writer
+        .lit(1.0)
+        .add(b.clone().sign().sub(writer.lit(1.0)).mul(odd));
+    e.mul(b.abs().log()).exp().mul(sign)
+}
+
+/// Converts the elements of a scalar or vector, keeping its shape. The shape
+/// must be known to write the conversion, so an operand of unknown type
+/// produces `0` instead.
+fn produce_cast(writer: &EffectWriter, lit: &ExprWriterEditor, elem: ScalarType) -> WriterExpr {
+    let Some(value_type) = lit.value_type_in(&writer.type_context()) else {
+        warn!("Cannot convert {} of unknown type", lit);
+        return match elem {
+            ScalarType::Uint => writer.lit(0u32),
+            ScalarType::Int => writer.lit(0i32),
+            _ => writer.lit(0.0),
+        };
+    };
+    lit.produce(writer).cast(with_elem_type(value_type, elem))
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -304,235 +896,416 @@ pub enum ExprWriterEditor {
     RandU32,
     RandFloat,
     RandVec3,
-    Operator(Box<ExprOperatorEditor>),
+    /// A random value in a range with constant bounds.
+    RandomRange(RandomRange),
+    /// An operator and its id, which files written before node ids existed
+    /// do not have.
+    Operator(Box<ExprOperatorEditor>, #[serde(default)] NodeId),
     Float(f32),
     U32(u32),
+    Bool(bool),
+    Vec2(Vec2),
     Vec3(Vec3),
     Vec4(Vec4),
+    /// An HDR colour: a linear RGBA colour in `0.0..=1.0` and the intensity
+    /// its RGB is multiplied by.
+    Color(Vec4, f32),
     Time,
     Age,
+    /// Simulation time since the last update.
+    DeltaTime,
+    /// Time since startup, unaffected by pausing or scaling the simulation.
+    RealTime,
+    RealDeltaTime,
+    /// Unique id of the particle, see [`Attribute::ID`].
+    ParticleId,
+    /// Value of an effect property, by name.
+    Property(String),
+    /// A project-level definition, by name.
+    Ref(String),
 }
 
 impl ExprWriterEditor {
-    pub fn produce(&self, writer: &ExprWriter) -> WriterExpr {
+    /// An operator node with a new id.
+    pub fn operator(o: ExprOperatorEditor) -> Self {
+        ExprWriterEditor::Operator(Box::new(o), NodeId::new())
+    }
+
+    /// Id of the node, if it is an operator. Other nodes are identified by
+    /// their place in the parent operator.
+    pub fn node_id(&self) -> Option<NodeId> {
         match self {
-            ExprWriterEditor::Operator(o) => o.produce(writer),
+            ExprWriterEditor::Operator(_, id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Calls `f` with the id of every operator node of the tree.
+    pub fn for_each_id_mut(&mut self, f: &mut impl FnMut(&mut NodeId)) {
+        if let ExprWriterEditor::Operator(o, id) = self {
+            f(id);
+            for operand in o.operands_mut() {
+                operand.for_each_id_mut(f);
+            }
+        }
+    }
+
+    /// Gives every operator node of the tree a new id, for copies of a tree
+    /// that end up next to the original.
+    pub fn renew_ids(&mut self) {
+        self.for_each_id_mut(&mut |id| *id = NodeId::new());
+    }
+
+    pub fn produce(&self, writer: &EffectWriter) -> WriterExpr {
+        writer.produce(self)
+    }
+
+    fn produce_node(&self, writer: &EffectWriter) -> WriterExpr {
+        match self {
+            ExprWriterEditor::Operator(o, _) => o.produce(writer),
             ExprWriterEditor::RandU32 => writer.rand(ValueType::Scalar(ScalarType::Uint)),
             ExprWriterEditor::RandFloat => writer.rand(ValueType::Scalar(ScalarType::Float)),
             ExprWriterEditor::RandVec3 => writer.rand(ValueType::Vector(VectorType::VEC3F)),
+            ExprWriterEditor::RandomRange(range) => range.produce(writer),
             ExprWriterEditor::Float(f) => writer.lit(*f),
             ExprWriterEditor::U32(f) => writer.lit(*f),
+            ExprWriterEditor::Bool(b) => writer.lit(*b),
+            ExprWriterEditor::Vec2(v) => writer.lit(*v),
             ExprWriterEditor::Vec3(v) => writer.lit(*v),
             ExprWriterEditor::Vec4(v) => writer.lit(*v),
+            ExprWriterEditor::Color(color, intensity) => {
+                writer.lit(apply_intensity(*color, *intensity))
+            }
             ExprWriterEditor::Time => writer.time(),
             ExprWriterEditor::Placeholder => writer.lit(0.0),
             ExprWriterEditor::Age => writer.attr(Attribute::AGE),
+            ExprWriterEditor::DeltaTime => writer.delta_time(),
+            ExprWriterEditor::RealTime => {
+                writer.push(Expr::BuiltIn(BuiltInExpr::new(BuiltInOperator::RealTime)))
+            }
+            ExprWriterEditor::RealDeltaTime => writer.push(Expr::BuiltIn(BuiltInExpr::new(
+                BuiltInOperator::RealDeltaTime,
+            ))),
+            ExprWriterEditor::ParticleId => writer.attr(Attribute::ID),
+            ExprWriterEditor::Property(name) => match writer.property(name) {
+                Some(handle) => writer.prop(handle),
+                None => {
+                    warn!("Unknown property {}", name);
+                    writer.lit(0.0)
+                }
+            },
+            ExprWriterEditor::Ref(name) => writer.inline_definition(name),
         }
     }
-    pub fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        let c = self.clone();
+    /// Best-effort type of the value this expression produces.
+    pub fn value_type(&self) -> Option<ValueType> {
         match self {
-            ExprWriterEditor::Operator(o) => {
-                if let Some(v) = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("", ui) {
-                        ExprControl::Delete => Some(ExprWriterEditor::Placeholder),
-                        ExprControl::Noop => {
-                            o.draw_ui(app, ui, index);
-                            None
-                        }
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            None
-                        }
-                    })
-                    .inner
-                {
-                    *self = v;
-                }
+            ExprWriterEditor::Placeholder
+            | ExprWriterEditor::Property(_)
+            | ExprWriterEditor::Ref(_) => None,
+            ExprWriterEditor::Operator(o, _) => o.value_type(),
+            ExprWriterEditor::RandomRange(range) => Some(range.value_type()),
+            ExprWriterEditor::RandU32 | ExprWriterEditor::U32(_) | ExprWriterEditor::ParticleId => {
+                Some(ValueType::Scalar(ScalarType::Uint))
             }
-            ExprWriterEditor::Age => {
-                if let Some(v) = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Age", ui) {
-                        ExprControl::Delete => Some(ExprWriterEditor::Placeholder),
-                        ExprControl::Noop => None,
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            None
-                        }
-                    })
-                    .inner
-                {
-                    *self = v;
-                }
+            ExprWriterEditor::RandFloat
+            | ExprWriterEditor::Float(_)
+            | ExprWriterEditor::Time
+            | ExprWriterEditor::DeltaTime
+            | ExprWriterEditor::RealTime
+            | ExprWriterEditor::RealDeltaTime
+            | ExprWriterEditor::Age => Some(ValueType::Scalar(ScalarType::Float)),
+            ExprWriterEditor::RandVec3 | ExprWriterEditor::Vec3(_) => {
+                Some(ValueType::Vector(VectorType::VEC3F))
             }
-            ExprWriterEditor::RandU32 => {
-                if let Some(v) = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Rand U32", ui) {
-                        ExprControl::Delete => Some(ExprWriterEditor::Placeholder),
-                        ExprControl::Noop => None,
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            None
-                        }
-                    })
-                    .inner
-                {
-                    *self = v;
-                }
+            ExprWriterEditor::Vec2(_) => Some(ValueType::Vector(VectorType::VEC2F)),
+            ExprWriterEditor::Vec4(_) | ExprWriterEditor::Color(..) => {
+                Some(ValueType::Vector(VectorType::VEC4F))
             }
-            ExprWriterEditor::RandFloat => {
-                if let Some(v) = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Rand Float", ui) {
-                        ExprControl::Delete => Some(ExprWriterEditor::Placeholder),
-                        ExprControl::Noop => None,
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            None
-                        }
-                    })
-                    .inner
-                {
-                    *self = v;
-                }
+            ExprWriterEditor::Bool(_) => Some(ValueType::Scalar(ScalarType::Bool)),
+        }
+    }
+
+    /// Label shown next to the node tools in tree view.
+    pub(crate) fn ui_label(&self) -> &'static str {
+        match self {
+            ExprWriterEditor::Placeholder | ExprWriterEditor::Operator(..) => "",
+            ExprWriterEditor::Age => "Age",
+            ExprWriterEditor::RandU32 => "Rand U32",
+            ExprWriterEditor::RandFloat => "Rand Float",
+            ExprWriterEditor::RandVec3 => "Rand Vec3",
+            ExprWriterEditor::RandomRange(range) => range.label(),
+            ExprWriterEditor::Float(_) => "Float",
+            ExprWriterEditor::U32(_) => "U32",
+            ExprWriterEditor::Bool(_) => "Bool",
+            ExprWriterEditor::Vec2(_) => "Vec2",
+            ExprWriterEditor::Vec3(_) => "Vec3",
+            ExprWriterEditor::Vec4(_) => "Vec4",
+            ExprWriterEditor::Color(..) => "Color",
+            ExprWriterEditor::Time => "Time",
+            ExprWriterEditor::DeltaTime => "Delta Time",
+            ExprWriterEditor::RealTime => "Real Time",
+            ExprWriterEditor::RealDeltaTime => "Real Delta Time",
+            ExprWriterEditor::ParticleId => "Particle ID",
+            ExprWriterEditor::Property(_) => "Property",
+            ExprWriterEditor::Ref(_) => "Ref",
+        }
+    }
+
+    /// Draws the expression filling a slot whose literals take values in
+    /// `range`.
+    pub fn draw_ui(
+        &mut self,
+        app: &mut AppContext,
+        ui: &mut egui::Ui,
+        index: u64,
+        range: FieldRange,
+    ) {
+        let text_id = ui.make_persistent_id(("expr text", index));
+        let preview_id = ui.make_persistent_id(("expr preview", index));
+        let graph_id = ui.make_persistent_id(("expr graph", index));
+        if let Some(state) = ui.data(|d| d.get_temp::<ExprTextState>(text_id)) {
+            self.draw_text_ui(ui, text_id, state);
+            return;
+        }
+        if ui.data(|d| d.get_temp::<bool>(graph_id)).unwrap_or(false) {
+            if ui.button("🌲").on_hover_text("Back to tree view").clicked() {
+                ui.data_mut(|d| d.remove::<bool>(graph_id));
             }
-            ExprWriterEditor::RandVec3 => {
-                if let Some(v) = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Rand Vec3", ui) {
-                        ExprControl::Delete => Some(ExprWriterEditor::Placeholder),
-                        ExprControl::Noop => None,
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            None
-                        }
-                    })
-                    .inner
-                {
-                    *self = v;
+            ui_for_expr_graph(self, app, ui, graph_id, range);
+            return;
+        }
+        if let ExprWriterEditor::Placeholder = self {
+            self.draw_placeholder_ui(app, ui, text_id);
+            return;
+        }
+
+        let control = ui
+            .horizontal(|ui| {
+                let control = ui_tools_for_expr_writer(self.ui_label(), ui);
+                if !matches!(control, ExprControl::Delete) {
+                    if let ExprWriterEditor::Operator(o, id) = self {
+                        o.draw_ui(app, ui, id.value());
+                    } else {
+                        self.draw_value_ui(app, ui, index, range);
+                    }
                 }
+                control
+            })
+            .inner;
+        match control {
+            ExprControl::Delete => *self = ExprWriterEditor::Placeholder,
+            ExprControl::SaveSnippet => app.snippets.save(self.clone()),
+            ExprControl::EditText => {
+                let state = ExprTextState {
+                    text: self.to_string(),
+                    error: None,
+                };
+                ui.data_mut(|d| d.insert_temp(text_id, state));
             }
-            ExprWriterEditor::Float(f) => {
-                *self = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Float", ui) {
-                        ExprControl::Delete => ExprWriterEditor::Placeholder,
-                        ExprControl::Noop => ExprWriterEditor::Float(ui_for_f32(ui, *f)),
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            ExprWriterEditor::Float(ui_for_f32(ui, *f))
-                        }
-                    })
-                    .inner;
-            }
-            ExprWriterEditor::U32(f) => {
-                *self = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("U32", ui) {
-                        ExprControl::Delete => ExprWriterEditor::Placeholder,
-                        ExprControl::Noop => {
-                            ExprWriterEditor::U32(ui_for_u32_ex(ui, *f, 0, 10000, 1))
-                        }
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            ExprWriterEditor::U32(ui_for_u32_ex(ui, *f, 0, 10000, 1))
-                        }
-                    })
-                    .inner;
-            }
-            ExprWriterEditor::Vec3(result) => {
-                *self = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Vec3", ui) {
-                        ExprControl::Delete => ExprWriterEditor::Placeholder,
-                        ExprControl::Noop => ExprWriterEditor::Vec3(ui_for_vec3(ui, *result)),
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            ExprWriterEditor::Vec3(ui_for_vec3(ui, *result))
-                        }
-                    })
-                    .inner;
-            }
-            ExprWriterEditor::Vec4(result) => {
-                *self = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Vec4", ui) {
-                        ExprControl::Delete => ExprWriterEditor::Placeholder,
-                        ExprControl::Noop => ExprWriterEditor::Vec4(ui_for_vec4(ui, *result)),
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            ExprWriterEditor::Vec4(ui_for_vec4(ui, *result))
+            ExprControl::EditGraph => ui.data_mut(|d| d.insert_temp(graph_id, true)),
+            ExprControl::TogglePreview => {
+                ui.data_mut(|d| {
+                    let shown = d.get_temp_mut_or_default::<bool>(preview_id);
+                    *shown = !*shown;
+                });
+            }
+            ExprControl::Noop => {}
+        }
+        if ui.data(|d| d.get_temp::<bool>(preview_id)).unwrap_or(false) {
+            ui_for_expr_preview(ui, self, &app.eval_inputs);
+        }
+    }
+
+    /// Widgets editing the value of a leaf, if it has one.
+    pub(crate) fn draw_value_ui(
+        &mut self,
+        app: &AppContext,
+        ui: &mut egui::Ui,
+        index: u64,
+        range: FieldRange,
+    ) {
+        match self {
+            ExprWriterEditor::Float(f) => *f = ui_for_f32(ui, *f, range),
+            ExprWriterEditor::U32(v) => *v = ui_for_u32_ex(ui, *v, 0, 10000, 1),
+            ExprWriterEditor::Bool(b) => {
+                ui.checkbox(b, "");
+            }
+            ExprWriterEditor::Vec2(v) => *v = ui_for_vec2(ui, *v, range),
+            ExprWriterEditor::Vec3(v) => *v = ui_for_vec3(ui, *v, range),
+            ExprWriterEditor::Vec4(v) => *v = ui_for_vec4(ui, *v, range),
+            ExprWriterEditor::Color(color, intensity) => {
+                (*color, *intensity) =
+                    ui_for_hdr_color(ui, *color, *intensity, FieldRange::INTENSITY);
+            }
+            ExprWriterEditor::RandomRange(random) => random.draw_ui(ui, index, range),
+            ExprWriterEditor::Property(name) => {
+                egui::ComboBox::from_id_salt(("property", index))
+                    .selected_text(name.as_str())
+                    .show_ui(ui, |ui| {
+                        for property in app.properties.iter() {
+                            ui.selectable_value(name, property.name.clone(), &property.name);
                         }
-                    })
-                    .inner;
-            }
-            ExprWriterEditor::Time => {
-                *self = ui
-                    .horizontal(|ui| match ui_tools_for_expr_writer("Time", ui) {
-                        ExprControl::Delete => ExprWriterEditor::Placeholder,
-                        ExprControl::Noop => ExprWriterEditor::Time,
-                        ExprControl::Copy => {
-                            app.expr_clipboard = Some(c);
-                            ExprWriterEditor::Time
+                    });
+            }
+            ExprWriterEditor::Ref(name) => {
+                egui::ComboBox::from_id_salt(("ref", index))
+                    .selected_text(name.as_str())
+                    .show_ui(ui, |ui| {
+                        for definition in app.definition_names.iter() {
+                            ui.selectable_value(name, definition.clone(), definition);
                         }
-                    })
-                    .inner;
+                    });
             }
+            _ => {}
+        }
+    }
 
-            ExprWriterEditor::Placeholder => {
-                ui.menu_button("+", |ui| {
-                    ui.menu_button("Operator", |ui| {
-                        if let Some(op) = ExprOperatorEditor::menu_ui(ui) {
-                            *self = ExprWriterEditor::Operator(op);
+    /// One-line text view of the expression. The text is parsed when the
+    /// field loses focus, and replaces the expression if it is valid.
+    fn draw_text_ui(&mut self, ui: &mut egui::Ui, text_id: egui::Id, mut state: ExprTextState) {
+        let mut close = false;
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                let apply = ui
+                    .button("🌲")
+                    .on_hover_text("Apply and go back to tree view")
+                    .clicked();
+                if ui.button("↺").on_hover_text("Discard the text").clicked() {
+                    close = true;
+                    return;
+                }
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut state.text)
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(320.0),
+                );
+                if apply || response.lost_focus() {
+                    match parse_expr(&state.text) {
+                        Ok(expr) => {
+                            *self = expr;
+                            state.text = self.to_string();
+                            state.error = None;
+                            close = apply;
                         }
-                    });
-                    if ui.button("Age").clicked() {
-                        *self = ExprWriterEditor::Age;
-                    }
-                    if ui.button("RandFloat").clicked() {
-                        *self = ExprWriterEditor::RandFloat;
-                    }
-                    if ui.button("RandU32").clicked() {
-                        *self = ExprWriterEditor::RandU32;
-                    }
-                    if ui.button("RandVec3").clicked() {
-                        *self = ExprWriterEditor::RandVec3;
-                    }
-                    if ui.button("Time").clicked() {
-                        *self = ExprWriterEditor::Time;
-                    }
-                    if ui.button("Float").clicked() {
-                        *self = ExprWriterEditor::Float(0.0);
-                    }
-                    if ui.button("U32").clicked() {
-                        *self = ExprWriterEditor::U32(0);
+                        Err(error) => state.error = Some(error),
                     }
-                    if ui.button("Vec3").clicked() {
-                        *self = ExprWriterEditor::Vec3(Vec3::ZERO);
+                }
+            });
+            if let Some(error) = &state.error {
+                ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", error));
+            }
+        });
+        ui.data_mut(|d| {
+            if close {
+                d.remove::<ExprTextState>(text_id);
+            } else {
+                d.insert_temp(text_id, state);
+            }
+        });
+    }
+
+    fn draw_placeholder_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, text_id: egui::Id) {
+        ui.menu_button("+", |ui| {
+            ui.menu_button("Operator", |ui| {
+                if let Some(op) = ExprOperatorEditor::menu_ui(ui) {
+                    *self = ExprWriterEditor::Operator(op, NodeId::new());
+                }
+            });
+            if ui.button("Age").clicked() {
+                *self = ExprWriterEditor::Age;
+            }
+            if ui.button("RandFloat").clicked() {
+                *self = ExprWriterEditor::RandFloat;
+            }
+            if ui.button("RandU32").clicked() {
+                *self = ExprWriterEditor::RandU32;
+            }
+            if ui.button("RandVec3").clicked() {
+                *self = ExprWriterEditor::RandVec3;
+            }
+            ui.menu_button("Random range", |ui| {
+                for range in RandomRange::menu_items() {
+                    if ui.button(range.label()).clicked() {
+                        *self = ExprWriterEditor::RandomRange(range);
+                        ui.close_menu();
                     }
-                    if ui.button("Vec4").clicked() {
-                        *self = ExprWriterEditor::Vec4(Vec4::ZERO);
+                }
+            });
+            if ui.button("Time").clicked() {
+                *self = ExprWriterEditor::Time;
+            }
+            ui.menu_button("Built-in", |ui| {
+                for leaf in [
+                    ExprWriterEditor::DeltaTime,
+                    ExprWriterEditor::RealTime,
+                    ExprWriterEditor::RealDeltaTime,
+                    ExprWriterEditor::ParticleId,
+                ] {
+                    if ui.button(leaf.ui_label()).clicked() {
+                        *self = leaf;
+                        ui.close_menu();
                     }
-                    ui.separator();
-                    ui.menu_button("Prebuilt", |ui| {
-                        if ui.button("Random Normalized Vector").clicked() {
-                            *self = ExprWriterEditor::Operator(Box::new(
-                                ExprOperatorEditor::Normalized(ExprWriterEditor::Operator(
-                                    Box::new(ExprOperatorEditor::Subtract(
-                                        ExprWriterEditor::Operator(Box::new(
-                                            ExprOperatorEditor::Multiply(
-                                                ExprWriterEditor::RandVec3,
-                                                ExprWriterEditor::Float(2.0),
-                                            ),
-                                        )),
-                                        ExprWriterEditor::Float(1.0),
-                                    )),
-                                )),
-                            ))
+                }
+            });
+            if ui.button("Float").clicked() {
+                *self = ExprWriterEditor::Float(0.0);
+            }
+            if ui.button("U32").clicked() {
+                *self = ExprWriterEditor::U32(0);
+            }
+            if ui.button("Bool").clicked() {
+                *self = ExprWriterEditor::Bool(false);
+            }
+            if ui.button("Vec2").clicked() {
+                *self = ExprWriterEditor::Vec2(Vec2::ZERO);
+            }
+            if ui.button("Vec3").clicked() {
+                *self = ExprWriterEditor::Vec3(Vec3::ZERO);
+            }
+            if ui.button("Vec4").clicked() {
+                *self = ExprWriterEditor::Vec4(Vec4::ZERO);
+            }
+            if ui.button("Color").clicked() {
+                *self = ExprWriterEditor::Color(Vec4::ONE, 1.0);
+            }
+            if !app.definition_names.is_empty() {
+                ui.menu_button("Definition", |ui| {
+                    for definition in app.definition_names.iter() {
+                        if ui.button(definition).clicked() {
+                            *self = ExprWriterEditor::Ref(definition.clone());
+                            ui.close_menu();
                         }
-                    });
-                    if app.expr_clipboard.is_some() {
-                        ui.separator();
-                        if ui.button("From clipboard").clicked() {
-                            *self = app.expr_clipboard.clone().unwrap().clone();
+                    }
+                });
+            }
+            if !app.properties.is_empty() {
+                ui.menu_button("Property", |ui| {
+                    for property in app.properties.iter() {
+                        if ui.button(&property.name).clicked() {
+                            *self = ExprWriterEditor::Property(property.name.clone());
+                            ui.close_menu();
                         }
                     }
                 });
             }
-        }
+            ui.separator();
+            ui.menu_button("Snippet", |ui| {
+                if let Some(expr) = app.snippets.menu_ui(ui) {
+                    *self = expr;
+                }
+            });
+            if ui.button("Text…").clicked() {
+                ui.data_mut(|d| d.insert_temp(text_id, ExprTextState::default()));
+                ui.close_menu();
+            }
+        });
     }
 }
+
+/// Text being edited in the text view of an expression.
+#[derive(Clone, Default)]
+struct ExprTextState {
+    text: String,
+    error: Option<ParseError>,
+}
@@ -0,0 +1,525 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_egui::*;
+use bevy_hanabi::prelude::*;
+
+use crate::editor_prelude::AppContext;
+use crate::expr::{ALL_ATTRS, ExprOperatorEditor, ExprWriterEditor, attr_to_label};
+use crate::helpers::FieldRange;
+use crate::ids::NodeId;
+use crate::syntax::parse_expr;
+use crate::typecheck::elem_type;
+
+/// Position of a node in an expression tree: the operand indices leading to
+/// it from the root.
+pub type NodePath = Vec<usize>;
+
+pub fn node_at<'a>(expr: &'a ExprWriterEditor, path: &[usize]) -> Option<&'a ExprWriterEditor> {
+    match path.split_first() {
+        None => Some(expr),
+        Some((index, rest)) => match expr {
+            ExprWriterEditor::Operator(o, _) => o
+                .operands()
+                .get(*index)
+                .copied()
+                .and_then(|operand| node_at(operand, rest)),
+            _ => None,
+        },
+    }
+}
+
+pub fn node_at_mut<'a>(
+    expr: &'a mut ExprWriterEditor,
+    path: &[usize],
+) -> Option<&'a mut ExprWriterEditor> {
+    match path.split_first() {
+        None => Some(expr),
+        Some((index, rest)) => match expr {
+            ExprWriterEditor::Operator(o, _) => o
+                .operands_mut()
+                .into_iter()
+                .nth(*index)
+                .and_then(|operand| node_at_mut(operand, rest)),
+            _ => None,
+        },
+    }
+}
+
+/// Moves the subtree at `source` into the input `target`, leaving an empty
+/// slot behind, which is what connecting the output of `source` to `target`
+/// does in the graph. The subtree previously at `target` is dropped. Returns
+/// `false`, leaving the tree untouched, when a path does not exist or the
+/// target is inside the source.
+pub fn move_subtree(expr: &mut ExprWriterEditor, source: &[usize], target: &[usize]) -> bool {
+    if target.starts_with(source) {
+        return false;
+    }
+    if node_at(expr, source).is_none() || node_at(expr, target).is_none() {
+        return false;
+    }
+    let taken = std::mem::replace(
+        node_at_mut(expr, source).unwrap(),
+        ExprWriterEditor::Placeholder,
+    );
+    *node_at_mut(expr, target).unwrap() = taken;
+    true
+}
+
+/// Identity of a node in the view state. Operators are keyed on their id and
+/// other nodes on the operand they fill, so that the state follows subtrees
+/// when the graph is rewired.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum NodeKey {
+    Operator(NodeId),
+    /// Operand `index` of the operator with the id, or the root if `None`.
+    Operand(Option<NodeId>, usize),
+}
+
+/// A node placed by the automatic layout, in columns by depth with the root
+/// on the right and rows by leaf order.
+struct LayoutNode {
+    path: NodePath,
+    key: NodeKey,
+    depth: usize,
+    row: f32,
+    /// Range of the literals of the node, the slot's for the root.
+    range: FieldRange,
+}
+
+fn layout(expr: &ExprWriterEditor, collapsed: &HashSet<NodeId>) -> Vec<LayoutNode> {
+    fn visit(
+        expr: &ExprWriterEditor,
+        path: NodePath,
+        key: NodeKey,
+        depth: usize,
+        collapsed: &HashSet<NodeId>,
+        next_row: &mut f32,
+        nodes: &mut Vec<LayoutNode>,
+    ) -> f32 {
+        let mut rows = Vec::new();
+        if let ExprWriterEditor::Operator(o, id) = expr
+            && !collapsed.contains(id)
+        {
+            for (index, operand) in o.operands().into_iter().enumerate() {
+                let mut operand_path = path.clone();
+                operand_path.push(index);
+                let operand_key = match operand.node_id() {
+                    Some(operand_id) => NodeKey::Operator(operand_id),
+                    None => NodeKey::Operand(Some(*id), index),
+                };
+                rows.push(visit(
+                    operand,
+                    operand_path,
+                    operand_key,
+                    depth + 1,
+                    collapsed,
+                    next_row,
+                    nodes,
+                ));
+            }
+        }
+        let row = if rows.is_empty() {
+            *next_row += 1.0;
+            *next_row - 1.0
+        } else {
+            rows.iter().sum::<f32>() / rows.len() as f32
+        };
+        nodes.push(LayoutNode {
+            path,
+            key,
+            depth,
+            row,
+            range: FieldRange::ANY,
+        });
+        row
+    }
+
+    let key = match expr.node_id() {
+        Some(id) => NodeKey::Operator(id),
+        None => NodeKey::Operand(None, 0),
+    };
+    let mut nodes = Vec::new();
+    visit(expr, Vec::new(), key, 0, collapsed, &mut 0.0, &mut nodes);
+    nodes
+}
+
+/// View state of a graph, kept in egui memory so that project files are not
+/// affected.
+#[derive(Clone, Default)]
+struct GraphState {
+    /// Offsets from the automatic layout of the nodes moved by hand.
+    offsets: HashMap<NodeKey, egui::Vec2>,
+    collapsed: HashSet<NodeId>,
+    selected: Option<NodeKey>,
+    /// Node whose output is being dragged to an input.
+    connecting: Option<NodePath>,
+}
+
+/// Port locations of the nodes drawn in this frame.
+#[derive(Default)]
+struct Ports {
+    /// Node, operand index, position and type of the operand.
+    inputs: Vec<(NodePath, usize, egui::Pos2, Option<ValueType>)>,
+    outputs: HashMap<NodePath, egui::Pos2>,
+}
+
+enum GraphAction {
+    Delete(NodePath),
+    Connect(NodePath, egui::Pos2),
+}
+
+const NODE_WIDTH: f32 = 200.0;
+const COLUMN_WIDTH: f32 = 250.0;
+const ROW_HEIGHT: f32 = 76.0;
+const MARGIN: f32 = 12.0;
+const PORT_RADIUS: f32 = 5.0;
+
+fn type_color(value_type: Option<ValueType>) -> egui::Color32 {
+    match value_type.map(elem_type) {
+        Some(ScalarType::Float) => egui::Color32::from_rgb(120, 200, 110),
+        Some(ScalarType::Uint) => egui::Color32::from_rgb(100, 150, 240),
+        Some(ScalarType::Int) => egui::Color32::from_rgb(90, 200, 210),
+        Some(ScalarType::Bool) => egui::Color32::from_rgb(230, 150, 70),
+        _ => egui::Color32::GRAY,
+    }
+}
+
+fn type_name(value_type: Option<ValueType>) -> String {
+    value_type.map_or("?".to_string(), |t| t.to_string())
+}
+
+fn wire(from: egui::Pos2, to: egui::Pos2, color: egui::Color32) -> egui::Shape {
+    let bend = egui::vec2(((to.x - from.x).abs() * 0.5).max(30.0), 0.0);
+    egui::epaint::CubicBezierShape::from_points_stroke(
+        [from, from + bend, to - bend, to],
+        false,
+        egui::Color32::TRANSPARENT,
+        egui::Stroke::new(2.0, color),
+    )
+    .into()
+}
+
+/// Draws one node and records its ports.
+fn draw_node(
+    ui: &mut egui::Ui,
+    node: &mut ExprWriterEditor,
+    layout_node: &LayoutNode,
+    app: &mut AppContext,
+    state: &mut GraphState,
+    ports: &mut Ports,
+    action: &mut Option<GraphAction>,
+) {
+    let (path, key) = (&layout_node.path, layout_node.key);
+    let collapsed = node
+        .node_id()
+        .is_some_and(|id| state.collapsed.contains(&id));
+    let error = match node {
+        ExprWriterEditor::Operator(o, _) => o.type_error_in(&app.type_context()),
+        _ => None,
+    };
+    let node_type = node.value_type_in(&app.type_context());
+    let visuals = ui.visuals();
+    let stroke = if error.is_some() {
+        egui::Stroke::new(1.5, visuals.error_fg_color)
+    } else if state.selected == Some(key) {
+        visuals.selection.stroke
+    } else {
+        visuals.widgets.noninteractive.bg_stroke
+    };
+    let frame = egui::Frame::group(ui.style())
+        .fill(visuals.window_fill)
+        .stroke(stroke);
+
+    let mut header_y = 0.0;
+    let mut input_rows = Vec::new();
+    let response = frame.show(ui, |ui| {
+        ui.set_max_width(NODE_WIDTH);
+        let header = ui.horizontal(|ui| {
+            let handle = ui
+                .add(egui::Label::new("☰").sense(egui::Sense::click_and_drag()))
+                .on_hover_text("Drag to move, click to select");
+            if handle.dragged() {
+                *state.offsets.entry(key).or_default() += handle.drag_delta();
+            }
+            if handle.clicked() {
+                state.selected = Some(key);
+            }
+            if let ExprWriterEditor::Placeholder = node {
+                ui.label("Empty");
+                return;
+            }
+            let delete_button = ui.small_button("❌").on_hover_text("Right-click to delete");
+            if delete_button.secondary_clicked() {
+                *action = Some(GraphAction::Delete(path.clone()));
+            }
+            if ui
+                .small_button("🗐")
+                .on_hover_text("Save as snippet")
+                .clicked()
+            {
+                app.snippets.save(node.clone());
+            }
+            if let ExprWriterEditor::Operator(o, id) = node
+                && !o.operands().is_empty()
+            {
+                let icon = if collapsed { "⏵" } else { "⏷" };
+                if ui.small_button(icon).on_hover_text("Collapse").clicked() {
+                    if collapsed {
+                        state.collapsed.remove(id);
+                    } else {
+                        state.collapsed.insert(*id);
+                    }
+                }
+            }
+            let title = match node {
+                ExprWriterEditor::Operator(o, _) => o.display_name(),
+                _ => node.ui_label().to_string(),
+            };
+            let label = match node_type {
+                Some(value_type) => format!("{} : {}", title, value_type),
+                None => title,
+            };
+            let label = ui.label(egui::RichText::new(label).strong());
+            if let Some(error) = &error {
+                label.on_hover_text(error);
+            }
+        });
+        header_y = header.response.rect.center().y;
+
+        match node {
+            ExprWriterEditor::Placeholder => node.draw_ui(app, ui, 0, layout_node.range),
+            ExprWriterEditor::Operator(..) if collapsed => {
+                let text: String = node.to_string().chars().take(40).collect();
+                ui.monospace(text).on_hover_text(node.to_string());
+            }
+            ExprWriterEditor::Operator(o, _) => {
+                let labels = o.operand_labels();
+                match o.as_mut() {
+                    ExprOperatorEditor::Attr(attr) | ExprOperatorEditor::ParentAttr(attr) => {
+                        egui::ComboBox::from_id_salt("attr")
+                            .selected_text(attr_to_label(*attr))
+                            .show_ui(ui, |ui| {
+                                for (value, label) in ALL_ATTRS.iter() {
+                                    ui.selectable_value(attr, *value, *label);
+                                }
+                            });
+                    }
+                    ExprOperatorEditor::Swizzle(_, components) => {
+                        ui.horizontal(|ui| {
+                            ui.label("components");
+                            ui.add(
+                                egui::TextEdit::singleline(components)
+                                    .font(egui::TextStyle::Monospace)
+                                    .desired_width(40.0),
+                            );
+                        });
+                    }
+                    _ => {}
+                }
+                for (index, operand) in o.operands().into_iter().enumerate() {
+                    let value_type = operand.value_type_in(&app.type_context());
+                    let label = labels
+                        .get(index)
+                        .map_or(format!("#{}", index + 1), |l| l.to_string());
+                    let row = ui.label(format!("{} : {}", label, type_name(value_type)));
+                    input_rows.push((index, row.rect.center().y, value_type));
+                }
+            }
+            _ => node.draw_value_ui(app, ui, path.len() as u64, layout_node.range),
+        }
+    });
+
+    let rect = response.response.rect;
+    let painter = ui.painter();
+    let port_stroke = egui::Stroke::new(1.0, ui.visuals().widgets.inactive.fg_stroke.color);
+    for (index, y, value_type) in input_rows {
+        let position = egui::pos2(rect.left(), y);
+        painter.circle(position, PORT_RADIUS, type_color(value_type), port_stroke);
+        ports
+            .inputs
+            .push((path.clone(), index, position, value_type));
+    }
+    // The root has nowhere to be connected to.
+    if !path.is_empty() {
+        let position = egui::pos2(rect.right(), header_y);
+        painter.circle(position, PORT_RADIUS, type_color(node_type), port_stroke);
+        ports.outputs.insert(path.clone(), position);
+        let port = ui
+            .interact(
+                egui::Rect::from_center_size(position, egui::Vec2::splat(PORT_RADIUS * 3.0)),
+                ui.id().with("output"),
+                egui::Sense::drag(),
+            )
+            .on_hover_text("Drag to an input to move this subtree there");
+        if port.drag_started() {
+            state.connecting = Some(path.clone());
+        }
+        if port.drag_stopped() {
+            if let Some(pointer) = ui.ctx().pointer_interact_pos() {
+                *action = Some(GraphAction::Connect(path.clone(), pointer));
+            }
+            state.connecting = None;
+        }
+    }
+}
+
+/// Copy, paste and delete shortcuts acting on the selected node.
+fn handle_keys(ui: &egui::Ui, expr: &mut ExprWriterEditor, selected: &[usize]) {
+    let events = ui.input(|i| i.events.clone());
+    for event in events {
+        match event {
+            egui::Event::Copy => {
+                if let Some(node) = node_at(expr, selected) {
+                    ui.ctx().copy_text(node.to_string());
+                }
+            }
+            egui::Event::Paste(text) => {
+                if let Ok(pasted) = parse_expr(&text)
+                    && let Some(node) = node_at_mut(expr, selected)
+                {
+                    *node = pasted;
+                }
+            }
+            egui::Event::Key {
+                key: egui::Key::Delete,
+                pressed: true,
+                ..
+            } => {
+                if let Some(node) = node_at_mut(expr, selected) {
+                    *node = ExprWriterEditor::Placeholder;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Node-graph view of an expression. Nodes can be moved, collapsed, and
+/// rewired by dragging an output onto an input, and the selected node is
+/// copied and pasted as text.
+pub fn ui_for_expr_graph(
+    expr: &mut ExprWriterEditor,
+    app: &mut AppContext,
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    range: FieldRange,
+) {
+    let state_id = id.with("state");
+    let mut state = ui
+        .data(|d| d.get_temp::<GraphState>(state_id))
+        .unwrap_or_default();
+    let mut nodes = layout(expr, &state.collapsed);
+    if let Some(root) = nodes.iter_mut().find(|n| n.path.is_empty()) {
+        root.range = range;
+    }
+    let max_depth = nodes.iter().map(|n| n.depth).max().unwrap_or(0);
+    let rows = nodes.iter().map(|n| n.row).fold(0.0, f32::max) + 1.0;
+    let size = egui::vec2(
+        (max_depth + 1) as f32 * COLUMN_WIDTH + MARGIN,
+        rows * ROW_HEIGHT + MARGIN,
+    );
+
+    let mut action = None;
+    egui::ScrollArea::both()
+        .id_salt(id)
+        .max_height(420.0)
+        .show(ui, |ui| {
+            let (canvas, canvas_response) = ui.allocate_exact_size(size, egui::Sense::click());
+            ui.painter()
+                .rect_filled(canvas, 4.0, ui.visuals().extreme_bg_color);
+            let wires = ui.painter().add(egui::Shape::Noop);
+
+            let mut ports = Ports::default();
+            for layout_node in nodes.iter() {
+                let offset = state
+                    .offsets
+                    .get(&layout_node.key)
+                    .copied()
+                    .unwrap_or_default();
+                let position = canvas.min
+                    + egui::vec2(
+                        (max_depth - layout_node.depth) as f32 * COLUMN_WIDTH + MARGIN,
+                        layout_node.row * ROW_HEIGHT + MARGIN,
+                    )
+                    + offset;
+                let mut node_ui = ui.new_child(
+                    egui::UiBuilder::new()
+                        .id_salt(("node", layout_node.key))
+                        .max_rect(egui::Rect::from_min_size(
+                            position,
+                            egui::vec2(NODE_WIDTH, ROW_HEIGHT * 8.0),
+                        )),
+                );
+                if let Some(node) = node_at_mut(expr, &layout_node.path) {
+                    draw_node(
+                        &mut node_ui,
+                        node,
+                        layout_node,
+                        app,
+                        &mut state,
+                        &mut ports,
+                        &mut action,
+                    );
+                }
+            }
+
+            let mut shapes = Vec::new();
+            for (path, index, to, value_type) in ports.inputs.iter() {
+                let mut operand = path.clone();
+                operand.push(*index);
+                if let Some(from) = ports.outputs.get(&operand) {
+                    shapes.push(wire(*from, *to, type_color(*value_type)));
+                }
+            }
+            if let Some(source) = &state.connecting
+                && let Some(from) = ports.outputs.get(source)
+                && let Some(pointer) = ui.ctx().pointer_latest_pos()
+            {
+                shapes.push(wire(*from, pointer, ui.visuals().selection.stroke.color));
+            }
+            ui.painter().set(wires, egui::Shape::Vec(shapes));
+
+            if let Some(GraphAction::Connect(source, pointer)) = &action {
+                let target = ports
+                    .inputs
+                    .iter()
+                    .find(|(_, _, position, _)| position.distance(*pointer) <= PORT_RADIUS * 3.0);
+                if let Some((path, index, _, _)) = target {
+                    let parent = node_at(expr, path).and_then(ExprWriterEditor::node_id);
+                    let mut target = path.clone();
+                    target.push(*index);
+                    let moved = nodes.iter().find(|n| n.path == *source).map(|n| n.key);
+                    // Operators keep their offset through their id, a leaf
+                    // takes its offset to the operand it now fills.
+                    if move_subtree(expr, source, &target)
+                        && let Some(key @ NodeKey::Operand(..)) = moved
+                        && let Some(offset) = state.offsets.remove(&key)
+                    {
+                        state
+                            .offsets
+                            .insert(NodeKey::Operand(parent, *index), offset);
+                    }
+                }
+            }
+            if canvas_response.clicked() {
+                state.selected = None;
+            }
+            let selected = nodes
+                .iter()
+                .find(|n| Some(n.key) == state.selected)
+                .map(|n| n.path.clone());
+            if let Some(selected) = selected
+                && ui.rect_contains_pointer(canvas)
+                && !ui.ctx().wants_keyboard_input()
+            {
+                handle_keys(ui, expr, &selected);
+            }
+        });
+
+    if let Some(GraphAction::Delete(path)) = action
+        && let Some(node) = node_at_mut(expr, &path)
+    {
+        *node = ExprWriterEditor::Placeholder;
+    }
+    ui.data_mut(|d| d.insert_temp(state_id, state));
+}
@@ -14,8 +14,20 @@ pub fn ui_tools_for_expr_writer(label: &str, ui: &mut egui::Ui) -> ExprControl {
             return ExprControl::Delete;
         }
 
-        if ui.button("🗐").clicked() {
-            return ExprControl::Copy;
+        if ui.button("🗐").on_hover_text("Save as snippet").clicked() {
+            return ExprControl::SaveSnippet;
+        }
+
+        if ui.button("✏").on_hover_text("Edit as text").clicked() {
+            return ExprControl::EditText;
+        }
+
+        if ui.button("🕸").on_hover_text("Edit as graph").clicked() {
+            return ExprControl::EditGraph;
+        }
+
+        if ui.button("📈").on_hover_text("Preview values").clicked() {
+            return ExprControl::TogglePreview;
         }
 
         if !label.is_empty() {
@@ -28,13 +40,41 @@ pub fn ui_tools_for_expr_writer(label: &str, ui: &mut egui::Ui) -> ExprControl {
     .inner
 }
 
-pub fn ui_for_f32(ui: &mut egui::Ui, v: f32) -> f32 {
+/// Range and drag speed of a numeric field. Each field picks the one that
+/// fits the values it holds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FieldRange {
+    pub min: f32,
+    pub max: f32,
+    pub speed: f32,
+}
+
+impl FieldRange {
+    /// Positions, speeds and other values of any sign.
+    pub const ANY: Self = Self::new(-1000.0, 1000.0, 0.01);
+    /// Sizes and other non-negative values.
+    pub const POSITIVE: Self = Self::new(0.0, 1000.0, 0.01);
+    /// Ratios, such as the keys of a gradient.
+    pub const UNIT: Self = Self::new(0.0, 1.0, 0.005);
+    /// Colour components, above 1 for HDR colours.
+    pub const COLOR: Self = Self::new(0.0, 100.0, 0.01);
+    /// Multiplier of an HDR colour.
+    pub const INTENSITY: Self = Self::new(1.0, 100.0, 0.05);
+
+    pub const fn new(min: f32, max: f32, speed: f32) -> Self {
+        Self { min, max, speed }
+    }
+
+    fn drag_value<'a>(&self, v: &'a mut f32) -> egui::DragValue<'a> {
+        egui::DragValue::new(v)
+            .speed(self.speed)
+            .range(self.min..=self.max)
+    }
+}
+
+pub fn ui_for_f32(ui: &mut egui::Ui, v: f32, range: FieldRange) -> f32 {
     let mut v = v;
-    ui.add(
-        egui::DragValue::new(&mut v)
-            .speed(0.01)
-            .range(-1000.0..=1000.0),
-    );
+    ui.add(range.drag_value(&mut v));
     v
 }
 
@@ -59,15 +99,21 @@ where
     v
 }
 
-pub fn ui_for_vec3(ui: &mut egui::Ui, mut v: Vec3) -> Vec3 {
+pub fn ui_for_vec2(ui: &mut egui::Ui, mut v: Vec2, range: FieldRange) -> Vec2 {
+    ui.horizontal(|col_ui| {
+        for i in 0..2 {
+            col_ui.add_space(5.0);
+            col_ui.add(range.drag_value(&mut v[i]));
+        }
+    });
+    v
+}
+
+pub fn ui_for_vec3(ui: &mut egui::Ui, mut v: Vec3, range: FieldRange) -> Vec3 {
     ui.horizontal(|col_ui| {
         for i in 0..3 {
             col_ui.add_space(5.0);
-            col_ui.add(
-                egui::DragValue::new(&mut v[i])
-                    .speed(0.01)
-                    .range(-1000.0..=1000.0),
-            );
+            col_ui.add(range.drag_value(&mut v[i]));
         }
         col_ui.menu_button("xyz", |ui| {
             if ui.button("Vec3::X").clicked() {
@@ -91,21 +137,84 @@ pub fn ui_for_vec3(ui: &mut egui::Ui, mut v: Vec3) -> Vec3 {
     v
 }
 
-pub fn ui_for_vec4(ui: &mut egui::Ui, mut v: Vec4) -> Vec4 {
+pub fn ui_for_vec4(ui: &mut egui::Ui, mut v: Vec4, range: FieldRange) -> Vec4 {
     ui.horizontal(|col_ui| {
         for i in 0..4 {
             col_ui.add_space(5.0); // Optional spacing
-
-            col_ui.add(
-                egui::DragValue::new(&mut v[i])
-                    .speed(0.01)
-                    .range(0.0..=100.0),
-            );
+            col_ui.add(range.drag_value(&mut v[i]));
         }
     });
     v
 }
 
+/// Colour picker for a linear RGBA colour in `0.0..=1.0`, followed by the
+/// intensity its RGB is multiplied by.
+pub fn ui_for_hdr_color(
+    ui: &mut egui::Ui,
+    color: Vec4,
+    intensity: f32,
+    range: FieldRange,
+) -> (Vec4, f32) {
+    let (mut color, mut intensity) = (color, intensity);
+    edit_hdr_color(ui, &mut color, &mut intensity, range);
+    (color, intensity)
+}
+
+/// Draws the widgets of [`ui_for_hdr_color`], returning whether one of them
+/// changed the colour.
+fn edit_hdr_color(
+    ui: &mut egui::Ui,
+    color: &mut Vec4,
+    intensity: &mut f32,
+    range: FieldRange,
+) -> bool {
+    // Alpha is edited on its own, so that the colour of transparent keys is
+    // not lost to premultiplication.
+    let mut rgb = color.truncate().to_array();
+    let mut alpha = color.w;
+    let changed = ui
+        .horizontal(|ui| {
+            let picker = egui::color_picker::color_edit_button_rgb(ui, &mut rgb);
+            ui.label("α");
+            let alpha = ui.add(FieldRange::UNIT.drag_value(&mut alpha));
+            ui.label("×");
+            let intensity = ui
+                .add(range.drag_value(intensity))
+                .on_hover_text("Intensity");
+            picker.changed() || alpha.changed() || intensity.changed()
+        })
+        .inner;
+    if changed {
+        *color = Vec3::from_array(rgb).extend(alpha);
+    }
+    changed
+}
+
+/// Splits an HDR colour into a colour in `0.0..=1.0` and the intensity that
+/// brings it back, at least 1.
+pub fn split_hdr_color(v: Vec4) -> (Vec4, f32) {
+    let intensity = v.truncate().max_element().max(1.0);
+    ((v.truncate() / intensity).extend(v.w), intensity)
+}
+
+/// The HDR colour given by a colour and its intensity.
+pub fn apply_intensity(color: Vec4, intensity: f32) -> Vec4 {
+    (color.truncate() * intensity).extend(color.w)
+}
+
+/// [`ui_for_hdr_color`] for a colour stored with its intensity applied.
+///
+/// Splitting and applying the intensity again is not exact in `f32`, so the
+/// value is only written back when it is edited.
+pub fn ui_for_hdr_vec4(ui: &mut egui::Ui, v: Vec4) -> Vec4 {
+    let (mut color, mut intensity) = split_hdr_color(v);
+    if edit_hdr_color(ui, &mut color, &mut intensity, FieldRange::INTENSITY) {
+        apply_intensity(color, intensity)
+    } else {
+        v
+    }
+}
+
 pub fn ui_for_list_item(ui: &mut egui::Ui, index: usize, len: usize) -> Option<ListCommand> {
     if ui
         .button("❌")
@@ -144,15 +253,25 @@ pub fn ui_for_list_item(ui: &mut egui::Ui, index: usize, len: usize) -> Option<L
     None
 }
 
-pub fn unique_collapsing(salt_id: u64, text: &str, ui: &mut egui::Ui) -> egui::CollapsingHeader {
-    let blend = format!("{}{}", text, salt_id);
+/// A collapsing header showing `text`, whose open state is keyed on
+/// `salt_id` and `id_text`, so that the text can change between frames.
+pub fn unique_collapsing(
+    salt_id: u64,
+    id_text: &str,
+    text: impl Into<egui::WidgetText>,
+    ui: &mut egui::Ui,
+) -> egui::CollapsingHeader {
+    let blend = format!("{}{}", id_text, salt_id);
     egui::CollapsingHeader::new(text).id_salt(ui.make_persistent_id(blend))
 }
 
 pub enum ExprControl {
     Noop,
     Delete,
-    Copy,
+    SaveSnippet,
+    EditText,
+    EditGraph,
+    TogglePreview,
 }
 
 pub enum ListCommand {
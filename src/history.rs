@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use bevy_egui::egui;
+
+use crate::OmagariProject;
+
+const MAX_HISTORY: usize = 256;
+
+/// Counter moved in every frame where the project may have been edited, so
+/// that the project is only serialized for snapshots when it moved, instead
+/// of every frame.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct EditGeneration(u64);
+
+impl EditGeneration {
+    /// Records an edit made outside of the widgets, e.g. loading a project.
+    pub fn bump(&mut self) {
+        self.0 += 1;
+    }
+
+    /// Records the edits of the frame drawn in `ctx`, once all of its
+    /// widgets are drawn. Widgets report their changes and clicks as egui
+    /// output events. The other edits, such as right-click deletes, graph
+    /// wiring and keyboard shortcuts, end with a pointer release or a key
+    /// press.
+    pub fn track_frame(&mut self, ctx: &egui::Context) {
+        let widget_changed = ctx.output(|o| {
+            o.events.iter().any(|event| {
+                matches!(
+                    event,
+                    egui::output::OutputEvent::Clicked(_)
+                        | egui::output::OutputEvent::DoubleClicked(_)
+                        | egui::output::OutputEvent::TripleClicked(_)
+                        | egui::output::OutputEvent::ValueChanged(_)
+                )
+            })
+        });
+        let input = ctx.input(|i| {
+            i.pointer.any_released()
+                || i.events
+                    .iter()
+                    .any(|e| matches!(e, egui::Event::Key { pressed: true, .. }))
+        });
+        if widget_changed || input {
+            self.bump();
+        }
+    }
+}
+
+/// Snapshot based undo/redo history for an [`OmagariProject`].
+///
+/// Snapshots are the serialized project, so any edit made anywhere in the
+/// editor is covered without each widget having to record its own command.
+/// Edits are grouped by only committing a snapshot once the user has settled,
+/// i.e. when no drag or text edit is in progress.
+#[derive(Default)]
+pub struct ProjectHistory {
+    current: Option<String>,
+    /// Generation `current` was taken at.
+    tracked: Option<EditGeneration>,
+    undo: VecDeque<String>,
+    redo: Vec<String>,
+}
+
+impl ProjectHistory {
+    /// Forgets all history, e.g. after loading a different project.
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.tracked = None;
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Records the project state if it differs from the last recorded one.
+    /// The project is only compared again once `generation` moved.
+    ///
+    /// Pass `settled = false` while an interaction is still ongoing, so that a
+    /// whole drag or text edit ends up as a single undo step.
+    pub fn track(&mut self, project: &OmagariProject, settled: bool, generation: EditGeneration) {
+        if !settled || self.tracked == Some(generation) {
+            return;
+        }
+        self.tracked = Some(generation);
+        let Ok(snapshot) = ron::ser::to_string(project) else {
+            return;
+        };
+        match self.current.take() {
+            Some(previous) if previous != snapshot => {
+                self.undo.push_back(previous);
+                if self.undo.len() > MAX_HISTORY {
+                    self.undo.pop_front();
+                }
+                self.redo.clear();
+            }
+            _ => {}
+        }
+        self.current = Some(snapshot);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) -> Option<OmagariProject> {
+        let snapshot = self.undo.pop_back()?;
+        if let Some(current) = self.current.replace(snapshot.clone()) {
+            self.redo.push(current);
+        }
+        ron::de::from_str(&snapshot).ok()
+    }
+
+    pub fn redo(&mut self) -> Option<OmagariProject> {
+        let snapshot = self.redo.pop()?;
+        if let Some(current) = self.current.replace(snapshot.clone()) {
+            self.undo.push_back(current);
+        }
+        ron::de::from_str(&snapshot).ok()
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::OmagariProject;
+
+/// Persistent identity of an effect, a modifier or an expression operator
+/// node, saved with the project. UI state such as open headers and the graph
+/// selection is keyed on it, so it follows the node when the node is moved or
+/// reordered.
+///
+/// Leaves such as literals, `Property` and `Ref` have no id of their own, so
+/// that they stay as short as `Float(1.0)` in project files. They are
+/// addressed as an operand of the operator or modifier holding them, see
+/// [`Diagnostic::operand`](crate::validate::Diagnostic::operand).
+///
+/// Files without ids get new random ones on load.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(transparent)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    pub fn new() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for NodeId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u64> for NodeId {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Editors identified by a [`NodeId`].
+pub trait Identified {
+    fn id(&self) -> NodeId;
+    fn id_mut(&mut self) -> &mut NodeId;
+}
+
+impl OmagariProject {
+    /// Gives new ids to nodes whose id is already taken, e.g. after parts of
+    /// a project file were copied by hand. Effects keep their ids first, then
+    /// definitions and snippets.
+    pub fn ensure_unique_ids(&mut self) {
+        let mut seen = HashSet::new();
+        let mut claim = |id: &mut NodeId| {
+            while !seen.insert(*id) {
+                *id = NodeId::new();
+            }
+        };
+        for effect in self.effects.iter_mut() {
+            effect.for_each_id_mut(&mut claim);
+        }
+        for definition in self.definitions.iter_mut() {
+            definition.expr.for_each_id_mut(&mut claim);
+        }
+        for snippet in self.snippets.iter_mut() {
+            snippet.expr.for_each_id_mut(&mut claim);
+        }
+    }
+}
@@ -2,18 +2,58 @@ use bevy::prelude::*;
 use bevy_hanabi::prelude::*;
 use serde::{Deserialize, Serialize};
 
+pub mod cli;
+pub mod complex;
 pub mod controller;
+pub mod definitions;
+pub mod document;
 pub mod effect;
+pub mod eval;
 pub mod expr;
+pub mod graph;
 pub mod helpers;
+pub mod history;
+pub mod ids;
+pub mod migrations;
 pub mod modifiers;
+pub mod optimize;
+pub mod properties;
+pub mod random;
+pub mod snippets;
+pub mod syntax;
+pub mod typecheck;
+pub mod validate;
 
-use effect::EffectEditor;
-use std::io::{self, Read};
+use complex::{EffectComplex, EffectComplexLoader, spawn_omagari_complexes};
+use controller::ExportedProject;
+use definitions::ExprDefinition;
+use effect::{EffectEditor, ParticleTexture, default_textures};
+use snippets::ExprSnippet;
+use std::io::Read;
 
-#[derive(Resource, Serialize, Deserialize, Default)]
+#[derive(Resource, Serialize, Deserialize)]
 pub struct OmagariProject {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub textures: Vec<ParticleTexture>,
     pub effects: Vec<EffectEditor>,
+    #[serde(default)]
+    pub snippets: Vec<ExprSnippet>,
+    #[serde(default)]
+    pub definitions: Vec<ExprDefinition>,
+}
+
+impl Default for OmagariProject {
+    fn default() -> Self {
+        Self {
+            version: migrations::PROJECT_VERSION,
+            textures: default_textures(),
+            effects: Vec::new(),
+            snippets: Vec::new(),
+            definitions: Vec::new(),
+        }
+    }
 }
 
 impl OmagariProject {
@@ -21,9 +61,36 @@ impl OmagariProject {
         let mut file = std::fs::File::open(p)?;
         let mut ron_string = String::new();
         file.read_to_string(&mut ron_string)?;
-        let graph: OmagariProject = ron::de::from_str(&ron_string)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(graph)
+        migrations::project_from_str(&ron_string)
+    }
+
+    pub fn texture_path(&self, name: &str) -> Option<&str> {
+        self.textures
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| t.path.as_str())
+    }
+
+    /// Adds a texture to the library, suffixing the name if it is already taken.
+    pub fn add_texture(&mut self, name: &str, path: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.textures.iter().any(|t| t.name == unique) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        self.textures.push(ParticleTexture::new(&unique, path));
+        unique
+    }
+
+    /// Renames a library texture and every effect using it.
+    pub fn rename_texture(&mut self, from: &str, to: &str) {
+        for texture in self.textures.iter_mut().filter(|t| t.name == from) {
+            texture.name = to.to_string();
+        }
+        for effect in self.effects.iter_mut() {
+            effect.rename_texture(from, to);
+        }
     }
 }
 
@@ -34,8 +101,32 @@ impl From<OmagariProject> for OmagariBundle {
                 .effects
                 .iter()
                 .map(|e| OmagariEffect {
-                    texture_asset: "effects/cloud2.png".to_string(),
-                    effect: e.produce(),
+                    name: e.name().to_string(),
+                    parent: e.parent(),
+                    texture_asset: e
+                        .texture()
+                        .and_then(|t| project.texture_path(t))
+                        .map(str::to_string),
+                    properties: e.properties().to_vec(),
+                    effect: e.produce(&project.definitions),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ExportedProject> for OmagariBundle {
+    fn from(project: ExportedProject) -> Self {
+        Self {
+            effects: project
+                .effects
+                .into_iter()
+                .map(|e| OmagariEffect {
+                    texture_asset: e.texture,
+                    name: e.name,
+                    parent: e.parent,
+                    properties: e.properties,
+                    effect: e.effect_asset,
                 })
                 .collect(),
         }
@@ -43,37 +134,86 @@ impl From<OmagariProject> for OmagariBundle {
 }
 
 pub struct OmagariEffect {
-    pub texture_asset: String,
+    pub name: String,
+    pub parent: Option<String>,
+    pub texture_asset: Option<String>,
+    pub properties: Vec<properties::EffectProperty>,
     pub effect: EffectAsset,
-    // For setting parenting for hanabi
-    // is_child: bool,
 }
 
 pub struct OmagariBundle {
     pub effects: Vec<OmagariEffect>,
 }
 
+/// Registers the [`EffectComplex`] asset and its loader for exported
+/// `{project_name}.hanabi.ron` files, and spawns complexes requested with
+/// [`complex::SpawnOmagariComplex`]. Requires `HanabiPlugin`.
+pub struct OmagariPlugin;
+
+impl Plugin for OmagariPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EffectComplex>()
+            .init_asset_loader::<EffectComplexLoader>()
+            .add_systems(Update, spawn_omagari_complexes);
+    }
+}
+
 pub mod prelude {
-    pub use super::OmagariProject;
+    pub use super::complex::{
+        EffectComplex, OmagariComplexEffects, PreparedEffect, SpawnOmagariComplex,
+    };
+    pub use super::controller::{ExportedEffect, ExportedProject};
+    pub use super::properties::{EffectProperty, EffectPropertyValue};
+    pub use super::validate::{Diagnostic, Severity};
+    pub use super::{OmagariPlugin, OmagariProject};
 }
 
 pub mod editor_prelude {
     use std::path::PathBuf;
 
     pub use super::controller::*;
+    pub use super::definitions::*;
     pub use super::effect::*;
+    pub use super::eval::*;
     pub use super::expr::*;
+    pub use super::graph::*;
     pub use super::helpers::*;
+    pub use super::history::*;
+    pub use super::ids::*;
     pub use super::modifiers::*;
+    pub use super::optimize::*;
+    pub use super::properties::*;
+    pub use super::random::*;
+    pub use super::snippets::*;
+    pub use super::syntax::*;
+    pub use super::typecheck::*;
+    pub use super::validate::*;
 
     pub use super::OmagariProject;
 
-    use super::expr::ExprWriterEditor;
-
     #[derive(Default)]
     pub struct AppContext {
-        pub expr_clipboard: Option<ExprWriterEditor>,
+        pub snippets: SnippetLibrary,
         pub visible_effects: Vec<String>,
+        pub texture_names: Vec<String>,
+        /// Properties of the effect being drawn.
+        pub properties: Vec<EffectProperty>,
+        pub preview_properties: PreviewProperties,
+        pub definition_names: Vec<String>,
+        /// Inputs of the value previews of expressions.
+        pub eval_inputs: EvalInputs,
         pub filename: Option<PathBuf>,
+        /// Why the last project picked with LOAD could not be read.
+        pub load_error: Option<String>,
+        pub history: ProjectHistory,
+        /// Moves when the project may have been edited.
+        pub edits: EditGeneration,
+    }
+
+    impl AppContext {
+        /// Types of the project leaves of the expressions being drawn.
+        pub fn type_context(&self) -> TypeContext<'_> {
+            TypeContext::new(&self.eval_inputs.definitions).with_properties(&self.properties)
+        }
     }
 }
@@ -20,6 +20,11 @@ use std::io::Write;
 use omagari::editor_prelude::*;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = omagari::cli::run(&args) {
+        std::process::exit(code);
+    }
+
     App::new()
         .insert_resource(ClearColor(Color::srgb(0.0, 0.0, 0.0)))
         .add_plugins(DefaultPlugins)
@@ -32,11 +37,7 @@ fn main() {
         .run();
 }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut egui_global_settings: ResMut<EguiGlobalSettings>,
-) {
+fn setup(mut commands: Commands, mut egui_global_settings: ResMut<EguiGlobalSettings>) {
     egui_global_settings.auto_create_primary_context = false;
     commands.spawn((
         Transform::from_xyz(0.0, 1.5, 5.0),
@@ -60,15 +61,20 @@ fn setup(
     ));
 
     commands.insert_resource(EffectResource {
-        effect_handles: Vec::new(),
-        textures: PARTICLE_TEXTURES
-            .iter()
-            .map(|v| asset_server.load(v.filename))
-            .collect(),
-        context: AppContext::default(),
+        previews: Vec::new(),
+        auto_preview: false,
+        previewed: None,
+        diagnostics: Vec::new(),
+        validated: None,
+        textures: Default::default(),
+        context: AppContext {
+            snippets: SnippetLibrary::load_user(),
+            ..default()
+        },
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn app_ui(
     mut commands: Commands,
     mut contexts: EguiContexts,
@@ -76,8 +82,9 @@ fn app_ui(
     window: Single<&mut Window, With<PrimaryWindow>>,
     project: ResMut<OmagariProject>,
     mut res: ResMut<EffectResource>,
-    effects: ResMut<Assets<EffectAsset>>,
+    mut effects: ResMut<Assets<EffectAsset>>,
     curr: Query<Entity, With<ParticleEffect>>,
+    asset_server: Res<AssetServer>,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
 
@@ -86,15 +93,45 @@ fn app_ui(
         .iter()
         .map(|e| e.name().to_string())
         .collect();
+    res.context.texture_names = project.textures.iter().map(|t| t.name.clone()).collect();
+    res.context.snippets.project = project.snippets.clone();
+    res.context.definition_names = project.definitions.iter().map(|d| d.name.clone()).collect();
+    res.context.eval_inputs.definitions = project.definitions.clone();
+    for texture in project.textures.iter() {
+        if !res.textures.contains_key(&texture.path) {
+            let handle = asset_server.load(texture.path.clone());
+            res.textures.insert(texture.path.clone(), handle);
+        }
+    }
 
     let project = std::rc::Rc::new(std::cell::RefCell::new(project.into_inner()));
 
+    if !ctx.wants_keyboard_input() {
+        let (undo, redo) = ctx.input_mut(|i| {
+            let redo = i.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::Z,
+            ));
+            let undo = i.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND,
+                egui::Key::Z,
+            ));
+            (undo, redo)
+        });
+        if undo {
+            undo_project_edit(&mut res.context, &mut project.borrow_mut());
+        }
+        if redo {
+            redo_project_edit(&mut res.context, &mut project.borrow_mut());
+        }
+    }
+
     let mut filename = res
         .context
         .filename
         .clone()
         .map(|x| x.display().to_string())
-        .unwrap_or(String::new());
+        .unwrap_or_default();
 
     egui::TopBottomPanel::top("Toolbar")
         .resizable(false)
@@ -107,10 +144,31 @@ fn app_ui(
                             &mut commands,
                             &mut res,
                             project.clone(),
-                            effects,
-                            curr,
+                            &mut effects,
+                            &curr,
                         );
                     }
+                    ui.checkbox(&mut res.auto_preview, "Auto")
+                        .on_hover_text("Re-render changed effects after every edit");
+
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.add_space(10.0);
+
+                    if ui
+                        .add_enabled(res.context.history.can_undo(), egui::Button::new("↶"))
+                        .on_hover_text("Undo (Ctrl+Z)")
+                        .clicked()
+                    {
+                        undo_project_edit(&mut res.context, &mut project.borrow_mut());
+                    }
+                    if ui
+                        .add_enabled(res.context.history.can_redo(), egui::Button::new("↷"))
+                        .on_hover_text("Redo (Ctrl+Shift+Z)")
+                        .clicked()
+                    {
+                        redo_project_edit(&mut res.context, &mut project.borrow_mut());
+                    }
 
                     ui.add_space(10.0);
                     ui.separator();
@@ -132,7 +190,10 @@ fn app_ui(
 
                     if ui.button("🌌 NEW").clicked() {
                         res.context.filename = None;
-                        commands.insert_resource(OmagariProject::default());
+                        res.context.load_error = None;
+                        res.context.history.clear();
+                        res.context.edits.bump();
+                        **project.borrow_mut() = OmagariProject::default();
                     }
 
                     ui.menu_button("⮉ LOAD", |ui| {
@@ -142,11 +203,21 @@ fn app_ui(
                             .pick_file();
 
                         if let Some(path) = files {
-                            if let Ok(project) = OmagariProject::load(&path) {
-                                commands.insert_resource(project);
-                                res.context.filename = Some(path.clone());
-                                ui.close_menu();
+                            match OmagariProject::load(&path) {
+                                Ok(loaded) => {
+                                    **project.borrow_mut() = loaded;
+                                    res.context.filename = Some(path.clone());
+                                    res.context.load_error = None;
+                                    res.context.history.clear();
+                                    res.context.edits.bump();
+                                }
+                                Err(e) => {
+                                    error!("Failed loading {}: {}", path.display(), e);
+                                    res.context.load_error =
+                                        Some(format!("{}: {}", path.display(), e));
+                                }
                             }
+                            ui.close_menu();
                         }
                     });
                     if ui
@@ -163,6 +234,19 @@ fn app_ui(
                             file.write_all(ron_string.as_bytes()).unwrap();
                         }
                     }
+                    if ui
+                        .add_enabled(filename_okay, egui::Button::new("⮋ EXPORT"))
+                        .clicked()
+                        && let Some(export_path) = export_filename(&filename)
+                    {
+                        for diagnostic in project.borrow().validate() {
+                            warn!("{}", diagnostic);
+                        }
+                        let exported = ExportedProject::from(&**project.borrow());
+                        if let Err(e) = exported.save(&export_path) {
+                            error!("Failed exporting {}: {}", export_path.display(), e);
+                        }
+                    }
 
                     ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.colored_label(egui::Color32::from_hex("#88AAFF").unwrap(), "OMAGARI🔥");
@@ -175,12 +259,68 @@ fn app_ui(
         .rect
         .height();
 
+    if res.validated != Some(res.context.edits) {
+        res.diagnostics = project.borrow().validate();
+        res.validated = Some(res.context.edits);
+    }
+    // Taken for the panel, which also needs `res`, and put back after it.
+    let diagnostics = std::mem::take(&mut res.diagnostics);
+    let bottom = if diagnostics.is_empty() && res.context.load_error.is_none() {
+        0.0
+    } else {
+        egui::TopBottomPanel::bottom("DiagnosticsPanel")
+            .resizable(true)
+            .show(ctx, |ui| {
+                if let Some(load_error) = &res.context.load_error {
+                    let mut dismiss = false;
+                    ui.horizontal(|ui| {
+                        dismiss = ui.small_button("❌").on_hover_text("Dismiss").clicked();
+                        ui.colored_label(
+                            egui::Color32::from_hex("#FF6666").unwrap(),
+                            "Could not load",
+                        );
+                        ui.label(load_error);
+                    });
+                    if dismiss {
+                        res.context.load_error = None;
+                    }
+                }
+                ui.label(format!("Diagnostics ({})", diagnostics.len()));
+                egui::ScrollArea::vertical()
+                    .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
+                    .show(ui, |ui| {
+                        for diagnostic in diagnostics.iter() {
+                            let color = match diagnostic.severity {
+                                Severity::Error => egui::Color32::from_hex("#FF6666").unwrap(),
+                                Severity::Warning => egui::Color32::from_hex("#FFCC66").unwrap(),
+                            };
+                            ui.horizontal(|ui| {
+                                ui.colored_label(color, diagnostic.location());
+                                ui.label(&diagnostic.message);
+                            });
+                        }
+                    });
+            })
+            .response
+            .rect
+            .height()
+    };
+    res.diagnostics = diagnostics;
+
     let left = egui::SidePanel::left("EffectsPanel")
         .resizable(true)
         .show(ctx, |ui| {
             egui::ScrollArea::vertical()
                 .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
                 .show(ui, |ui| {
+                    ui_for_texture_library(&mut project.borrow_mut(), ui);
+                    ui_for_snippet_library(
+                        &mut project.borrow_mut(),
+                        &mut res.context.snippets,
+                        ui,
+                    );
+                    ui_for_definitions(&mut project.borrow_mut(), &mut res.context, ui);
+                    ui.separator();
                     ui.horizontal(|ui| {
                         ui.vertical(|ui| {
                             let effects = &mut project.borrow_mut().effects;
@@ -224,9 +364,20 @@ fn app_ui(
         .rect
         .width();
 
+    let settled = !ctx.is_using_pointer() && !ctx.wants_keyboard_input();
+    res.context.snippets.apply_saved(&mut project.borrow_mut());
+    res.context.edits.track_frame(ctx);
+    let edits = res.context.edits;
+    res.context.history.track(&project.borrow(), settled, edits);
+    if res.auto_preview && settled && res.previewed != Some(edits) {
+        update_particle_effects(&mut commands, &mut res, &project.borrow(), &mut effects);
+        res.previewed = Some(edits);
+    }
+    update_preview_properties(&mut commands, &mut res, &project.borrow());
+
     let pos = UVec2::new(left as u32, 0);
-    let size =
-        UVec2::new(window.physical_width(), window.physical_height()) - UVec2::new(left as u32, 0);
+    let size = UVec2::new(window.physical_width(), window.physical_height())
+        - UVec2::new(left as u32, bottom as u32);
     camera.viewport = Some(Viewport {
         physical_position: pos,
         physical_size: size,
@@ -235,3 +386,18 @@ fn app_ui(
 
     Ok(())
 }
+
+fn undo_project_edit(context: &mut AppContext, project: &mut OmagariProject) {
+    context.history.track(project, true, context.edits);
+    if let Some(previous) = context.history.undo() {
+        *project = previous;
+        context.edits.bump();
+    }
+}
+
+fn redo_project_edit(context: &mut AppContext, project: &mut OmagariProject) {
+    if let Some(next) = context.history.redo() {
+        *project = next;
+        context.edits.bump();
+    }
+}
@@ -0,0 +1,207 @@
+use std::io;
+
+use crate::OmagariProject;
+use crate::document::{Document, Node};
+use crate::effect::default_textures;
+use crate::ids::NodeId;
+
+/// Version of the project file format written by this build.
+pub const PROJECT_VERSION: u32 = 8;
+
+/// A step upgrading a project file from `version - 1` to `version`.
+///
+/// Steps edit the untyped [`Document`] before it is deserialized, so they
+/// see the file as it was written and the typed project only has to read
+/// the current schema.
+struct Migration {
+    version: u32,
+    apply: fn(&mut Node),
+}
+
+const MIGRATIONS: [Migration; 8] = [
+    Migration {
+        version: 1,
+        apply: texture_library,
+    },
+    Migration {
+        version: 2,
+        apply: effect_properties,
+    },
+    Migration {
+        version: 3,
+        apply: snippet_library,
+    },
+    Migration {
+        version: 4,
+        apply: expression_definitions,
+    },
+    Migration {
+        version: 5,
+        apply: no_changes,
+    },
+    Migration {
+        version: 6,
+        apply: node_ids,
+    },
+    Migration {
+        version: 7,
+        apply: no_changes,
+    },
+    Migration {
+        version: 8,
+        apply: effect_seeds,
+    },
+];
+
+fn effects_mut(project: &mut Node) -> &mut [Node] {
+    match project.field_mut("effects") {
+        Some(effects) => effects.items_mut(),
+        None => &mut [],
+    }
+}
+
+/// Version 1 replaced the built-in texture array with a per-project texture
+/// library referenced by name.
+fn texture_library(project: &mut Node) {
+    let textures = default_textures();
+    if project
+        .field("textures")
+        .is_none_or(|t| *t == Node::List(Vec::new()))
+    {
+        let library = textures
+            .iter()
+            .map(|t| {
+                Node::Struct(
+                    None,
+                    vec![
+                        ("name".to_string(), Node::string(&t.name)),
+                        ("path".to_string(), Node::string(&t.path)),
+                    ],
+                )
+            })
+            .collect();
+        project.set_field("textures", Node::List(library));
+    }
+    for effect in effects_mut(project) {
+        let index = match effect.remove_field("texture_index") {
+            Some(Node::Tuple(Some(name), items)) if name == "Some" => {
+                items.first().and_then(Node::as_u64)
+            }
+            _ => None,
+        };
+        if let Some(texture) = index.and_then(|i| textures.get(i as usize))
+            && effect
+                .field("texture")
+                .is_none_or(|t| *t == Node::Ident("None".to_string()))
+        {
+            effect.set_field(
+                "texture",
+                Node::Tuple(Some("Some".to_string()), vec![Node::string(&texture.name)]),
+            );
+        }
+    }
+}
+
+/// Version 2 added typed properties to effects.
+fn effect_properties(project: &mut Node) {
+    for effect in effects_mut(project) {
+        effect.set_default_field("properties", Node::List(Vec::new()));
+    }
+}
+
+/// Version 3 replaced the expression clipboard with a snippet library.
+fn snippet_library(project: &mut Node) {
+    project.set_default_field("snippets", Node::List(Vec::new()));
+}
+
+/// Version 4 added project-level expression definitions and `Ref` leaves.
+fn expression_definitions(project: &mut Node) {
+    project.set_default_field("definitions", Node::List(Vec::new()));
+}
+
+/// Versions 5 (swizzles, casts and simulation value leaves) and 7 (`Vec2`
+/// and `Color` literals) only added node kinds, which older files cannot
+/// contain. The version still changes, so that older builds reject files
+/// using them.
+fn no_changes(_project: &mut Node) {}
+
+/// Version 6 gave effects, modifiers and expression operators persistent
+/// ids.
+fn node_ids(project: &mut Node) {
+    const MODIFIER_LISTS: [&str; 3] = ["init_modifiers", "update_modifiers", "render_modifiers"];
+    for effect in effects_mut(project) {
+        effect.set_default_field("id", Node::Atom(NodeId::new().value().to_string()));
+        for list in MODIFIER_LISTS {
+            let Some(modifiers) = effect.field_mut(list) else {
+                continue;
+            };
+            // Modifiers are written `Kind((field: value, ..))`.
+            for modifier in modifiers.items_mut() {
+                if let [fields @ Node::Struct(..)] = modifier.items_mut()
+                    && fields.field("id").is_none()
+                {
+                    fields.set_field("id", Node::Atom(NodeId::new().value().to_string()));
+                }
+            }
+        }
+    }
+    project.for_each_mut(&mut |node| {
+        if let Node::Tuple(Some(name), items) = node
+            && name == "Operator"
+            && items.len() == 1
+        {
+            items.push(Node::Atom(NodeId::new().value().to_string()));
+        }
+    });
+}
+
+/// Version 8 added random ranges and a random seed per effect.
+fn effect_seeds(project: &mut Node) {
+    for effect in effects_mut(project) {
+        effect.set_default_field("seed", Node::Atom("0".to_string()));
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Parses a project file of any supported version and upgrades it to
+/// [`PROJECT_VERSION`]. Files without a version are treated as version 0.
+///
+/// Node ids missing from the file are generated, and duplicated ones
+/// replaced, see [`OmagariProject::ensure_unique_ids`].
+pub fn project_from_str(ron_string: &str) -> Result<OmagariProject, io::Error> {
+    let mut document = Document::parse(ron_string).map_err(invalid_data)?;
+    let version = match document.root.field("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| invalid_data(format!("invalid project file version {}", version)))?,
+        None => 0,
+    };
+    if version > PROJECT_VERSION as u64 {
+        return Err(invalid_data(format!(
+            "project file version {} is newer than the supported version {}, please update Omagari",
+            version, PROJECT_VERSION
+        )));
+    }
+
+    // Current files are read directly, so that errors point into them.
+    let mut project: OmagariProject = if version == PROJECT_VERSION as u64 {
+        ron::de::from_str(ron_string)
+    } else {
+        for migration in MIGRATIONS.iter() {
+            if migration.version as u64 > version {
+                (migration.apply)(&mut document.root);
+            }
+        }
+        ron::de::from_str(&document.to_string())
+    }
+    .map_err(invalid_data)?;
+    project.version = PROJECT_VERSION;
+    project.ensure_unique_ids();
+    Ok(project)
+}
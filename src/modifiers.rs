@@ -7,12 +7,21 @@ use serde::Serialize;
 use crate::editor_prelude::AppContext;
 use crate::expr::*;
 use crate::helpers::*;
+use crate::ids::NodeId;
 
 pub trait ModifierProducer<T>
 where
     T: bevy_hanabi::Modifier,
 {
-    fn produce(&self, writer: &bevy_hanabi::ExprWriter) -> T;
+    fn produce(&self, writer: &EffectWriter) -> T;
+}
+
+/// Gives access to the expressions of a modifier editor, labeled the same
+/// way as in the UI, along with the value type each one must produce.
+pub trait ExprSlots {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)>;
+    /// The same expressions as [`ExprSlots::exprs`], in the same order.
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor>;
 }
 
 pub trait RenderModifierProducer<T>
@@ -25,6 +34,8 @@ where
 
 #[derive(Serialize, Deserialize)]
 pub struct SetAttributeModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     attr: Attribute,
     attr_expr: ExprWriterEditor,
 }
@@ -33,10 +44,23 @@ impl SetAttributeModifierEditor {
     pub fn label() -> &'static str {
         "🗠 SetAttributeModifier"
     }
+    pub fn attr(&self) -> Attribute {
+        self.attr
+    }
+}
+
+impl ExprSlots for SetAttributeModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![("Value", &self.attr_expr, self.attr.value_type())]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.attr_expr]
+    }
 }
 
 impl ModifierProducer<SetAttributeModifier> for SetAttributeModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetAttributeModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetAttributeModifier {
         SetAttributeModifier {
             attribute: self.attr,
             value: self.attr_expr.produce(writer).expr(),
@@ -45,9 +69,9 @@ impl ModifierProducer<SetAttributeModifier> for SetAttributeModifierEditor {
 }
 
 impl UiProvider for SetAttributeModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
         let selected_text = attr_to_label(self.attr);
-        let id = ui.make_persistent_id(format!("{} {}", selected_text, index));
+        let id = ui.make_persistent_id(self.id);
         ui.vertical(|ui| {
             egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
                 .show_header(ui, |ui| {
@@ -60,15 +84,45 @@ impl UiProvider for SetAttributeModifierEditor {
                         });
                 })
                 .body(|ui| {
-                    self.attr_expr.draw_ui(app, ui, 1);
+                    self.attr_expr.draw_ui(app, ui, 1, attr_range(self.attr));
+                    if let Some(value_type) = self.attr_expr.value_type_in(&app.type_context())
+                        && value_type != self.attr.value_type()
+                    {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!(
+                                "⚠ {} expects {} but the expression is {}",
+                                selected_text,
+                                self.attr.value_type(),
+                                value_type
+                            ),
+                        );
+                    }
                 });
         });
     }
 }
 
+/// Range of the literals setting an attribute.
+fn attr_range(attr: Attribute) -> FieldRange {
+    match attr {
+        a if a == Attribute::COLOR || a == Attribute::HDR_COLOR => FieldRange::COLOR,
+        a if a == Attribute::AGE
+            || a == Attribute::LIFETIME
+            || a == Attribute::SIZE
+            || a == Attribute::SIZE3
+            || a == Attribute::SIZE2 =>
+        {
+            FieldRange::POSITIVE
+        }
+        _ => FieldRange::ANY,
+    }
+}
+
 impl Default for SetAttributeModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             attr: Attribute::ID,
             attr_expr: ExprWriterEditor::Float(0.0),
         }
@@ -77,6 +131,8 @@ impl Default for SetAttributeModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct InheritAttributeModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     attr: Attribute,
 }
 
@@ -86,8 +142,18 @@ impl InheritAttributeModifierEditor {
     }
 }
 
+impl ExprSlots for InheritAttributeModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        Vec::new()
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        Vec::new()
+    }
+}
+
 impl ModifierProducer<InheritAttributeModifier> for InheritAttributeModifierEditor {
-    fn produce(&self, _writer: &ExprWriter) -> InheritAttributeModifier {
+    fn produce(&self, _writer: &EffectWriter) -> InheritAttributeModifier {
         InheritAttributeModifier {
             attribute: self.attr,
         }
@@ -95,9 +161,9 @@ impl ModifierProducer<InheritAttributeModifier> for InheritAttributeModifierEdit
 }
 
 impl UiProvider for InheritAttributeModifierEditor {
-    fn draw_ui(&mut self, _app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
+    fn draw_ui(&mut self, _app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
         let selected_text = attr_to_label(self.attr);
-        let id = ui.make_persistent_id(format!("{} {}", selected_text, index));
+        let id = ui.make_persistent_id(self.id);
         ui.vertical(|ui| {
             egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
                 .show_header(ui, |ui| {
@@ -117,6 +183,7 @@ impl UiProvider for InheritAttributeModifierEditor {
 impl Default for InheritAttributeModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             attr: Attribute::ID,
         }
     }
@@ -124,6 +191,8 @@ impl Default for InheritAttributeModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct SetVelocityCircleModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     center_expr: ExprWriterEditor,
     axis_expr: ExprWriterEditor,
     speed_expr: ExprWriterEditor,
@@ -135,8 +204,38 @@ impl SetVelocityCircleModifierEditor {
     }
 }
 
+impl ExprSlots for SetVelocityCircleModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Origin",
+                &self.center_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Axis",
+                &self.axis_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Speed",
+                &self.speed_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![
+            &mut self.center_expr,
+            &mut self.axis_expr,
+            &mut self.speed_expr,
+        ]
+    }
+}
+
 impl ModifierProducer<SetVelocityCircleModifier> for SetVelocityCircleModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetVelocityCircleModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetVelocityCircleModifier {
         SetVelocityCircleModifier {
             center: self.center_expr.produce(writer).expr(),
             axis: self.axis_expr.produce(writer).expr(),
@@ -146,16 +245,16 @@ impl ModifierProducer<SetVelocityCircleModifier> for SetVelocityCircleModifierEd
 }
 
 impl UiProvider for SetVelocityCircleModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(1, "Origin", ui).show(ui, |ui| {
-                self.center_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Origin", "Origin", ui).show(ui, |ui| {
+                self.center_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(2, "Axis", ui).show(ui, |ui| {
-                self.axis_expr.draw_ui(app, ui, 2);
+            unique_collapsing(self.id.value(), "Axis", "Axis", ui).show(ui, |ui| {
+                self.axis_expr.draw_ui(app, ui, 2, FieldRange::ANY);
             });
-            unique_collapsing(3, "Speed", ui).show(ui, |ui| {
-                self.speed_expr.draw_ui(app, ui, 3);
+            unique_collapsing(self.id.value(), "Speed", "Speed", ui).show(ui, |ui| {
+                self.speed_expr.draw_ui(app, ui, 3, FieldRange::ANY);
             });
         });
     }
@@ -164,6 +263,7 @@ impl UiProvider for SetVelocityCircleModifierEditor {
 impl Default for SetVelocityCircleModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             center_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             axis_expr: ExprWriterEditor::Vec3(Vec3::Y),
             speed_expr: ExprWriterEditor::Float(0.5),
@@ -173,6 +273,8 @@ impl Default for SetVelocityCircleModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct SetVelocitySphereModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     center_expr: ExprWriterEditor,
     speed_expr: ExprWriterEditor,
 }
@@ -183,8 +285,29 @@ impl SetVelocitySphereModifierEditor {
     }
 }
 
+impl ExprSlots for SetVelocitySphereModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Origin",
+                &self.center_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Speed",
+                &self.speed_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.center_expr, &mut self.speed_expr]
+    }
+}
+
 impl ModifierProducer<SetVelocitySphereModifier> for SetVelocitySphereModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetVelocitySphereModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetVelocitySphereModifier {
         SetVelocitySphereModifier {
             center: self.center_expr.produce(writer).expr(),
             speed: self.speed_expr.produce(writer).expr(),
@@ -193,13 +316,13 @@ impl ModifierProducer<SetVelocitySphereModifier> for SetVelocitySphereModifierEd
 }
 
 impl UiProvider for SetVelocitySphereModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(1, "Origin", ui).show(ui, |ui| {
-                self.center_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Origin", "Origin", ui).show(ui, |ui| {
+                self.center_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(3, "Speed", ui).show(ui, |ui| {
-                self.speed_expr.draw_ui(app, ui, 3);
+            unique_collapsing(self.id.value(), "Speed", "Speed", ui).show(ui, |ui| {
+                self.speed_expr.draw_ui(app, ui, 3, FieldRange::ANY);
             });
         });
     }
@@ -208,6 +331,7 @@ impl UiProvider for SetVelocitySphereModifierEditor {
 impl Default for SetVelocitySphereModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             center_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             speed_expr: ExprWriterEditor::Float(0.5),
         }
@@ -216,6 +340,8 @@ impl Default for SetVelocitySphereModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct SetVelocityTangentModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     origin_expr: ExprWriterEditor,
     axis_expr: ExprWriterEditor,
     speed_expr: ExprWriterEditor,
@@ -227,8 +353,38 @@ impl SetVelocityTangentModifierEditor {
     }
 }
 
+impl ExprSlots for SetVelocityTangentModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Origin",
+                &self.origin_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Axis",
+                &self.axis_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Speed",
+                &self.speed_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![
+            &mut self.origin_expr,
+            &mut self.axis_expr,
+            &mut self.speed_expr,
+        ]
+    }
+}
+
 impl ModifierProducer<SetVelocityTangentModifier> for SetVelocityTangentModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetVelocityTangentModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetVelocityTangentModifier {
         SetVelocityTangentModifier {
             origin: self.origin_expr.produce(writer).expr(),
             axis: self.axis_expr.produce(writer).expr(),
@@ -238,16 +394,16 @@ impl ModifierProducer<SetVelocityTangentModifier> for SetVelocityTangentModifier
 }
 
 impl UiProvider for SetVelocityTangentModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(index, "Origin", ui).show(ui, |ui| {
-                self.origin_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Origin", "Origin", ui).show(ui, |ui| {
+                self.origin_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(index, "Axis", ui).show(ui, |ui| {
-                self.axis_expr.draw_ui(app, ui, 2);
+            unique_collapsing(self.id.value(), "Axis", "Axis", ui).show(ui, |ui| {
+                self.axis_expr.draw_ui(app, ui, 2, FieldRange::ANY);
             });
-            unique_collapsing(index, "Speed", ui).show(ui, |ui| {
-                self.speed_expr.draw_ui(app, ui, 3);
+            unique_collapsing(self.id.value(), "Speed", "Speed", ui).show(ui, |ui| {
+                self.speed_expr.draw_ui(app, ui, 3, FieldRange::ANY);
             });
         });
     }
@@ -256,18 +412,21 @@ impl UiProvider for SetVelocityTangentModifierEditor {
 impl Default for SetVelocityTangentModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             origin_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             axis_expr: ExprWriterEditor::Vec3(Vec3::Y),
-            speed_expr: ExprWriterEditor::Operator(Box::new(ExprOperatorEditor::Uniform(
+            speed_expr: ExprWriterEditor::operator(ExprOperatorEditor::Uniform(
                 ExprWriterEditor::Float(0.2),
                 ExprWriterEditor::Float(1.0),
-            ))),
+            )),
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SetPositionCircleModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     center_expr: ExprWriterEditor,
     axis_expr: ExprWriterEditor,
     radius_expr: ExprWriterEditor,
@@ -280,8 +439,38 @@ impl SetPositionCircleModifierEditor {
     }
 }
 
+impl ExprSlots for SetPositionCircleModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Center",
+                &self.center_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Axis",
+                &self.axis_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Radius",
+                &self.radius_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![
+            &mut self.center_expr,
+            &mut self.axis_expr,
+            &mut self.radius_expr,
+        ]
+    }
+}
+
 impl ModifierProducer<SetPositionCircleModifier> for SetPositionCircleModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetPositionCircleModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetPositionCircleModifier {
         SetPositionCircleModifier {
             center: self.center_expr.produce(writer).expr(),
             axis: self.axis_expr.produce(writer).expr(),
@@ -292,16 +481,16 @@ impl ModifierProducer<SetPositionCircleModifier> for SetPositionCircleModifierEd
 }
 
 impl UiProvider for SetPositionCircleModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(index, "Center", ui).show(ui, |ui| {
-                self.center_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Center", "Center", ui).show(ui, |ui| {
+                self.center_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(index, "Axis", ui).show(ui, |ui| {
-                self.axis_expr.draw_ui(app, ui, 2);
+            unique_collapsing(self.id.value(), "Axis", "Axis", ui).show(ui, |ui| {
+                self.axis_expr.draw_ui(app, ui, 2, FieldRange::ANY);
             });
-            unique_collapsing(index, "Radius", ui).show(ui, |ui| {
-                self.radius_expr.draw_ui(app, ui, 3);
+            unique_collapsing(self.id.value(), "Radius", "Radius", ui).show(ui, |ui| {
+                self.radius_expr.draw_ui(app, ui, 3, FieldRange::POSITIVE);
             });
             ui.horizontal(|ui| {
                 ui.radio_value(&mut self.dimension, ShapeDimension::Surface, "Surface");
@@ -314,6 +503,7 @@ impl UiProvider for SetPositionCircleModifierEditor {
 impl Default for SetPositionCircleModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             center_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             axis_expr: ExprWriterEditor::Vec3(Vec3::Y),
             radius_expr: ExprWriterEditor::Float(0.2),
@@ -324,6 +514,8 @@ impl Default for SetPositionCircleModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct SetPositionSphereModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     center_expr: ExprWriterEditor,
     radius_expr: ExprWriterEditor,
     dimension: ShapeDimension,
@@ -335,8 +527,29 @@ impl SetPositionSphereModifierEditor {
     }
 }
 
+impl ExprSlots for SetPositionSphereModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Center",
+                &self.center_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Radius",
+                &self.radius_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.center_expr, &mut self.radius_expr]
+    }
+}
+
 impl ModifierProducer<SetPositionSphereModifier> for SetPositionSphereModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> SetPositionSphereModifier {
+    fn produce(&self, writer: &EffectWriter) -> SetPositionSphereModifier {
         SetPositionSphereModifier {
             center: self.center_expr.produce(writer).expr(),
             radius: self.radius_expr.produce(writer).expr(),
@@ -346,13 +559,13 @@ impl ModifierProducer<SetPositionSphereModifier> for SetPositionSphereModifierEd
 }
 
 impl UiProvider for SetPositionSphereModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(index, "Center", ui).show(ui, |ui| {
-                self.center_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Center", "Center", ui).show(ui, |ui| {
+                self.center_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(index, "Radius", ui).show(ui, |ui| {
-                self.radius_expr.draw_ui(app, ui, 3);
+            unique_collapsing(self.id.value(), "Radius", "Radius", ui).show(ui, |ui| {
+                self.radius_expr.draw_ui(app, ui, 3, FieldRange::POSITIVE);
             });
             ui.horizontal(|ui| {
                 ui.radio_value(&mut self.dimension, ShapeDimension::Surface, "Surface");
@@ -365,6 +578,7 @@ impl UiProvider for SetPositionSphereModifierEditor {
 impl Default for SetPositionSphereModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             center_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             radius_expr: ExprWriterEditor::Float(0.2),
             dimension: ShapeDimension::Surface,
@@ -374,6 +588,8 @@ impl Default for SetPositionSphereModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct AccelModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     accel_expr: ExprWriterEditor,
 }
 
@@ -383,17 +599,31 @@ impl AccelModifierEditor {
     }
 }
 
+impl ExprSlots for AccelModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![(
+            "Accel",
+            &self.accel_expr,
+            ValueType::Vector(VectorType::VEC3F),
+        )]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.accel_expr]
+    }
+}
+
 impl ModifierProducer<AccelModifier> for AccelModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> AccelModifier {
+    fn produce(&self, writer: &EffectWriter) -> AccelModifier {
         AccelModifier::new(self.accel_expr.produce(writer).expr())
     }
 }
 
 impl UiProvider for AccelModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(index, "Origin", ui).show(ui, |ui| {
-                self.accel_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Origin", "Origin", ui).show(ui, |ui| {
+                self.accel_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
         });
     }
@@ -402,19 +632,22 @@ impl UiProvider for AccelModifierEditor {
 impl Default for AccelModifierEditor {
     fn default() -> Self {
         Self {
-            accel_expr: ExprWriterEditor::Operator(Box::new(ExprOperatorEditor::Subtract(
-                ExprWriterEditor::Operator(Box::new(ExprOperatorEditor::Multiply(
+            id: NodeId::new(),
+            accel_expr: ExprWriterEditor::operator(ExprOperatorEditor::Subtract(
+                ExprWriterEditor::operator(ExprOperatorEditor::Multiply(
                     ExprWriterEditor::RandVec3,
                     ExprWriterEditor::Vec3(Vec3::splat(2.0)),
-                ))),
+                )),
                 ExprWriterEditor::Vec3(Vec3::ONE),
-            ))),
+            )),
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LinearDragModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     drag_expr: ExprWriterEditor,
 }
 
@@ -424,8 +657,22 @@ impl LinearDragModifierEditor {
     }
 }
 
+impl ExprSlots for LinearDragModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![(
+            "Drag",
+            &self.drag_expr,
+            ValueType::Scalar(ScalarType::Float),
+        )]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.drag_expr]
+    }
+}
+
 impl ModifierProducer<LinearDragModifier> for LinearDragModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> LinearDragModifier {
+    fn produce(&self, writer: &EffectWriter) -> LinearDragModifier {
         LinearDragModifier {
             drag: self.drag_expr.produce(writer).expr(),
         }
@@ -433,10 +680,10 @@ impl ModifierProducer<LinearDragModifier> for LinearDragModifierEditor {
 }
 
 impl UiProvider for LinearDragModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(index, "Drag", ui).show(ui, |ui| {
-                self.drag_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Drag", "Drag", ui).show(ui, |ui| {
+                self.drag_expr.draw_ui(app, ui, 1, FieldRange::POSITIVE);
             });
         });
     }
@@ -445,6 +692,7 @@ impl UiProvider for LinearDragModifierEditor {
 impl Default for LinearDragModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             drag_expr: ExprWriterEditor::Placeholder,
         }
     }
@@ -452,6 +700,8 @@ impl Default for LinearDragModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct EmitSpawnEventModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     condition: EventEmitCondition,
     count_expr: ExprWriterEditor,
     child_index: u32,
@@ -461,10 +711,27 @@ impl EmitSpawnEventModifierEditor {
     pub fn label() -> &'static str {
         "⚡ EmitSpawnEvent"
     }
+    pub fn child_index(&self) -> u32 {
+        self.child_index
+    }
+}
+
+impl ExprSlots for EmitSpawnEventModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![(
+            "Count",
+            &self.count_expr,
+            ValueType::Scalar(ScalarType::Uint),
+        )]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![&mut self.count_expr]
+    }
 }
 
 impl ModifierProducer<EmitSpawnEventModifier> for EmitSpawnEventModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> EmitSpawnEventModifier {
+    fn produce(&self, writer: &EffectWriter) -> EmitSpawnEventModifier {
         EmitSpawnEventModifier {
             condition: self.condition,
             count: self.count_expr.produce(writer).expr(),
@@ -474,11 +741,11 @@ impl ModifierProducer<EmitSpawnEventModifier> for EmitSpawnEventModifierEditor {
 }
 
 impl UiProvider for EmitSpawnEventModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
             ui.horizontal(|ui| {
                 ui.label("Count:");
-                self.count_expr.draw_ui(app, ui, 1);
+                self.count_expr.draw_ui(app, ui, 1, FieldRange::POSITIVE);
             });
             ui.horizontal(|ui| {
                 ui.label("Child index:");
@@ -495,6 +762,7 @@ impl UiProvider for EmitSpawnEventModifierEditor {
 impl Default for EmitSpawnEventModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             condition: EventEmitCondition::OnDie,
             count_expr: ExprWriterEditor::U32(0),
             child_index: 0,
@@ -504,6 +772,8 @@ impl Default for EmitSpawnEventModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct ConformToSphereModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     origin_expr: ExprWriterEditor,
     radius_expr: ExprWriterEditor,
     influence_dist_expr: ExprWriterEditor,
@@ -517,8 +787,50 @@ impl ConformToSphereModifierEditor {
     }
 }
 
+impl ExprSlots for ConformToSphereModifierEditor {
+    fn exprs(&self) -> Vec<(&'static str, &ExprWriterEditor, ValueType)> {
+        vec![
+            (
+                "Origin",
+                &self.origin_expr,
+                ValueType::Vector(VectorType::VEC3F),
+            ),
+            (
+                "Radius",
+                &self.radius_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+            (
+                "Influence Distance",
+                &self.influence_dist_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+            (
+                "Attraction Acceleration",
+                &self.attraction_accel_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+            (
+                "Max Attraction Speed",
+                &self.max_attraction_speed_expr,
+                ValueType::Scalar(ScalarType::Float),
+            ),
+        ]
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut ExprWriterEditor> {
+        vec![
+            &mut self.origin_expr,
+            &mut self.radius_expr,
+            &mut self.influence_dist_expr,
+            &mut self.attraction_accel_expr,
+            &mut self.max_attraction_speed_expr,
+        ]
+    }
+}
+
 impl ModifierProducer<ConformToSphereModifier> for ConformToSphereModifierEditor {
-    fn produce(&self, writer: &ExprWriter) -> ConformToSphereModifier {
+    fn produce(&self, writer: &EffectWriter) -> ConformToSphereModifier {
         ConformToSphereModifier {
             origin: self.origin_expr.produce(writer).expr(),
             radius: self.radius_expr.produce(writer).expr(),
@@ -532,22 +844,43 @@ impl ModifierProducer<ConformToSphereModifier> for ConformToSphereModifierEditor
 }
 
 impl UiProvider for ConformToSphereModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(1, "Origin", ui).show(ui, |ui| {
-                self.origin_expr.draw_ui(app, ui, 1);
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Origin", "Origin", ui).show(ui, |ui| {
+                self.origin_expr.draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(2, "Radius", ui).show(ui, |ui| {
-                self.radius_expr.draw_ui(app, ui, 1);
+            unique_collapsing(self.id.value(), "Radius", "Radius", ui).show(ui, |ui| {
+                self.radius_expr.draw_ui(app, ui, 1, FieldRange::POSITIVE);
             });
-            unique_collapsing(3, "Influence Distance", ui).show(ui, |ui| {
-                self.influence_dist_expr.draw_ui(app, ui, 1);
+            unique_collapsing(
+                self.id.value(),
+                "Influence Distance",
+                "Influence Distance",
+                ui,
+            )
+            .show(ui, |ui| {
+                self.influence_dist_expr
+                    .draw_ui(app, ui, 1, FieldRange::POSITIVE);
             });
-            unique_collapsing(4, "Attraction Acceleration", ui).show(ui, |ui| {
-                self.attraction_accel_expr.draw_ui(app, ui, 1);
+            unique_collapsing(
+                self.id.value(),
+                "Attraction Acceleration",
+                "Attraction Acceleration",
+                ui,
+            )
+            .show(ui, |ui| {
+                self.attraction_accel_expr
+                    .draw_ui(app, ui, 1, FieldRange::ANY);
             });
-            unique_collapsing(5, "Max Attraction Speed", ui).show(ui, |ui| {
-                self.max_attraction_speed_expr.draw_ui(app, ui, 1);
+            unique_collapsing(
+                self.id.value(),
+                "Max Attraction Speed",
+                "Max Attraction Speed",
+                ui,
+            )
+            .show(ui, |ui| {
+                self.max_attraction_speed_expr
+                    .draw_ui(app, ui, 1, FieldRange::POSITIVE);
             });
         });
     }
@@ -556,6 +889,7 @@ impl UiProvider for ConformToSphereModifierEditor {
 impl Default for ConformToSphereModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             origin_expr: ExprWriterEditor::Vec3(Vec3::ZERO),
             radius_expr: ExprWriterEditor::Float(1.0),
             influence_dist_expr: ExprWriterEditor::Float(10.0),
@@ -567,6 +901,8 @@ impl Default for ConformToSphereModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct SizeOverLifetimeModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     gradient: GradientVec3Editor,
 }
 
@@ -588,9 +924,10 @@ impl RenderModifierProducer<SizeOverLifetimeModifier> for SizeOverLifetimeModifi
 }
 
 impl UiProvider for SizeOverLifetimeModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(1, "Gradient", ui).show(ui, |ui| self.gradient.draw_ui(app, ui, 1));
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Gradient", "Gradient", ui)
+                .show(ui, |ui| self.gradient.draw_ui(app, ui, 1));
         });
     }
 }
@@ -598,6 +935,7 @@ impl UiProvider for SizeOverLifetimeModifierEditor {
 impl Default for SizeOverLifetimeModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             gradient: GradientVec3Editor {
                 g: vec![(0.3, Vec3::splat(0.1)), (1.0, Vec3::splat(1.0))],
             },
@@ -607,6 +945,8 @@ impl Default for SizeOverLifetimeModifierEditor {
 
 #[derive(Serialize, Deserialize)]
 pub struct ColorOverLifetimeModifierEditor {
+    #[serde(default)]
+    pub(crate) id: NodeId,
     gradient: GradientVec4Editor,
     blend: Option<ColorBlendMode>,
     mask: Option<ColorBlendMask>,
@@ -624,18 +964,19 @@ impl RenderModifierProducer<ColorOverLifetimeModifier> for ColorOverLifetimeModi
     fn produce(&self) -> Self::Output {
         ColorOverLifetimeModifier {
             gradient: self.gradient.produce(),
-            blend: self.blend.unwrap_or(ColorBlendMode::default()),
-            mask: self.mask.unwrap_or(ColorBlendMask::default()),
+            blend: self.blend.unwrap_or_default(),
+            mask: self.mask.unwrap_or_default(),
         }
     }
 }
 
 impl UiProvider for ColorOverLifetimeModifierEditor {
-    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, index: u64) {
-        unique_collapsing(index, Self::label(), ui).show(ui, |ui| {
-            unique_collapsing(1, "Gradient", ui).show(ui, |ui| self.gradient.draw_ui(app, ui, 0));
-            unique_collapsing(2, "Blend", ui).show(ui, |ui| {
-                let mut blend = self.blend.unwrap_or(ColorBlendMode::default());
+    fn draw_ui(&mut self, app: &mut AppContext, ui: &mut egui::Ui, _index: u64) {
+        unique_collapsing(self.id.value(), Self::label(), Self::label(), ui).show(ui, |ui| {
+            unique_collapsing(self.id.value(), "Gradient", "Gradient", ui)
+                .show(ui, |ui| self.gradient.draw_ui(app, ui, 0));
+            unique_collapsing(self.id.value(), "Blend", "Blend", ui).show(ui, |ui| {
+                let mut blend = self.blend.unwrap_or_default();
                 ui.horizontal(|ui| {
                     ui.radio_value(&mut blend, ColorBlendMode::Add, "Add");
                     ui.radio_value(&mut blend, ColorBlendMode::Modulate, "Modulate");
@@ -643,8 +984,8 @@ impl UiProvider for ColorOverLifetimeModifierEditor {
                 });
                 self.blend = Some(blend);
             });
-            unique_collapsing(3, "Mask", ui).show(ui, |ui| {
-                let mut mask = self.mask.unwrap_or(ColorBlendMask::default());
+            unique_collapsing(self.id.value(), "Mask", "Mask", ui).show(ui, |ui| {
+                let mut mask = self.mask.unwrap_or_default();
                 ui.horizontal(|ui| {
                     ui.radio_value(&mut mask, ColorBlendMask::RGB, "RGB");
                     ui.radio_value(&mut mask, ColorBlendMask::RGBA, "RGBA");
@@ -658,6 +999,7 @@ impl UiProvider for ColorOverLifetimeModifierEditor {
 impl Default for ColorOverLifetimeModifierEditor {
     fn default() -> Self {
         Self {
+            id: NodeId::new(),
             gradient: GradientVec4Editor {
                 g: vec![
                     (0.0, Vec4::new(0.0, 4.0, 4.0, 0.0)),
@@ -698,11 +1040,11 @@ impl UiProvider for GradientVec3Editor {
                         return Some(list_command);
                     } else {
                         ui.label("t:");
-                        *t = ui_for_f32(ui, *t);
+                        *t = ui_for_f32(ui, *t, FieldRange::UNIT);
                         ui.label("vec3:");
-                        *v = ui_for_vec3(ui, *v);
-                        None
+                        *v = ui_for_vec3(ui, *v, FieldRange::POSITIVE);
                     }
+                    None
                 })
                 .inner
             {
@@ -741,9 +1083,9 @@ impl UiProvider for GradientVec4Editor {
                         return Some(list_command);
                     } else {
                         ui.label("t:");
-                        *t = ui_for_f32(ui, *t);
-                        ui.label("vec4:");
-                        *v = ui_for_vec4(ui, *v);
+                        *t = ui_for_f32(ui, *t, FieldRange::UNIT);
+                        ui.label("color:");
+                        *v = ui_for_hdr_vec4(ui, *v);
                     }
                     None
                 })
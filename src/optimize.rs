@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use crate::definitions::{ExprDefinition, find_definition};
+use crate::eval::{EvalInputs, EvalValue, expr_uses, is_random};
+use crate::expr::{ExprOperatorEditor, ExprWriterEditor};
+
+fn is_literal(expr: &ExprWriterEditor) -> bool {
+    matches!(
+        expr,
+        ExprWriterEditor::Float(_)
+            | ExprWriterEditor::U32(_)
+            | ExprWriterEditor::Bool(_)
+            | ExprWriterEditor::Vec2(_)
+            | ExprWriterEditor::Vec3(_)
+            | ExprWriterEditor::Vec4(_)
+            | ExprWriterEditor::Color(..)
+    )
+}
+
+/// The literal node for `value`, if there is one for its type and the value
+/// can be written in WGSL.
+pub fn literal_for(value: &EvalValue) -> Option<ExprWriterEditor> {
+    match value {
+        EvalValue::Float(v) if v.iter().any(|x| !x.is_finite()) => None,
+        EvalValue::Float(v) => match v.as_slice() {
+            [x] => Some(ExprWriterEditor::Float(*x)),
+            [_, _] => Some(ExprWriterEditor::Vec2(bevy::math::Vec2::from_slice(v))),
+            [_, _, _] => Some(ExprWriterEditor::Vec3(bevy::math::Vec3::from_slice(v))),
+            [_, _, _, _] => Some(ExprWriterEditor::Vec4(bevy::math::Vec4::from_slice(v))),
+            _ => None,
+        },
+        EvalValue::Uint(v) if v.len() == 1 => Some(ExprWriterEditor::U32(v[0])),
+        EvalValue::Bool(v) if v.len() == 1 => Some(ExprWriterEditor::Bool(v[0])),
+        _ => None,
+    }
+}
+
+/// Whether an expression can be shared by every place it appears in the
+/// effect. Random values must stay distinct, so subtrees drawing them are
+/// not.
+pub fn is_shareable(expr: &ExprWriterEditor, definitions: &[ExprDefinition]) -> bool {
+    !expr_uses(expr, definitions, &is_random)
+}
+
+/// Returns `expr` with its definitions inlined and its constant subtrees,
+/// such as `2.0 * 3.0`, replaced by literals. Cyclic and unknown references
+/// are kept, as are constants of types without a literal node and nodes that
+/// do not type check.
+pub fn fold_constants(expr: &ExprWriterEditor, definitions: &[ExprDefinition]) -> ExprWriterEditor {
+    fn fold(
+        expr: &ExprWriterEditor,
+        definitions: &[ExprDefinition],
+        inlining: &mut Vec<String>,
+    ) -> ExprWriterEditor {
+        match expr {
+            ExprWriterEditor::Ref(name) if !inlining.contains(name) => {
+                match find_definition(definitions, name) {
+                    Some(definition) => {
+                        inlining.push(name.clone());
+                        let folded = fold(&definition.expr, definitions, inlining);
+                        inlining.pop();
+                        folded
+                    }
+                    None => expr.clone(),
+                }
+            }
+            ExprWriterEditor::Operator(o, id) => {
+                let mut o = o.clone();
+                for operand in o.operands_mut() {
+                    *operand = fold(operand, definitions, inlining);
+                }
+                let constant = !matches!(
+                    *o,
+                    ExprOperatorEditor::Uniform(..)
+                        | ExprOperatorEditor::Attr(_)
+                        | ExprOperatorEditor::ParentAttr(_)
+                ) && o.operands().into_iter().all(is_literal)
+                    // Ill-typed nodes are left for validation to report.
+                    && matches!(o.check_types(), Ok(Some(_)));
+                let folded = ExprWriterEditor::Operator(o, *id);
+                if constant
+                    && let Ok(value) = folded.eval(&EvalInputs::default(), 0)
+                    && let Some(literal) = literal_for(&value)
+                {
+                    return literal;
+                }
+                folded
+            }
+            _ => expr.clone(),
+        }
+    }
+    fold(expr, definitions, &mut Vec::new())
+}
+
+/// Number of expression nodes of an effect before and after optimization.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct NodeCounts {
+    /// Nodes of the trees as written, with definitions inlined.
+    pub before: usize,
+    /// Nodes left once constants are folded and identical subtrees shared.
+    pub after: usize,
+}
+
+impl NodeCounts {
+    pub fn of<'a>(
+        exprs: impl IntoIterator<Item = &'a ExprWriterEditor>,
+        definitions: &[ExprDefinition],
+    ) -> Self {
+        fn count(
+            expr: &ExprWriterEditor,
+            definitions: &[ExprDefinition],
+            inlining: &mut Vec<String>,
+        ) -> usize {
+            match expr {
+                ExprWriterEditor::Ref(name) if !inlining.contains(name) => {
+                    match find_definition(definitions, name) {
+                        Some(definition) => {
+                            inlining.push(name.clone());
+                            let n = count(&definition.expr, definitions, inlining);
+                            inlining.pop();
+                            n
+                        }
+                        None => 1,
+                    }
+                }
+                ExprWriterEditor::Operator(o, _) => {
+                    1 + o
+                        .operands()
+                        .into_iter()
+                        .map(|operand| count(operand, definitions, inlining))
+                        .sum::<usize>()
+                }
+                _ => 1,
+            }
+        }
+
+        /// Counts the nodes not shared with a subtree in `seen`, like
+        /// [`crate::expr::EffectWriter`] does when producing.
+        fn count_unique(
+            expr: &ExprWriterEditor,
+            definitions: &[ExprDefinition],
+            seen: &mut HashSet<String>,
+        ) -> usize {
+            if is_shareable(expr, definitions) && !seen.insert(expr.to_string()) {
+                return 0;
+            }
+            match expr {
+                ExprWriterEditor::Operator(o, _) => {
+                    1 + o
+                        .operands()
+                        .into_iter()
+                        .map(|operand| count_unique(operand, definitions, seen))
+                        .sum::<usize>()
+                }
+                _ => 1,
+            }
+        }
+
+        let mut counts = NodeCounts::default();
+        let mut seen = HashSet::new();
+        for expr in exprs {
+            counts.before += count(expr, definitions, &mut Vec::new());
+            let folded = fold_constants(expr, definitions);
+            counts.after += count_unique(&folded, definitions, &mut seen);
+        }
+        counts
+    }
+}
@@ -0,0 +1,279 @@
+use bevy::prelude::*;
+use bevy_egui::*;
+use bevy_hanabi::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::expr::ExprWriterEditor;
+use crate::helpers::*;
+use crate::ids::NodeId;
+use crate::syntax::is_identifier;
+
+/// Value of an effect property, which also determines its type.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum EffectPropertyValue {
+    Float(f32),
+    U32(u32),
+    Bool(bool),
+    Vec3(Vec3),
+    Vec4(Vec4),
+}
+
+impl EffectPropertyValue {
+    /// One default value of each supported type, as offered by the editor.
+    pub const ALL: [EffectPropertyValue; 5] = [
+        EffectPropertyValue::Float(0.0),
+        EffectPropertyValue::U32(0),
+        EffectPropertyValue::Bool(false),
+        EffectPropertyValue::Vec3(Vec3::ZERO),
+        EffectPropertyValue::Vec4(Vec4::ZERO),
+    ];
+
+    pub fn value_type(&self) -> ValueType {
+        Value::from(*self).value_type()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            EffectPropertyValue::Float(_) => "Float",
+            EffectPropertyValue::U32(_) => "U32",
+            EffectPropertyValue::Bool(_) => "Bool",
+            EffectPropertyValue::Vec3(_) => "Vec3",
+            EffectPropertyValue::Vec4(_) => "Vec4",
+        }
+    }
+
+    fn same_type(&self, other: &EffectPropertyValue) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl From<EffectPropertyValue> for Value {
+    fn from(value: EffectPropertyValue) -> Self {
+        match value {
+            EffectPropertyValue::Float(v) => v.into(),
+            EffectPropertyValue::U32(v) => v.into(),
+            EffectPropertyValue::Bool(v) => v.into(),
+            EffectPropertyValue::Vec3(v) => v.into(),
+            EffectPropertyValue::Vec4(v) => v.into(),
+        }
+    }
+}
+
+fn default_range() -> (f32, f32) {
+    (0.0, 1.0)
+}
+
+/// A named parameter of an effect that game code can change at runtime
+/// through hanabi's `EffectProperties`, without rebuilding the effect.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct EffectProperty {
+    pub name: String,
+    pub default: EffectPropertyValue,
+    /// Range of the preview sliders in the editor.
+    #[serde(default = "default_range")]
+    pub range: (f32, f32),
+}
+
+impl EffectProperty {
+    pub fn new(name: &str, default: EffectPropertyValue) -> Self {
+        Self {
+            name: name.to_string(),
+            default,
+            range: default_range(),
+        }
+    }
+}
+
+/// Property names end up as WGSL struct fields, so they must be identifiers.
+pub fn is_valid_property_name(name: &str) -> bool {
+    is_identifier(name)
+}
+
+/// Values of the properties of the previewed effects, changed from the
+/// editor without touching the project.
+#[derive(Default)]
+pub struct PreviewProperties {
+    values: Vec<(String, String, EffectPropertyValue)>,
+    changed: bool,
+}
+
+impl PreviewProperties {
+    pub fn get(&self, effect: &str, property: &str) -> Option<EffectPropertyValue> {
+        self.values
+            .iter()
+            .find(|(e, p, _)| e == effect && p == property)
+            .map(|(_, _, v)| *v)
+    }
+
+    pub fn set(&mut self, effect: &str, property: &str, value: EffectPropertyValue) {
+        match self
+            .values
+            .iter_mut()
+            .find(|(e, p, _)| e == effect && p == property)
+        {
+            Some((_, _, v)) => *v = value,
+            None => self
+                .values
+                .push((effect.to_string(), property.to_string(), value)),
+        }
+        self.changed = true;
+    }
+
+    /// The previewed value of `property`, or its default when it has none of
+    /// the declared type.
+    pub fn value(&self, effect: &str, property: &EffectProperty) -> EffectPropertyValue {
+        self.get(effect, &property.name)
+            .filter(|v| v.same_type(&property.default))
+            .unwrap_or(property.default)
+    }
+
+    /// Moves the previewed value of a property that was renamed.
+    pub fn rename(&mut self, effect: &str, from: &str, to: &str) {
+        for (e, p, _) in self.values.iter_mut() {
+            if e == effect && p == from {
+                *p = to.to_string();
+            }
+        }
+    }
+
+    /// Returns whether any value changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// The `EffectProperties` to put on a previewed instance of an effect,
+    /// skipping values whose type no longer matches the declared property.
+    pub fn effect_properties(&self, effect: &str, declared: &[EffectProperty]) -> EffectProperties {
+        EffectProperties::default().with_properties(declared.iter().filter_map(|property| {
+            self.get(effect, &property.name)
+                .filter(|v| v.same_type(&property.default))
+                .map(|v| (property.name.clone(), v.into()))
+        }))
+    }
+}
+
+fn ui_for_property_value(ui: &mut egui::Ui, value: &mut EffectPropertyValue, range: (f32, f32)) {
+    match value {
+        EffectPropertyValue::Float(v) => {
+            ui.add(egui::Slider::new(v, range.0..=range.1));
+        }
+        EffectPropertyValue::U32(v) => {
+            let low = range.0.max(0.0) as u32;
+            let high = (range.1.max(0.0) as u32).max(low);
+            ui.add(egui::Slider::new(v, low..=high));
+        }
+        EffectPropertyValue::Bool(v) => {
+            ui.checkbox(v, "");
+        }
+        EffectPropertyValue::Vec3(v) => {
+            for c in v.as_mut() {
+                ui.add(egui::DragValue::new(c).speed(0.01).range(range.0..=range.1));
+            }
+        }
+        EffectPropertyValue::Vec4(v) => {
+            for c in v.as_mut() {
+                ui.add(egui::DragValue::new(c).speed(0.01).range(range.0..=range.1));
+            }
+        }
+    }
+}
+
+/// Calls `f` with the names of the properties read by `expr`, to change
+/// them.
+pub fn for_each_property_mut(expr: &mut ExprWriterEditor, f: &mut impl FnMut(&mut String)) {
+    match expr {
+        ExprWriterEditor::Property(name) => f(name),
+        ExprWriterEditor::Operator(o, _) => {
+            for operand in o.operands_mut() {
+                for_each_property_mut(operand, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Declaration list of the properties of `effect`, with a live preview
+/// value next to each one. Returns the old and new name of a property the
+/// user renamed, to be applied with [`EffectEditor::rename_property`].
+///
+/// [`EffectEditor::rename_property`]: crate::effect::EffectEditor::rename_property
+pub fn ui_for_properties(
+    ui: &mut egui::Ui,
+    effect_id: NodeId,
+    effect: &str,
+    properties: &mut Vec<EffectProperty>,
+    preview: &mut PreviewProperties,
+) -> Option<(String, String)> {
+    let mut rename = None;
+    unique_collapsing(effect_id.value(), "Properties", "Properties", ui).show(ui, |ui| {
+        let mut remove = None;
+        for (index, property) in properties.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    let delete_button = ui.button("❌").on_hover_text("Right-click to delete");
+                    if delete_button.secondary_clicked() {
+                        remove = Some(index);
+                    }
+                    let mut name = property.name.clone();
+                    ui.add(egui::TextEdit::singleline(&mut name).desired_width(100.0))
+                        .on_hover_text("Property names are identifiers, e.g. 'spark_color'");
+                    if name != property.name {
+                        rename = Some((property.name.clone(), name));
+                    }
+                    egui::ComboBox::from_id_salt("type")
+                        .selected_text(property.default.type_name())
+                        .show_ui(ui, |ui| {
+                            for value in EffectPropertyValue::ALL {
+                                if ui
+                                    .selectable_label(
+                                        property.default.same_type(&value),
+                                        value.type_name(),
+                                    )
+                                    .clicked()
+                                    && !property.default.same_type(&value)
+                                {
+                                    property.default = value;
+                                }
+                            }
+                        });
+                    ui.label("Range:");
+                    property.range.0 = ui_for_f32(ui, property.range.0, FieldRange::ANY);
+                    property.range.1 = ui_for_f32(ui, property.range.1, FieldRange::ANY);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Default:");
+                    ui_for_property_value(ui, &mut property.default, property.range);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Preview:");
+                    let mut value = preview.value(effect, property);
+                    let before = value;
+                    ui_for_property_value(ui, &mut value, property.range);
+                    if value != before {
+                        preview.set(effect, &property.name, value);
+                    }
+                    if ui.button("↺").on_hover_text("Reset to default").clicked() {
+                        preview.set(effect, &property.name, property.default);
+                    }
+                });
+                ui.separator();
+            });
+        }
+        if let Some(index) = remove {
+            properties.remove(index);
+        }
+        if ui.button("➕ Property").clicked() {
+            let mut n = properties.len();
+            let name = loop {
+                let name = format!("property{}", n);
+                if !properties.iter().any(|p| p.name == name) {
+                    break name;
+                }
+                n += 1;
+            };
+            properties.push(EffectProperty::new(&name, EffectPropertyValue::Float(0.0)));
+        }
+    });
+    rename
+}
@@ -0,0 +1,255 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_egui::*;
+use bevy_hanabi::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::expr::EffectWriter;
+use crate::helpers::*;
+
+/// How the values of a [`RandomRange`] spread over the range.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Distribution {
+    /// Every value of the range is equally likely.
+    #[default]
+    Uniform,
+    /// Normal distribution centred in the range, which spans ±3 standard
+    /// deviations. The rare values outside are clamped to the range.
+    Normal,
+}
+
+impl Distribution {
+    fn label(self) -> &'static str {
+        match self {
+            Distribution::Uniform => "uniform",
+            Distribution::Normal => "normal",
+        }
+    }
+}
+
+/// Shorthand for the random values effects use most, with constant bounds
+/// instead of operand subtrees. Expanded into Hanabi primitives when the
+/// effect is produced.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum RandomRange {
+    /// A float between two bounds.
+    Float(Distribution, f32, f32),
+    /// A vector whose components are drawn independently between the
+    /// components of two bounds.
+    Vec3(Distribution, Vec3, Vec3),
+    /// A colour between two HDR colours, stored like
+    /// [`ExprWriterEditor::Color`](crate::expr::ExprWriterEditor::Color).
+    /// A single random value blends them, so hues stay between the two.
+    Color(Distribution, (Vec4, f32), (Vec4, f32)),
+    /// A point on the surface of the unit sphere.
+    OnSphere,
+    /// A point inside the unit disc, as a `vec2`.
+    InDisc,
+}
+
+impl RandomRange {
+    /// Ranges offered by the "+" menu.
+    pub(crate) fn menu_items() -> [RandomRange; 5] {
+        [
+            RandomRange::Float(Distribution::Uniform, 0.0, 1.0),
+            RandomRange::Vec3(Distribution::Uniform, Vec3::splat(-1.0), Vec3::ONE),
+            RandomRange::Color(
+                Distribution::Uniform,
+                (Vec4::ONE, 1.0),
+                (Vec4::new(0.0, 0.0, 0.0, 1.0), 1.0),
+            ),
+            RandomRange::OnSphere,
+            RandomRange::InDisc,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RandomRange::Float(..) => "Random Float",
+            RandomRange::Vec3(..) => "Random Vec3",
+            RandomRange::Color(..) => "Random Color",
+            RandomRange::OnSphere => "Random On Sphere",
+            RandomRange::InDisc => "Random In Disc",
+        }
+    }
+
+    pub fn distribution(&self) -> Option<Distribution> {
+        match self {
+            RandomRange::Float(d, ..) | RandomRange::Vec3(d, ..) | RandomRange::Color(d, ..) => {
+                Some(*d)
+            }
+            RandomRange::OnSphere | RandomRange::InDisc => None,
+        }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            RandomRange::Float(..) => ValueType::Scalar(ScalarType::Float),
+            RandomRange::Vec3(..) | RandomRange::OnSphere => ValueType::Vector(VectorType::VEC3F),
+            RandomRange::Color(..) => ValueType::Vector(VectorType::VEC4F),
+            RandomRange::InDisc => ValueType::Vector(VectorType::VEC2F),
+        }
+    }
+
+    pub fn produce(&self, writer: &EffectWriter) -> WriterExpr {
+        match *self {
+            RandomRange::Float(distribution, a, b) => {
+                produce_range(writer, distribution, a.min(b), a.max(b))
+            }
+            RandomRange::Vec3(distribution, a, b) => {
+                produce_range(writer, distribution, a.min(b), a.max(b))
+            }
+            RandomRange::Color(distribution, (a, ia), (b, ib)) => {
+                let t = produce_range(writer, distribution, 0.0, 1.0);
+                writer
+                    .lit(apply_intensity(a, ia))
+                    .mix(writer.lit(apply_intensity(b, ib)), t)
+            }
+            // Random builtins are stored in a variable, so reusing them
+            // reuses the value.
+            RandomRange::OnSphere => {
+                let z = writer
+                    .rand(ScalarType::Float)
+                    .mul(writer.lit(2.0))
+                    .sub(writer.lit(1.0));
+                let angle = writer.rand(ScalarType::Float).mul(writer.lit(TAU));
+                let radius = writer.lit(1.0).sub(z.clone().mul(z.clone())).sqrt();
+                radius
+                    .clone()
+                    .mul(angle.clone().cos())
+                    .vec3(radius.mul(angle.sin()), z)
+            }
+            RandomRange::InDisc => {
+                let radius = writer.rand(ScalarType::Float).sqrt();
+                let angle = writer.rand(ScalarType::Float).mul(writer.lit(TAU));
+                radius
+                    .clone()
+                    .mul(angle.clone().cos())
+                    .vec2(radius.mul(angle.sin()))
+            }
+        }
+    }
+
+    /// Draws a value on the CPU, following what [`RandomRange::produce`]
+    /// computes on the GPU.
+    pub(crate) fn sample(&self, rng: &mut impl Rng) -> Vec<f32> {
+        match *self {
+            RandomRange::Float(distribution, a, b) => {
+                sample_range(rng, distribution, &[a.min(b)], &[a.max(b)])
+            }
+            RandomRange::Vec3(distribution, a, b) => sample_range(
+                rng,
+                distribution,
+                &a.min(b).to_array(),
+                &a.max(b).to_array(),
+            ),
+            RandomRange::Color(distribution, (a, ia), (b, ib)) => {
+                let t = sample_range(rng, distribution, &[0.0], &[1.0])[0];
+                apply_intensity(a, ia)
+                    .lerp(apply_intensity(b, ib), t)
+                    .to_array()
+                    .to_vec()
+            }
+            RandomRange::OnSphere => {
+                let z = rng.random::<f32>() * 2.0 - 1.0;
+                let angle = rng.random::<f32>() * TAU;
+                let radius = (1.0 - z * z).sqrt();
+                vec![radius * angle.cos(), radius * angle.sin(), z]
+            }
+            RandomRange::InDisc => {
+                let radius = rng.random::<f32>().sqrt();
+                let angle = rng.random::<f32>() * TAU;
+                vec![radius * angle.cos(), radius * angle.sin()]
+            }
+        }
+    }
+
+    /// Draws the bounds, which take values in `range`. Colours keep their own
+    /// picker.
+    pub(crate) fn draw_ui(&mut self, ui: &mut egui::Ui, index: u64, range: FieldRange) {
+        if let RandomRange::Float(distribution, ..)
+        | RandomRange::Vec3(distribution, ..)
+        | RandomRange::Color(distribution, ..) = self
+        {
+            egui::ComboBox::from_id_salt(("distribution", index))
+                .selected_text(distribution.label())
+                .show_ui(ui, |ui| {
+                    for d in [Distribution::Uniform, Distribution::Normal] {
+                        ui.selectable_value(distribution, d, d.label());
+                    }
+                });
+        }
+        match self {
+            RandomRange::Float(_, a, b) => {
+                *a = ui_for_f32(ui, *a, range);
+                ui.label("to");
+                *b = ui_for_f32(ui, *b, range);
+            }
+            RandomRange::Vec3(_, a, b) => {
+                ui.vertical(|ui| {
+                    *a = ui_for_vec3(ui, *a, range);
+                    *b = ui_for_vec3(ui, *b, range);
+                });
+            }
+            RandomRange::Color(_, (a, ia), (b, ib)) => {
+                ui.vertical(|ui| {
+                    (*a, *ia) = ui_for_hdr_color(ui, *a, *ia, FieldRange::INTENSITY);
+                    (*b, *ib) = ui_for_hdr_color(ui, *b, *ib, FieldRange::INTENSITY);
+                });
+            }
+            RandomRange::OnSphere | RandomRange::InDisc => {}
+        }
+    }
+}
+
+/// A value between `min` and `max`, which are floats or vectors of floats.
+fn produce_range<T>(writer: &EffectWriter, distribution: Distribution, min: T, max: T) -> WriterExpr
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>,
+    Value: From<T>,
+{
+    match distribution {
+        Distribution::Uniform => writer.lit(min).uniform(writer.lit(max)),
+        Distribution::Normal => writer
+            .lit((min + max) * 0.5)
+            .normal(writer.lit((max - min) * (1.0 / 6.0)))
+            .max(writer.lit(min))
+            .min(writer.lit(max)),
+    }
+}
+
+/// Components between those of `min` and `max`, drawn like Hanabi's
+/// `rand_uniform` and `rand_normal` do.
+fn sample_range(
+    rng: &mut impl Rng,
+    distribution: Distribution,
+    min: &[f32],
+    max: &[f32],
+) -> Vec<f32> {
+    match distribution {
+        Distribution::Uniform => min
+            .iter()
+            .zip(max)
+            .map(|(min, max)| min + rng.random::<f32>() * (max - min))
+            .collect(),
+        Distribution::Normal => {
+            // One radius for all the components, as in Hanabi. `1 - u` is
+            // never 0, so the log is finite.
+            let radius = (-2.0 * (1.0 - rng.random::<f32>()).ln()).sqrt();
+            min.iter()
+                .zip(max)
+                .map(|(min, max)| {
+                    let mean = (min + max) * 0.5;
+                    let std_dev = (max - min) / 6.0;
+                    let value = mean + std_dev * radius * (TAU * rng.random::<f32>()).cos();
+                    value.max(*min).min(*max)
+                })
+                .collect()
+        }
+    }
+}
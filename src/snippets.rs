@@ -0,0 +1,250 @@
+use bevy_egui::*;
+use ron::ser::PrettyConfig;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::OmagariProject;
+use crate::expr::{ExprOperatorEditor, ExprWriterEditor};
+use crate::helpers::*;
+
+/// A named expression that can be inserted into any empty expression slot.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExprSnippet {
+    pub name: String,
+    pub expr: ExprWriterEditor,
+}
+
+impl ExprSnippet {
+    pub fn new(name: &str, expr: ExprWriterEditor) -> Self {
+        Self {
+            name: name.to_string(),
+            expr,
+        }
+    }
+}
+
+/// Snippets shipped with Omagari.
+pub fn builtin_snippets() -> Vec<ExprSnippet> {
+    let op = ExprWriterEditor::operator;
+    vec![ExprSnippet::new(
+        "Random Normalized Vector",
+        op(ExprOperatorEditor::Normalized(op(
+            ExprOperatorEditor::Subtract(
+                op(ExprOperatorEditor::Multiply(
+                    ExprWriterEditor::RandVec3,
+                    ExprWriterEditor::Float(2.0),
+                )),
+                ExprWriterEditor::Float(1.0),
+            ),
+        ))),
+    )]
+}
+
+/// Returns `name`, suffixed if it is already taken by one of `snippets`.
+pub fn unique_snippet_name(snippets: &[ExprSnippet], name: &str) -> String {
+    let mut unique = name.to_string();
+    let mut n = 1;
+    while snippets.iter().any(|s| s.name == unique) {
+        n += 1;
+        unique = format!("{} {}", name, n);
+    }
+    unique
+}
+
+/// Contents of the user-level snippet file, shared by all projects.
+#[derive(Serialize, Deserialize, Default)]
+pub struct UserSnippets {
+    pub snippets: Vec<ExprSnippet>,
+}
+
+impl UserSnippets {
+    /// Location of the user snippet file, `omagari/snippets.ron` in the user
+    /// configuration directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("omagari").join("snippets.ron"))
+    }
+
+    /// Loads the user snippets. A missing file is an empty library.
+    pub fn load<P: AsRef<Path>>(p: P) -> Result<Self, io::Error> {
+        match std::fs::read_to_string(p) {
+            Ok(ron_string) => ron::de::from_str(&ron_string)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, p: P) -> Result<(), io::Error> {
+        if let Some(dir) = p.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let ron_string =
+            ron::ser::to_string_pretty(self, PrettyConfig::new().new_line("\n".to_string()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut file = std::fs::File::create(p)?;
+        file.write_all(ron_string.as_bytes())
+    }
+}
+
+/// The snippets offered by the expression editor: those of the project, of
+/// the user file and the built-in ones.
+#[derive(Default)]
+pub struct SnippetLibrary {
+    /// Copy of the project snippets, refreshed every frame.
+    pub project: Vec<ExprSnippet>,
+    pub user: UserSnippets,
+    pub user_path: Option<PathBuf>,
+    user_changed: bool,
+    saved: Vec<ExprWriterEditor>,
+}
+
+impl SnippetLibrary {
+    /// Loads the user snippets from their default location.
+    pub fn load_user() -> Self {
+        let user_path = UserSnippets::default_path();
+        let user = match &user_path {
+            Some(path) => UserSnippets::load(path).unwrap_or_else(|e| {
+                bevy::log::warn!("Failed loading snippets from {}: {}", path.display(), e);
+                UserSnippets::default()
+            }),
+            None => UserSnippets::default(),
+        };
+        Self {
+            user,
+            user_path,
+            ..Default::default()
+        }
+    }
+
+    /// Queues an expression to be added to the project snippets.
+    pub fn save(&mut self, expr: ExprWriterEditor) {
+        self.saved.push(expr);
+    }
+
+    /// Adds the expressions queued with [`SnippetLibrary::save`] to the
+    /// project, named after their text.
+    pub fn apply_saved(&mut self, project: &mut OmagariProject) {
+        for expr in self.saved.drain(..) {
+            let text = expr.to_string();
+            let mut name: String = text.chars().take(32).collect();
+            if name.len() < text.len() {
+                name.push('…');
+            }
+            let name = unique_snippet_name(&project.snippets, &name);
+            project.snippets.push(ExprSnippet::new(&name, expr));
+        }
+    }
+
+    fn save_user(&self) {
+        if let Some(path) = &self.user_path
+            && let Err(e) = self.user.save(path)
+        {
+            bevy::log::error!("Failed saving snippets to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Menu listing every snippet, returning a copy of the one picked with
+    /// new node ids.
+    pub fn menu_ui(&self, ui: &mut egui::Ui) -> Option<ExprWriterEditor> {
+        let mut picked = None;
+        let builtin = builtin_snippets();
+        let groups = [
+            ("Project", &self.project),
+            ("User", &self.user.snippets),
+            ("Built-in", &builtin),
+        ];
+        for (label, snippets) in groups {
+            if snippets.is_empty() {
+                continue;
+            }
+            ui.menu_button(label, |ui| {
+                for snippet in snippets.iter() {
+                    if ui
+                        .button(&snippet.name)
+                        .on_hover_text(snippet.expr.to_string())
+                        .clicked()
+                    {
+                        let mut expr = snippet.expr.clone();
+                        expr.renew_ids();
+                        picked = Some(expr);
+                        ui.close_menu();
+                    }
+                }
+            });
+        }
+        picked
+    }
+}
+
+/// Rows of a snippet list, with rename, reorder and delete controls and a
+/// button copying a snippet to the other library.
+fn ui_for_snippet_list(
+    ui: &mut egui::Ui,
+    snippets: &mut Vec<ExprSnippet>,
+    transfer: &str,
+) -> (bool, Option<ExprSnippet>) {
+    let n_snippets = snippets.len();
+    let mut changed = false;
+    let mut command = None;
+    let mut transferred = None;
+    for (index, snippet) in snippets.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            if let Some(list_command) = ui_for_list_item(ui, index, n_snippets) {
+                command = Some(list_command);
+            }
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut snippet.name).desired_width(160.0))
+                .on_hover_text(snippet.expr.to_string())
+                .changed();
+            if ui.button(transfer).clicked() {
+                transferred = Some(snippet.clone());
+            }
+        });
+    }
+    if let Some(command) = command {
+        command.apply(snippets);
+        changed = true;
+    }
+    (changed, transferred)
+}
+
+pub fn ui_for_snippet_library(
+    project: &mut OmagariProject,
+    library: &mut SnippetLibrary,
+    ui: &mut egui::Ui,
+) {
+    egui::CollapsingHeader::new("Snippets")
+        .id_salt("snippet library")
+        .show(ui, |ui| {
+            ui.label("Project");
+            let (_, to_user) = ui_for_snippet_list(ui, &mut project.snippets, "⤴ User");
+            ui.separator();
+            ui.label("User");
+            let (user_changed, to_project) =
+                ui_for_snippet_list(ui, &mut library.user.snippets, "⤵ Project");
+            library.user_changed |= user_changed;
+            if let Some(snippet) = to_user {
+                let name = unique_snippet_name(&library.user.snippets, &snippet.name);
+                library
+                    .user
+                    .snippets
+                    .push(ExprSnippet::new(&name, snippet.expr));
+                library.user_changed = true;
+            }
+            if let Some(snippet) = to_project {
+                let name = unique_snippet_name(&project.snippets, &snippet.name);
+                project.snippets.push(ExprSnippet::new(&name, snippet.expr));
+            }
+            // Renames are written once editing ends, not on every keystroke.
+            if library.user_changed && !ui.ctx().wants_keyboard_input() {
+                library.user_changed = false;
+                library.save_user();
+            }
+        });
+}
@@ -0,0 +1,594 @@
+use std::fmt;
+use std::sync::LazyLock;
+
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use regex::Regex;
+
+use crate::expr::{ExprOperatorEditor, ExprWriterEditor};
+use crate::random::{Distribution, RandomRange};
+
+/// Whether `name` can be written as the argument of `prop()` and `ref()`.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_"
+}
+
+/// Numbers (`1`, `2.5`, `1e-3`, `7u`), identifiers and operators.
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:(?P<number>(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?u?)|(?P<ident>[A-Za-z_][A-Za-z0-9_]*)|(?P<punct>&&|\|\||==|!=|<=|>=|[-+*/%<>!(),.]))",
+    )
+    .unwrap()
+});
+
+/// A syntax error, located by the character column it was found at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// 0-based character offset into the parsed text.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.position + 1, self.message)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TokenKind {
+    Number,
+    Ident,
+    Punct,
+}
+
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    position: usize,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    let mut position = 0;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let trimmed = rest.trim_start();
+        position += rest[..rest.len() - trimmed.len()].chars().count();
+        offset += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            break;
+        }
+        let Some(captures) = TOKEN.captures(trimmed) else {
+            return Err(ParseError {
+                position,
+                message: format!("unexpected '{}'", trimmed.chars().next().unwrap()),
+            });
+        };
+        let (kind, m) = if let Some(m) = captures.name("number") {
+            (TokenKind::Number, m)
+        } else if let Some(m) = captures.name("ident") {
+            (TokenKind::Ident, m)
+        } else {
+            (TokenKind::Punct, captures.name("punct").unwrap())
+        };
+        tokens.push(Token {
+            kind,
+            text: m.as_str(),
+            position,
+        });
+        offset += m.end();
+        position += m.as_str().chars().count();
+    }
+    Ok(tokens)
+}
+
+/// Binary operators by precedence level, lowest first.
+const BINARY_LEVELS: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["<", "<=", ">", ">=", "==", "!="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+const UNARY_PRECEDENCE: u8 = BINARY_LEVELS.len() as u8 + 1;
+const ATOM_PRECEDENCE: u8 = UNARY_PRECEDENCE + 1;
+
+fn binary_operator(symbol: &str, a: ExprWriterEditor, b: ExprWriterEditor) -> ExprOperatorEditor {
+    match symbol {
+        "||" => ExprOperatorEditor::Or(a, b),
+        "&&" => ExprOperatorEditor::And(a, b),
+        "<" => ExprOperatorEditor::Lt(a, b),
+        "<=" => ExprOperatorEditor::Le(a, b),
+        ">" => ExprOperatorEditor::Gt(a, b),
+        ">=" => ExprOperatorEditor::Ge(a, b),
+        "==" => ExprOperatorEditor::Eq(a, b),
+        "!=" => ExprOperatorEditor::Neq(a, b),
+        "+" => ExprOperatorEditor::Add(a, b),
+        "-" => ExprOperatorEditor::Subtract(a, b),
+        "*" => ExprOperatorEditor::Multiply(a, b),
+        "/" => ExprOperatorEditor::Div(a, b),
+        "%" => ExprOperatorEditor::Rem(a, b),
+        _ => unreachable!(),
+    }
+}
+
+/// Infix symbol and precedence of the operators printed as `a op b`.
+fn infix(op: &ExprOperatorEditor) -> Option<(&'static str, u8)> {
+    let symbol = match op {
+        ExprOperatorEditor::Or(..) => "||",
+        ExprOperatorEditor::And(..) => "&&",
+        ExprOperatorEditor::Lt(..) => "<",
+        ExprOperatorEditor::Le(..) => "<=",
+        ExprOperatorEditor::Gt(..) => ">",
+        ExprOperatorEditor::Ge(..) => ">=",
+        ExprOperatorEditor::Eq(..) => "==",
+        ExprOperatorEditor::Neq(..) => "!=",
+        ExprOperatorEditor::Add(..) => "+",
+        ExprOperatorEditor::Subtract(..) => "-",
+        ExprOperatorEditor::Multiply(..) => "*",
+        ExprOperatorEditor::Div(..) => "/",
+        ExprOperatorEditor::Rem(..) => "%",
+        _ => return None,
+    };
+    let level = BINARY_LEVELS
+        .iter()
+        .position(|level| level.contains(&symbol))
+        .unwrap();
+    Some((symbol, level as u8 + 1))
+}
+
+/// Name of an operator in function call syntax, e.g. `smoothstep(a, b, x)`.
+fn function_name(op: &ExprOperatorEditor) -> String {
+    match op {
+        ExprOperatorEditor::ParentAttr(_) => "parent_attr".to_string(),
+        ExprOperatorEditor::Normalized(_) => "normalize".to_string(),
+        ExprOperatorEditor::Vec4XyzW(..) => "vec4_xyz_w".to_string(),
+        ExprOperatorEditor::AsF32(_) => "as_f32".to_string(),
+        ExprOperatorEditor::AsU32(_) => "as_u32".to_string(),
+        ExprOperatorEditor::AsI32(_) => "as_i32".to_string(),
+        // Swizzles are written as a postfix `.xz`, never as calls.
+        ExprOperatorEditor::Swizzle(_, components) => format!(".{}", components),
+        _ => op.name().to_lowercase(),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.index)
+    }
+
+    fn position(&self) -> usize {
+        self.peek().map_or(self.end, |t| t.position)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position(),
+            message: message.into(),
+        })
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if let Some(token) = self.peek()
+            && token.kind == TokenKind::Punct
+            && token.text == punct
+        {
+            self.index += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            match self.peek() {
+                Some(token) => self.error(format!("expected '{}', found '{}'", punct, token.text)),
+                None => self.error(format!("expected '{}'", punct)),
+            }
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<ExprWriterEditor, ParseError> {
+        if level == BINARY_LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for symbol in BINARY_LEVELS[level] {
+                if self.eat(symbol) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = ExprWriterEditor::operator(binary_operator(symbol, lhs, rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<ExprWriterEditor, ParseError> {
+        if self.eat("-") {
+            return Ok(match self.unary()? {
+                ExprWriterEditor::Float(f) => ExprWriterEditor::Float(-f),
+                operand => ExprWriterEditor::operator(ExprOperatorEditor::Neg(operand)),
+            });
+        }
+        if self.eat("!") {
+            let operand = self.unary()?;
+            return Ok(ExprWriterEditor::operator(ExprOperatorEditor::Not(operand)));
+        }
+        self.postfix()
+    }
+
+    /// A primary expression followed by swizzles, e.g. `attr(position).xz`.
+    fn postfix(&mut self) -> Result<ExprWriterEditor, ParseError> {
+        let mut expr = self.primary()?;
+        while self.eat(".") {
+            let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Ident) else {
+                return self.error("expected swizzle components, e.g. 'xz'");
+            };
+            let components = token.text.to_string();
+            if !components.chars().all(|c| "xyzw".contains(c)) || components.len() > 4 {
+                return self.error(format!("invalid swizzle '{}'", components));
+            }
+            self.index += 1;
+            expr = ExprWriterEditor::operator(ExprOperatorEditor::Swizzle(expr, components));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<ExprWriterEditor, ParseError> {
+        let Some(token) = self.peek() else {
+            return self.error("expected an expression");
+        };
+        let (kind, text, position) = (token.kind, token.text, token.position);
+        match kind {
+            TokenKind::Number => {
+                self.index += 1;
+                number(text).ok_or(ParseError {
+                    position,
+                    message: format!("invalid number '{}'", text),
+                })
+            }
+            TokenKind::Ident => {
+                self.index += 1;
+                if self.eat("(") {
+                    self.call(text, position)
+                } else {
+                    match text {
+                        "_" => Ok(ExprWriterEditor::Placeholder),
+                        "true" => Ok(ExprWriterEditor::Bool(true)),
+                        "false" => Ok(ExprWriterEditor::Bool(false)),
+                        // Non-finite floats, as printed by `Debug`.
+                        "inf" => Ok(ExprWriterEditor::Float(f32::INFINITY)),
+                        "NaN" => Ok(ExprWriterEditor::Float(f32::NAN)),
+                        "time" => Ok(ExprWriterEditor::Time),
+                        "age" => Ok(ExprWriterEditor::Age),
+                        "delta_time" => Ok(ExprWriterEditor::DeltaTime),
+                        "real_time" => Ok(ExprWriterEditor::RealTime),
+                        "real_delta_time" => Ok(ExprWriterEditor::RealDeltaTime),
+                        "particle_id" => Ok(ExprWriterEditor::ParticleId),
+                        _ => Err(ParseError {
+                            position,
+                            message: format!("unknown name '{}'", text),
+                        }),
+                    }
+                }
+            }
+            TokenKind::Punct if text == "(" => {
+                self.index += 1;
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            TokenKind::Punct => self.error(format!("expected an expression, found '{}'", text)),
+        }
+    }
+
+    /// Parses the arguments of a call whose opening parenthesis was just
+    /// consumed.
+    fn call(&mut self, name: &str, position: usize) -> Result<ExprWriterEditor, ParseError> {
+        if name == "attr" || name == "parent_attr" {
+            let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Ident) else {
+                return self.error("expected an attribute name");
+            };
+            let Some(attr) = Attribute::from_name(token.text) else {
+                return self.error(format!("unknown attribute '{}'", token.text));
+            };
+            self.index += 1;
+            self.expect(")")?;
+            let op = if name == "attr" {
+                ExprOperatorEditor::Attr(attr)
+            } else {
+                ExprOperatorEditor::ParentAttr(attr)
+            };
+            return Ok(ExprWriterEditor::operator(op));
+        }
+        if name == "prop" || name == "ref" {
+            let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Ident) else {
+                return self.error(if name == "prop" {
+                    "expected a property name"
+                } else {
+                    "expected a definition name"
+                });
+            };
+            let target = token.text.to_string();
+            self.index += 1;
+            self.expect(")")?;
+            return Ok(if name == "prop" {
+                ExprWriterEditor::Property(target)
+            } else {
+                ExprWriterEditor::Ref(target)
+            });
+        }
+
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.binary(0)?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let arity_error = |expected: usize| {
+            Err(ParseError {
+                position,
+                message: format!(
+                    "'{}' takes {} argument(s), {} given",
+                    name,
+                    expected,
+                    args.len()
+                ),
+            })
+        };
+        let leaf = match name {
+            "rand_f32" => Some(ExprWriterEditor::RandFloat),
+            "rand_u32" => Some(ExprWriterEditor::RandU32),
+            "rand_vec3" => Some(ExprWriterEditor::RandVec3),
+            "random_on_sphere" => Some(ExprWriterEditor::RandomRange(RandomRange::OnSphere)),
+            "random_in_disc" => Some(ExprWriterEditor::RandomRange(RandomRange::InDisc)),
+            _ => None,
+        };
+        if let Some(leaf) = leaf {
+            return if args.is_empty() {
+                Ok(leaf)
+            } else {
+                arity_error(0)
+            };
+        }
+
+        let distribution = match name {
+            "random" => Some(Distribution::Uniform),
+            "random_normal" => Some(Distribution::Normal),
+            _ => None,
+        };
+        if let Some(distribution) = distribution {
+            use ExprWriterEditor as E;
+            let range = match args.as_slice() {
+                [E::Float(a), E::Float(b)] => RandomRange::Float(distribution, *a, *b),
+                [E::Vec3(a), E::Vec3(b)] => RandomRange::Vec3(distribution, *a, *b),
+                [E::Color(a, ia), E::Color(b, ib)] => {
+                    RandomRange::Color(distribution, (*a, *ia), (*b, *ib))
+                }
+                _ => {
+                    return Err(ParseError {
+                        position,
+                        message: format!("'{}' takes two numbers, vec3s or colors", name),
+                    });
+                }
+            };
+            return Ok(ExprWriterEditor::RandomRange(range));
+        }
+
+        // Vectors of constants are literals.
+        let floats: Vec<f32> = args
+            .iter()
+            .filter_map(|a| match a {
+                ExprWriterEditor::Float(f) => Some(*f),
+                _ => None,
+            })
+            .collect();
+        match (name, floats.as_slice()) {
+            ("vec2", &[x, y]) if args.len() == 2 => {
+                return Ok(ExprWriterEditor::Vec2(Vec2::new(x, y)));
+            }
+            ("vec3", &[x, y, z]) if args.len() == 3 => {
+                return Ok(ExprWriterEditor::Vec3(Vec3::new(x, y, z)));
+            }
+            ("vec4", &[x, y, z, w]) if args.len() == 4 => {
+                return Ok(ExprWriterEditor::Vec4(Vec4::new(x, y, z, w)));
+            }
+            ("color", &[r, g, b, a]) if args.len() == 4 => {
+                return Ok(ExprWriterEditor::Color(Vec4::new(r, g, b, a), 1.0));
+            }
+            ("color", &[r, g, b, a, intensity]) if args.len() == 5 => {
+                return Ok(ExprWriterEditor::Color(Vec4::new(r, g, b, a), intensity));
+            }
+            ("color", _) => {
+                return Err(ParseError {
+                    position,
+                    message: "'color' takes 4 numbers, or 5 with an intensity".to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        let Some(mut op) = ExprOperatorEditor::menu_groups()
+            .into_iter()
+            .flat_map(|(_, ops)| ops)
+            .find(|op| function_name(op) == name && !op.operands().is_empty())
+        else {
+            return Err(ParseError {
+                position,
+                message: format!("unknown function '{}'", name),
+            });
+        };
+        let operands = op.operands_mut();
+        if operands.len() != args.len() {
+            return arity_error(operands.len());
+        }
+        for (operand, arg) in operands.into_iter().zip(args) {
+            *operand = arg;
+        }
+        Ok(ExprWriterEditor::operator(op))
+    }
+}
+
+fn number(text: &str) -> Option<ExprWriterEditor> {
+    match text.strip_suffix('u') {
+        Some(int) => int.parse().ok().map(ExprWriterEditor::U32),
+        None => text.parse().ok().map(ExprWriterEditor::Float),
+    }
+}
+
+/// Parses the text syntax printed by the `Display` implementation of
+/// [`ExprWriterEditor`], e.g. `normalize(rand_vec3() * 2.0 - 1.0)`.
+pub fn parse_expr(text: &str) -> Result<ExprWriterEditor, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        index: 0,
+        end: text.chars().count(),
+    };
+    let expr = parser.binary(0)?;
+    match parser.peek() {
+        Some(token) => parser.error(format!("unexpected '{}'", token.text)),
+        None => Ok(expr),
+    }
+}
+
+fn precedence(expr: &ExprWriterEditor) -> u8 {
+    match expr {
+        ExprWriterEditor::Operator(op, _) => match op.as_ref() {
+            ExprOperatorEditor::Neg(_) | ExprOperatorEditor::Not(_) => UNARY_PRECEDENCE,
+            op => infix(op).map_or(ATOM_PRECEDENCE, |(_, p)| p),
+        },
+        ExprWriterEditor::Float(f) if f.is_sign_negative() => UNARY_PRECEDENCE,
+        _ => ATOM_PRECEDENCE,
+    }
+}
+
+fn write_operand(
+    f: &mut fmt::Formatter<'_>,
+    operand: &ExprWriterEditor,
+    min_precedence: u8,
+) -> fmt::Result {
+    if precedence(operand) < min_precedence {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+impl fmt::Display for ExprOperatorEditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((symbol, p)) = infix(self) {
+            let operands = self.operands();
+            // Left associative, so a right operand of the same level needs
+            // parentheses.
+            write_operand(f, operands[0], p)?;
+            write!(f, " {} ", symbol)?;
+            return write_operand(f, operands[1], p + 1);
+        }
+        match self {
+            ExprOperatorEditor::Neg(a) => {
+                write!(f, "-")?;
+                write_operand(f, a, UNARY_PRECEDENCE)
+            }
+            ExprOperatorEditor::Not(a) => {
+                write!(f, "!")?;
+                write_operand(f, a, UNARY_PRECEDENCE)
+            }
+            ExprOperatorEditor::Attr(attr) | ExprOperatorEditor::ParentAttr(attr) => {
+                write!(f, "{}({})", function_name(self), attr.name())
+            }
+            ExprOperatorEditor::Swizzle(a, components) => {
+                write_operand(f, a, ATOM_PRECEDENCE)?;
+                write!(f, ".{}", components)
+            }
+            _ => {
+                write!(f, "{}(", function_name(self))?;
+                for (i, operand) in self.operands().into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", operand)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExprWriterEditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprWriterEditor::Placeholder => write!(f, "_"),
+            ExprWriterEditor::RandU32 => write!(f, "rand_u32()"),
+            ExprWriterEditor::RandFloat => write!(f, "rand_f32()"),
+            ExprWriterEditor::RandVec3 => write!(f, "rand_vec3()"),
+            ExprWriterEditor::RandomRange(range) => write!(f, "{}", range),
+            ExprWriterEditor::Operator(op, _) => write!(f, "{}", op),
+            ExprWriterEditor::Float(v) => write!(f, "{:?}", v),
+            ExprWriterEditor::U32(v) => write!(f, "{}u", v),
+            ExprWriterEditor::Bool(v) => write!(f, "{}", v),
+            ExprWriterEditor::Vec2(v) => write!(f, "vec2({:?}, {:?})", v.x, v.y),
+            ExprWriterEditor::Vec3(v) => write!(f, "vec3({:?}, {:?}, {:?})", v.x, v.y, v.z),
+            ExprWriterEditor::Vec4(v) => {
+                write!(f, "vec4({:?}, {:?}, {:?}, {:?})", v.x, v.y, v.z, v.w)
+            }
+            ExprWriterEditor::Color(c, intensity) => {
+                write!(f, "color({:?}, {:?}, {:?}, {:?}", c.x, c.y, c.z, c.w)?;
+                if *intensity != 1.0 {
+                    write!(f, ", {:?}", intensity)?;
+                }
+                write!(f, ")")
+            }
+            ExprWriterEditor::Time => write!(f, "time"),
+            ExprWriterEditor::Age => write!(f, "age"),
+            ExprWriterEditor::DeltaTime => write!(f, "delta_time"),
+            ExprWriterEditor::RealTime => write!(f, "real_time"),
+            ExprWriterEditor::RealDeltaTime => write!(f, "real_delta_time"),
+            ExprWriterEditor::ParticleId => write!(f, "particle_id"),
+            ExprWriterEditor::Property(name) => write!(f, "prop({})", name),
+            ExprWriterEditor::Ref(name) => write!(f, "ref({})", name),
+        }
+    }
+}
+
+impl fmt::Display for RandomRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = match *self {
+            RandomRange::Float(_, a, b) => (ExprWriterEditor::Float(a), ExprWriterEditor::Float(b)),
+            RandomRange::Vec3(_, a, b) => (ExprWriterEditor::Vec3(a), ExprWriterEditor::Vec3(b)),
+            RandomRange::Color(_, (a, ia), (b, ib)) => (
+                ExprWriterEditor::Color(a, ia),
+                ExprWriterEditor::Color(b, ib),
+            ),
+            RandomRange::OnSphere => return write!(f, "random_on_sphere()"),
+            RandomRange::InDisc => return write!(f, "random_in_disc()"),
+        };
+        let name = match self.distribution() {
+            Some(Distribution::Normal) => "random_normal",
+            _ => "random",
+        };
+        write!(f, "{}({}, {})", name, a, b)
+    }
+}
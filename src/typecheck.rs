@@ -0,0 +1,414 @@
+use bevy_hanabi::prelude::*;
+
+use crate::definitions::{ExprDefinition, find_definition};
+use crate::expr::{ExprOperatorEditor, ExprWriterEditor};
+use crate::properties::EffectProperty;
+
+/// Scalar type of a scalar, or of the components of a vector.
+pub fn elem_type(value_type: ValueType) -> ScalarType {
+    match value_type {
+        ValueType::Scalar(s) => s,
+        ValueType::Vector(v) => v.elem_type(),
+        _ => ScalarType::Float,
+    }
+}
+
+/// The same scalar or vector shape as `value_type`, with elements of `elem`.
+pub fn with_elem_type(value_type: ValueType, elem: ScalarType) -> ValueType {
+    match value_type {
+        ValueType::Vector(v) => ValueType::Vector(VectorType::new(elem, v.count() as u8)),
+        _ => ValueType::Scalar(elem),
+    }
+}
+
+fn is_float(t: ValueType) -> bool {
+    elem_type(t) == ScalarType::Float
+}
+
+fn is_numeric(t: ValueType) -> bool {
+    elem_type(t) != ScalarType::Bool
+}
+
+fn is_bool(t: ValueType) -> bool {
+    elem_type(t) == ScalarType::Bool
+}
+
+fn is_float_vector(t: ValueType) -> bool {
+    matches!(t, ValueType::Vector(v) if v.elem_type() == ScalarType::Float)
+}
+
+fn is_scalar(t: ValueType) -> bool {
+    matches!(t, ValueType::Scalar(s) if s != ScalarType::Bool)
+}
+
+fn is_bool_scalar(t: ValueType) -> bool {
+    t == ValueType::Scalar(ScalarType::Bool)
+}
+
+const F32: ValueType = ValueType::Scalar(ScalarType::Float);
+const BOOL: ValueType = ValueType::Scalar(ScalarType::Bool);
+
+/// Operand types of one operator, with the helpers to check them.
+struct Operands<'a> {
+    name: &'static str,
+    types: &'a [Option<ValueType>],
+}
+
+impl Operands<'_> {
+    fn get(&self, index: usize) -> Option<ValueType> {
+        self.types.get(index).copied().flatten()
+    }
+
+    /// Requires every known operand in `indices` to satisfy `ok`.
+    fn require(
+        &self,
+        indices: &[usize],
+        ok: fn(ValueType) -> bool,
+        expected: &str,
+    ) -> Result<(), String> {
+        for &index in indices {
+            if let Some(t) = self.get(index)
+                && !ok(t)
+            {
+                return Err(format!(
+                    "{} expects {} operands, got {}",
+                    self.name, expected, t
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Requires all known operands in `indices` to have the same type, and
+    /// returns it.
+    fn same(&self, indices: &[usize]) -> Result<Option<ValueType>, String> {
+        let mut result: Option<ValueType> = None;
+        for &index in indices {
+            match (result, self.get(index)) {
+                (Some(a), Some(b)) if a != b => {
+                    return Err(format!(
+                        "{} expects operands of the same type, got {} and {}",
+                        self.name, a, b
+                    ));
+                }
+                (None, t) => result = t,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+
+    /// Component-wise arithmetic, where a scalar is applied to every
+    /// component of a vector of the same scalar type.
+    fn broadcast(&self, a: usize, b: usize) -> Result<Option<ValueType>, String> {
+        match (self.get(a), self.get(b)) {
+            (Some(x), Some(y)) => {
+                let shapes_match = match (x, y) {
+                    (ValueType::Vector(v), ValueType::Vector(w)) => v.count() == w.count(),
+                    _ => true,
+                };
+                if elem_type(x) != elem_type(y) || !shapes_match {
+                    return Err(format!("{} cannot combine {} and {}", self.name, x, y));
+                }
+                Ok(Some(if let ValueType::Vector(_) = y { y } else { x }))
+            }
+            (Some(v @ ValueType::Vector(_)), None) | (None, Some(v @ ValueType::Vector(_))) => {
+                Ok(Some(v))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Checks a swizzle such as `xz` against the vector it applies to.
+fn check_swizzle(
+    operand: Option<ValueType>,
+    components: &str,
+) -> Result<Option<ValueType>, String> {
+    let len = components.chars().count();
+    if !(1..=4).contains(&len) || !components.chars().all(|c| "xyzw".contains(c)) {
+        return Err(format!(
+            "Swizzle expects 1 to 4 of the components x, y, z and w, got '{}'",
+            components
+        ));
+    }
+    let Some(t) = operand else {
+        return Ok(None);
+    };
+    let ValueType::Vector(v) = t else {
+        return Err(format!("Swizzle expects a vector operand, got {}", t));
+    };
+    if let Some(c) = components
+        .chars()
+        .find(|c| "xyzw".find(*c).unwrap() >= v.count())
+    {
+        return Err(format!("{} has no {} component", t, c));
+    }
+    Ok(Some(if len == 1 {
+        ValueType::Scalar(v.elem_type())
+    } else {
+        ValueType::Vector(VectorType::new(v.elem_type(), len as u8))
+    }))
+}
+
+/// What the leaves referring to the project stand for when checking types.
+/// Without it, such leaves are of unknown type.
+#[derive(Clone, Copy, Default)]
+pub struct TypeContext<'a> {
+    pub definitions: &'a [ExprDefinition],
+    /// Properties of the effect the expression belongs to.
+    pub properties: &'a [EffectProperty],
+    /// References followed so far, to stop at cyclic definitions.
+    depth: usize,
+}
+
+impl<'a> TypeContext<'a> {
+    pub fn new(definitions: &'a [ExprDefinition]) -> Self {
+        Self {
+            definitions,
+            properties: &[],
+            depth: 0,
+        }
+    }
+
+    pub fn with_properties(self, properties: &'a [EffectProperty]) -> Self {
+        Self { properties, ..self }
+    }
+
+    /// Type of the property `name`, `None` if the effect does not declare it.
+    pub fn property_type(&self, name: &str) -> Option<ValueType> {
+        self.properties
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.default.value_type())
+    }
+
+    /// Type of the definition `name`, `None` if it is unknown, cyclic or of
+    /// unknown type.
+    pub fn ref_type(&self, name: &str) -> Option<ValueType> {
+        if self.depth >= self.definitions.len() {
+            return None;
+        }
+        let definition = find_definition(self.definitions, name)?;
+        let inner = Self {
+            depth: self.depth + 1,
+            ..*self
+        };
+        definition.expr.value_type_in(&inner)
+    }
+}
+
+impl ExprWriterEditor {
+    /// [`ExprWriterEditor::value_type`], with `Ref` and `Property` leaves
+    /// resolved through `context`.
+    pub fn value_type_in(&self, context: &TypeContext) -> Option<ValueType> {
+        match self {
+            ExprWriterEditor::Ref(name) => context.ref_type(name),
+            ExprWriterEditor::Property(name) => context.property_type(name),
+            ExprWriterEditor::Operator(o, _) => o.check_types_in(context).ok().flatten(),
+            _ => self.value_type(),
+        }
+    }
+}
+
+impl ExprOperatorEditor {
+    /// Result type of the operator, `None` when it cannot be determined, e.g.
+    /// because an operand is still a placeholder or the operator does not
+    /// type check.
+    pub fn value_type(&self) -> Option<ValueType> {
+        self.check_types().ok().flatten()
+    }
+
+    /// Type error of this node. Errors inside the operands are reported by
+    /// the operands themselves.
+    pub fn type_error(&self) -> Option<String> {
+        self.check_types().err()
+    }
+
+    /// [`ExprOperatorEditor::type_error`] within `context`.
+    pub fn type_error_in(&self, context: &TypeContext) -> Option<String> {
+        self.check_types_in(context).err()
+    }
+
+    /// Checks the operand types against the operator and infers its result
+    /// type. Operands of unknown type are not checked, so an incomplete tree
+    /// only reports what is already wrong.
+    pub fn check_types(&self) -> Result<Option<ValueType>, String> {
+        self.check_types_in(&TypeContext::default())
+    }
+
+    /// [`ExprOperatorEditor::check_types`], with the leaves referring to the
+    /// project resolved through `context`.
+    pub fn check_types_in(&self, context: &TypeContext) -> Result<Option<ValueType>, String> {
+        let types: Vec<Option<ValueType>> = self
+            .operands()
+            .iter()
+            .map(|o| o.value_type_in(context))
+            .collect();
+        let ops = Operands {
+            name: self.name(),
+            types: &types,
+        };
+        match self {
+            ExprOperatorEditor::Add(..)
+            | ExprOperatorEditor::Subtract(..)
+            | ExprOperatorEditor::Multiply(..)
+            | ExprOperatorEditor::Div(..)
+            | ExprOperatorEditor::Rem(..) => {
+                ops.require(&[0, 1], is_numeric, "numeric")?;
+                ops.broadcast(0, 1)
+            }
+            ExprOperatorEditor::Min(..) | ExprOperatorEditor::Max(..) => {
+                ops.require(&[0, 1], is_numeric, "numeric")?;
+                ops.same(&[0, 1])
+            }
+            ExprOperatorEditor::Clamp(..) => {
+                ops.require(&[0, 1, 2], is_numeric, "numeric")?;
+                ops.same(&[0, 1, 2])
+            }
+            ExprOperatorEditor::Mix(..) => {
+                ops.require(&[0, 1, 2], is_float, "float")?;
+                let result = ops.same(&[0, 1])?;
+                if let (Some(r), Some(t)) = (result, ops.get(2))
+                    && t != r
+                    && t != F32
+                {
+                    return Err(format!("Mix expects an f32 or {} factor, got {}", r, t));
+                }
+                Ok(result)
+            }
+            ExprOperatorEditor::Uniform(..)
+            | ExprOperatorEditor::Step(..)
+            | ExprOperatorEditor::Pow(..)
+            | ExprOperatorEditor::Atan2(..) => {
+                ops.require(&[0, 1], is_float, "float")?;
+                ops.same(&[0, 1])
+            }
+            ExprOperatorEditor::Smoothstep(..) => {
+                ops.require(&[0, 1, 2], is_float, "float")?;
+                ops.same(&[0, 1, 2])
+            }
+            ExprOperatorEditor::Abs(..) => {
+                ops.require(&[0], is_numeric, "numeric")?;
+                Ok(ops.get(0))
+            }
+            ExprOperatorEditor::Neg(..)
+            | ExprOperatorEditor::Sign(..)
+            | ExprOperatorEditor::Floor(..)
+            | ExprOperatorEditor::Ceil(..)
+            | ExprOperatorEditor::Fract(..)
+            | ExprOperatorEditor::Sqrt(..)
+            | ExprOperatorEditor::Exp(..)
+            | ExprOperatorEditor::Log(..)
+            | ExprOperatorEditor::Sin(..)
+            | ExprOperatorEditor::Cos(..)
+            | ExprOperatorEditor::Tan(..) => {
+                ops.require(&[0], is_float, "float")?;
+                Ok(ops.get(0))
+            }
+            ExprOperatorEditor::Distance(..) => {
+                ops.require(&[0, 1], is_float, "float")?;
+                ops.same(&[0, 1])?;
+                Ok(Some(F32))
+            }
+            ExprOperatorEditor::Dot(..) => {
+                ops.require(&[0, 1], is_float_vector, "float vector")?;
+                ops.same(&[0, 1])?;
+                Ok(Some(F32))
+            }
+            ExprOperatorEditor::Cross(..) => {
+                ops.require(
+                    &[0, 1],
+                    |t| t == ValueType::Vector(VectorType::VEC3F),
+                    "vec3<f32>",
+                )?;
+                Ok(Some(ValueType::Vector(VectorType::VEC3F)))
+            }
+            ExprOperatorEditor::Length(..) => {
+                ops.require(&[0], is_float, "float")?;
+                Ok(Some(F32))
+            }
+            ExprOperatorEditor::Normalized(..) => {
+                ops.require(&[0], is_float_vector, "float vector")?;
+                Ok(ops.get(0))
+            }
+            ExprOperatorEditor::Vec2(..)
+            | ExprOperatorEditor::Vec3(..)
+            | ExprOperatorEditor::Vec4(..) => {
+                let count = types.len();
+                let indices: Vec<usize> = (0..count).collect();
+                ops.require(&indices, is_scalar, "numeric scalar")?;
+                let elem = ops.same(&indices)?.map_or(ScalarType::Float, elem_type);
+                Ok(Some(ValueType::Vector(VectorType::new(elem, count as u8))))
+            }
+            ExprOperatorEditor::Vec4XyzW(..) => {
+                ops.require(
+                    &[0],
+                    |t| matches!(t, ValueType::Vector(v) if v.count() == 3) && is_numeric(t),
+                    "numeric vec3",
+                )?;
+                ops.require(&[1], is_scalar, "numeric scalar")?;
+                let elem = match (ops.get(0), ops.get(1)) {
+                    (Some(xyz), Some(w)) if elem_type(xyz) != elem_type(w) => {
+                        return Err(format!("Vec4XyzW cannot combine {} and {}", xyz, w));
+                    }
+                    (Some(t), _) | (None, Some(t)) => elem_type(t),
+                    (None, None) => ScalarType::Float,
+                };
+                Ok(Some(ValueType::Vector(VectorType::new(elem, 4))))
+            }
+            ExprOperatorEditor::Swizzle(_, components) => check_swizzle(ops.get(0), components),
+            ExprOperatorEditor::AsF32(..) => {
+                Ok(ops.get(0).map(|t| with_elem_type(t, ScalarType::Float)))
+            }
+            ExprOperatorEditor::AsU32(..) => {
+                Ok(ops.get(0).map(|t| with_elem_type(t, ScalarType::Uint)))
+            }
+            ExprOperatorEditor::AsI32(..) => {
+                Ok(ops.get(0).map(|t| with_elem_type(t, ScalarType::Int)))
+            }
+            ExprOperatorEditor::Pack4x8UNorm(..) => {
+                ops.require(
+                    &[0],
+                    |t| t == ValueType::Vector(VectorType::VEC4F),
+                    "vec4<f32>",
+                )?;
+                Ok(Some(ValueType::Scalar(ScalarType::Uint)))
+            }
+            ExprOperatorEditor::Lt(..)
+            | ExprOperatorEditor::Le(..)
+            | ExprOperatorEditor::Gt(..)
+            | ExprOperatorEditor::Ge(..) => {
+                ops.require(&[0, 1], is_numeric, "numeric")?;
+                Ok(ops
+                    .same(&[0, 1])?
+                    .map(|t| with_elem_type(t, ScalarType::Bool)))
+            }
+            ExprOperatorEditor::Eq(..) | ExprOperatorEditor::Neq(..) => {
+                ops.require(&[0, 1], is_numeric, "numeric")?;
+                ops.same(&[0, 1])?;
+                Ok(Some(BOOL))
+            }
+            ExprOperatorEditor::And(..) | ExprOperatorEditor::Or(..) => {
+                ops.require(&[0, 1], is_bool_scalar, "bool")?;
+                Ok(Some(BOOL))
+            }
+            ExprOperatorEditor::Not(..) => {
+                ops.require(&[0], is_bool_scalar, "bool")?;
+                Ok(Some(BOOL))
+            }
+            ExprOperatorEditor::All(..) | ExprOperatorEditor::Any(..) => {
+                ops.require(&[0], is_bool, "bool")?;
+                Ok(Some(BOOL))
+            }
+            ExprOperatorEditor::Select(..) => {
+                ops.require(&[0], is_bool_scalar, "bool")?;
+                ops.require(&[1, 2], is_numeric, "numeric")?;
+                ops.same(&[1, 2])
+            }
+            ExprOperatorEditor::Attr(attr) | ExprOperatorEditor::ParentAttr(attr) => {
+                Ok(Some(attr.value_type()))
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+
+use crate::OmagariProject;
+use crate::definitions::{definition_cycle, find_definition};
+use crate::effect::{EffectEditor, ModifierEditor};
+use crate::expr::ExprWriterEditor;
+use crate::ids::{Identified, NodeId};
+use crate::modifiers::ExprSlots;
+use crate::properties::is_valid_property_name;
+use crate::syntax::is_identifier;
+use crate::typecheck::TypeContext;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a project, located by a path of effect, modifier and
+/// expression node labels, e.g. `Rocket › Init #1 SetAttribute(lifetime) › Value › Uniform[0]`.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: Vec<String>,
+    /// The modifier or expression operator the problem is in, which unlike
+    /// `path` stays the same when the project is edited.
+    pub node: Option<NodeId>,
+    /// Set when the problem is in a leaf, which has no id: the leaf is this
+    /// operand of `node`, or this expression slot if `node` is a modifier.
+    pub operand: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, path: &[String], message: String) -> Self {
+        Self {
+            severity,
+            path: path.to_vec(),
+            node: None,
+            operand: None,
+            message,
+        }
+    }
+
+    fn in_node(mut self, node: Option<NodeId>) -> Self {
+        self.node = node;
+        self
+    }
+
+    fn in_operand(mut self, node: Option<NodeId>, operand: usize) -> Self {
+        self.node = node;
+        self.operand = Some(operand);
+        self
+    }
+
+    pub fn location(&self) -> String {
+        self.path.join(" › ")
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.location(), self.message)
+    }
+}
+
+impl OmagariProject {
+    /// Checks the project for problems that would otherwise silently produce
+    /// broken or surprising effects.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (index, definition) in self.definitions.iter().enumerate() {
+            let mut path = vec![format!("Definition '{}'", definition.name)];
+            if !is_identifier(&definition.name) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    &path,
+                    "Definition names must be identifiers, e.g. 'spark_color'".to_string(),
+                ));
+            }
+            if self.definitions[..index]
+                .iter()
+                .any(|d| d.name == definition.name)
+            {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    &path,
+                    "Duplicate definition name".to_string(),
+                ));
+            }
+            if let Some(cycle) = definition_cycle(&self.definitions, &definition.name) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    &path,
+                    format!("Definition refers to itself: {}", cycle.join(" › ")),
+                ));
+            }
+            self.validate_expr(
+                None,
+                &definition.expr,
+                (None, 0),
+                &mut path,
+                &mut diagnostics,
+            );
+        }
+        for (index, effect) in self.effects.iter().enumerate() {
+            let path = vec![effect.name().to_string()];
+            if self.effects[..index]
+                .iter()
+                .any(|e| e.name() == effect.name())
+            {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    &path,
+                    "Duplicate effect name, parent links by this name are ambiguous".to_string(),
+                ));
+            }
+            if let Some(parent) = effect.parent_name() {
+                if parent == effect.name() {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        &path,
+                        "Effect is its own parent".to_string(),
+                    ));
+                } else if !self.effects.iter().any(|e| e.name() == parent) {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        &path,
+                        format!("Parent effect '{}' does not exist", parent),
+                    ));
+                }
+            }
+            if let Some(texture) = effect.texture()
+                && self.texture_path(texture).is_none()
+            {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    &path,
+                    format!("Texture '{}' is not in the texture library", texture),
+                ));
+            }
+            for (index, property) in effect.properties().iter().enumerate() {
+                let mut path = path.clone();
+                path.push(format!("Property '{}'", property.name));
+                if !is_valid_property_name(&property.name) {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        &path,
+                        "Property names must be identifiers, e.g. 'spark_color'".to_string(),
+                    ));
+                }
+                if effect.properties()[..index]
+                    .iter()
+                    .any(|p| p.name == property.name)
+                {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        &path,
+                        "Duplicate property name".to_string(),
+                    ));
+                }
+            }
+            self.validate_modifiers(effect, &path, &mut diagnostics);
+        }
+        diagnostics
+    }
+
+    fn validate_modifiers(
+        &self,
+        effect: &EffectEditor,
+        path: &[String],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let n_children = self
+            .effects
+            .iter()
+            .filter(|e| e.parent_name() == Some(effect.name()))
+            .count();
+        let lists = [
+            ("Init", effect.init_modifiers()),
+            ("Update", effect.update_modifiers()),
+        ];
+        for (list, modifiers) in lists {
+            for (index, modifier) in modifiers.iter().enumerate() {
+                let mut path = path.to_vec();
+                path.push(modifier_label(list, index, modifier));
+
+                if let ModifierEditor::EmitSpawnEventModifier(m) = modifier
+                    && m.child_index() as usize >= n_children
+                {
+                    diagnostics.push(
+                        Diagnostic::new(
+                            Severity::Error,
+                            &path,
+                            format!(
+                                "Child index {} has no matching child effect ({} children)",
+                                m.child_index(),
+                                n_children
+                            ),
+                        )
+                        .in_node(Some(modifier.id())),
+                    );
+                }
+
+                for (slot_index, (slot, expr, expected)) in modifier.exprs().into_iter().enumerate()
+                {
+                    let mut path = path.clone();
+                    path.push(slot.to_string());
+                    let context =
+                        TypeContext::new(&self.definitions).with_properties(effect.properties());
+                    if let Some(value_type) = expr.value_type_in(&context)
+                        && value_type != expected
+                    {
+                        diagnostics.push(
+                            Diagnostic::new(
+                                Severity::Error,
+                                &path,
+                                format!(
+                                    "Expected {} but the expression is {}",
+                                    expected, value_type
+                                ),
+                            )
+                            .in_node(Some(modifier.id())),
+                        );
+                    }
+                    let location = (Some(modifier.id()), slot_index);
+                    self.validate_expr(Some(effect), expr, location, &mut path, diagnostics);
+                }
+            }
+        }
+    }
+
+    /// Checks an expression of `effect`, or of a definition when `effect` is
+    /// `None`. `expr` is operand `location.1` of the modifier or operator
+    /// `location.0`, or the root of a definition if that is `None`.
+    fn validate_expr(
+        &self,
+        effect: Option<&EffectEditor>,
+        expr: &ExprWriterEditor,
+        location: (Option<NodeId>, usize),
+        path: &mut Vec<String>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        match expr {
+            ExprWriterEditor::Placeholder => diagnostics.push(
+                Diagnostic::new(
+                    Severity::Warning,
+                    path,
+                    "Empty expression, evaluates to 0.0".to_string(),
+                )
+                .in_operand(location.0, location.1),
+            ),
+            ExprWriterEditor::Operator(o, id) => {
+                let properties = effect.map_or(&[][..], |e| e.properties());
+                let context = TypeContext::new(&self.definitions).with_properties(properties);
+                if let Some(error) = o.type_error_in(&context) {
+                    diagnostics
+                        .push(Diagnostic::new(Severity::Error, path, error).in_node(Some(*id)));
+                }
+                for (index, operand) in o.operands().into_iter().enumerate() {
+                    path.push(format!("{}[{}]", o.name(), index));
+                    self.validate_expr(effect, operand, (Some(*id), index), path, diagnostics);
+                    path.pop();
+                }
+            }
+            ExprWriterEditor::Property(name)
+                if effect.is_some_and(|e| !e.properties().iter().any(|p| p.name == *name)) =>
+            {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        path,
+                        format!("Property '{}' is not declared by this effect", name),
+                    )
+                    .in_operand(location.0, location.1),
+                )
+            }
+            ExprWriterEditor::Ref(name) if find_definition(&self.definitions, name).is_none() => {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        path,
+                        format!("Definition '{}' does not exist", name),
+                    )
+                    .in_operand(location.0, location.1),
+                )
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Label of a modifier in diagnostic locations, e.g. `Init #1 SetAttribute(lifetime)`.
+pub(crate) fn modifier_label(list: &str, index: usize, modifier: &ModifierEditor) -> String {
+    let label = format!("{} #{} {}", list, index, modifier.label());
+    match modifier {
+        ModifierEditor::SetAttribute(m) => format!("{}({})", label, m.attr().name()),
+        _ => label,
+    }
+}
@@ -0,0 +1,26 @@
+//! Fixtures shared by the integration tests.
+
+// Each test crate only uses some of them.
+#![allow(dead_code)]
+
+/// Text of `examples/<name>.omagari.ron`.
+pub fn example(name: &str) -> String {
+    let path = format!(
+        "{}/examples/{}.omagari.ron",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+/// `text` with the first `from` replaced by `to`. Panics when `text` does
+/// not contain `from`, so that a fixture notices when the example it is
+/// built from changes instead of silently testing the unpatched file.
+pub fn patched(text: &str, from: &str, to: &str) -> String {
+    assert!(
+        text.contains(from),
+        "fixture patch did not match, the example no longer contains:\n{}",
+        from
+    );
+    text.replacen(from, to, 1)
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use bevy_hanabi::prelude::*;
+use common::{example, patched};
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+/// The explode example with its lifetime moved to a `lifetime` definition,
+/// followed by the `extra` definitions given as text.
+fn project_with_definitions(extra: &[(&str, &str)]) -> OmagariProject {
+    let text = patched(
+        &example("explode"),
+        "attr_expr: Operator(Uniform(Float(2.1), Float(5.51)))",
+        "attr_expr: Ref(\"lifetime\")",
+    );
+    let mut project = project_from_str(&text).unwrap();
+    let definitions = [("lifetime", "uniform(2.1, 5.51)")];
+    for (name, expr) in definitions.iter().chain(extra) {
+        project
+            .definitions
+            .push(ExprDefinition::new(name, parse_expr(expr).unwrap()));
+    }
+    project
+}
+
+fn errors(project: &OmagariProject) -> Vec<String> {
+    project
+        .validate()
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.to_string())
+        .collect()
+}
+
+#[test]
+fn definitions_are_inlined_and_their_usages_found() {
+    let project = project_with_definitions(&[]);
+    assert!(errors(&project).is_empty(), "{:#?}", errors(&project));
+    assert_eq!(
+        project.definition_usages("lifetime"),
+        vec!["Name your effect › Init #4 🗠 SetAttributeModifier(lifetime) › Value"]
+    );
+    for effect in project.effects.iter() {
+        effect.produce(&project.definitions);
+    }
+
+    let expr = parse_expr("ref(lifetime)").unwrap();
+    assert_eq!(expr.to_string(), "ref(lifetime)");
+    let resolved = resolve_refs(&project.definitions, &expr);
+    assert_eq!(resolved.to_string(), "uniform(2.1, 5.51)");
+}
+
+#[test]
+fn cyclic_definitions_are_errors() {
+    let project = project_with_definitions(&[("a", "ref(b) + 1.0"), ("b", "ref(a)")]);
+    assert_eq!(
+        definition_cycle(&project.definitions, "a"),
+        Some(vec!["a".to_string(), "b".to_string(), "a".to_string()])
+    );
+    assert_eq!(definition_cycle(&project.definitions, "lifetime"), None);
+    let errors = errors(&project);
+    assert_eq!(errors.len(), 2, "{:#?}", errors);
+    assert!(errors[0].contains("a › b › a"), "{}", errors[0]);
+
+    // Producing a cycle gives up instead of recursing forever.
+    let writer = EffectWriter::with_definitions(&project.definitions);
+    writer.inline_definition("a");
+}
+
+#[test]
+fn unknown_definitions_are_errors() {
+    let mut project = project_with_definitions(&[]);
+    project.definitions[0].name = "lifespan".to_string();
+    let errors = errors(&project);
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(
+        errors[0].contains("Definition 'lifetime' does not exist"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn renames_follow_references() {
+    let mut project = project_with_definitions(&[("longer", "ref(lifetime) * 2.0")]);
+    project.rename_definition("lifetime", "lifespan");
+    assert!(errors(&project).is_empty(), "{:#?}", errors(&project));
+    assert_eq!(
+        project.definitions[1].expr.to_string(),
+        "ref(lifespan) * 2.0"
+    );
+    assert_eq!(project.definition_usages("lifespan").len(), 2);
+
+    assert!(is_identifier("spark_color2"));
+    assert!(!is_identifier("spark color"));
+    assert!(!is_identifier("2fast"));
+    project.definitions.push(ExprDefinition::new(
+        "spark color",
+        ExprWriterEditor::Float(1.0),
+    ));
+    let errors = errors(&project);
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(errors[0].contains("must be identifiers"), "{}", errors[0]);
+}
+
+#[test]
+fn references_have_the_type_of_their_definition() {
+    let project = project_with_definitions(&[
+        ("longer", "ref(lifetime) * 2.0"),
+        ("wrong", "ref(longer) && true"),
+        ("a", "ref(b)"),
+        ("b", "ref(a)"),
+    ]);
+    let context = TypeContext::new(&project.definitions);
+    let expr = parse_expr("ref(longer)").unwrap();
+    assert_eq!(expr.value_type(), None);
+    assert_eq!(
+        expr.value_type_in(&context),
+        Some(ValueType::Scalar(ScalarType::Float))
+    );
+    assert_eq!(parse_expr("ref(a)").unwrap().value_type_in(&context), None);
+
+    let errors = errors(&project);
+    assert!(
+        errors
+            .iter()
+            .any(|e| e.contains("Definition 'wrong'") && e.contains("And expects bool")),
+        "{:#?}",
+        errors
+    );
+}
@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use omagari::editor_prelude::*;
+
+fn eval(text: &str, inputs: &EvalInputs) -> EvalValue {
+    parse_expr(text).unwrap().eval(inputs, 0).unwrap()
+}
+
+#[test]
+fn arithmetic_follows_wgsl() {
+    let inputs = EvalInputs::default();
+    assert_eq!(
+        eval("1.0 + 2.0 * 3.0", &inputs),
+        EvalValue::Float(vec![7.0])
+    );
+    assert_eq!(
+        eval("vec3(1.0, 2.0, 3.0) * 2.0", &inputs),
+        EvalValue::Float(vec![2.0, 4.0, 6.0])
+    );
+    assert_eq!(eval("0u - 1u", &inputs), EvalValue::Uint(vec![u32::MAX]));
+    assert_eq!(eval("7u / 0u", &inputs), EvalValue::Uint(vec![7]));
+    assert_eq!(
+        eval("vec4(1.0, 0.0, 0.0, 1.0).zx", &inputs),
+        EvalValue::Float(vec![0.0, 1.0])
+    );
+    assert_eq!(
+        eval("pack4x8unorm(vec4(1.0, 0.0, 0.0, 1.0))", &inputs),
+        EvalValue::Uint(vec![0xff0000ff])
+    );
+    assert_eq!(eval("as_u32(-1.5)", &inputs), EvalValue::Uint(vec![0]));
+    assert_eq!(
+        eval("select(2.0 > 1.0 && !false, 1u, 2u)", &inputs),
+        EvalValue::Uint(vec![1])
+    );
+}
+
+#[test]
+fn inputs_are_read_from_the_context() {
+    let mut inputs = EvalInputs::default()
+        .at_time(2.0)
+        .with_attribute(Attribute::ID, EvalValue::Uint(vec![5]));
+    inputs
+        .properties
+        .push(("heat".to_string(), EffectPropertyValue::Float(3.0).into()));
+    inputs.definitions.push(ExprDefinition::new(
+        "double_age",
+        parse_expr("age * 2.0").unwrap(),
+    ));
+    assert_eq!(
+        eval("ref(double_age) + prop(heat) + time", &inputs),
+        EvalValue::Float(vec![9.0])
+    );
+    assert_eq!(eval("particle_id", &inputs), EvalValue::Uint(vec![5]));
+
+    let err = parse_expr("prop(cold)").unwrap().eval(&inputs, 0);
+    assert_eq!(err, Err("Property 'cold' does not exist".to_string()));
+}
+
+#[test]
+fn random_values_depend_on_the_seed() {
+    let expr = parse_expr("uniform(2.0, 5.0)").unwrap();
+    let inputs = EvalInputs::default();
+    let samples: Vec<f32> = (0..100)
+        .map(|seed| expr.eval(&inputs, seed).unwrap().as_f32().unwrap())
+        .collect();
+    assert!(samples.iter().all(|v| (2.0..5.0).contains(v)));
+    assert!(samples.iter().any(|v| *v != samples[0]));
+    assert_eq!(expr.eval(&inputs, 7), expr.eval(&inputs, 7));
+}
+
+#[test]
+fn evaluated_types_match_the_type_checker() {
+    let inputs = EvalInputs::default();
+    for text in [
+        "rand_vec3() * 2.0 - 1.0",
+        "length(vec2(3.0, 4.0))",
+        "vec3(1u, 2u, 3u) < vec3(2u, 2u, 2u)",
+        "as_i32(vec2(1.5, -2.5))",
+        "vec4_xyz_w(vec3(1.0, 2.0, 3.0), 4.0)",
+        "attr(velocity)",
+        "smoothstep(0.0, 1.0, age)",
+    ] {
+        let expr = parse_expr(text).unwrap();
+        let value = expr.eval(&inputs, 0).unwrap();
+        assert_eq!(Some(value.value_type()), expr.value_type(), "{}", text);
+    }
+}
+
+#[test]
+fn vec2_and_color_literals() {
+    let inputs = EvalInputs::default();
+    let color = parse_expr("color(1, 0.5, 0, 1, 4)").unwrap();
+    assert_eq!(color.to_string(), "color(1.0, 0.5, 0.0, 1.0, 4.0)");
+    assert_eq!(
+        color.value_type(),
+        Some(ValueType::Vector(VectorType::VEC4F))
+    );
+    assert_eq!(
+        color.eval(&inputs, 0).unwrap(),
+        EvalValue::Float(vec![4.0, 2.0, 0.0, 1.0])
+    );
+    assert_eq!(
+        parse_expr("color(1, 1, 1, 0.5)").unwrap().to_string(),
+        "color(1.0, 1.0, 1.0, 0.5)"
+    );
+    assert!(parse_expr("color(age, 0, 0, 1)").is_err());
+
+    let v = parse_expr("vec2(1, 2)").unwrap();
+    assert!(matches!(v, ExprWriterEditor::Vec2(_)));
+    assert_eq!(
+        eval("vec2(1, 2) * 2.0", &inputs),
+        EvalValue::Float(vec![2.0, 4.0])
+    );
+}
+
+#[test]
+fn powers_of_negative_bases() {
+    let inputs = EvalInputs::default();
+    assert_eq!(
+        eval("pow(-2.0, 3.0)", &inputs),
+        EvalValue::Float(vec![-8.0])
+    );
+    assert_eq!(
+        eval("pow(-2.0, -2.0)", &inputs),
+        EvalValue::Float(vec![0.25])
+    );
+    // Exponents that are not literals keep the sign of odd powers too.
+    let EvalValue::Float(v) = eval("pow(-2.0, 1.0 + 2.0)", &inputs) else {
+        panic!();
+    };
+    assert!((v[0] + 8.0).abs() < 1e-4, "{:?}", v);
+    let EvalValue::Float(v) = eval("pow(-2.0, 2.0 + 2.0)", &inputs) else {
+        panic!();
+    };
+    assert!((v[0] - 16.0).abs() < 1e-4, "{:?}", v);
+}
+
+#[test]
+fn mismatched_shapes_are_errors() {
+    let inputs = EvalInputs::default();
+    for text in [
+        "cross(1.0, 2.0)",
+        "cross(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+        "mix(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0), 0.5)",
+        "dot(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+        "distance(vec2(1.0, 2.0), vec4(1.0, 2.0, 3.0, 4.0))",
+        "step(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+        "smoothstep(0.0, vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+        "atan2(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+        "uniform(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))",
+    ] {
+        let result = parse_expr(text).unwrap().eval(&inputs, 0);
+        assert!(result.is_err(), "{}: {:?}", text, result);
+    }
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use common::{example, patched};
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+fn op(operator: ExprOperatorEditor) -> ExprWriterEditor {
+    ExprWriterEditor::operator(operator)
+}
+
+/// Builds the expression into a module and generates its WGSL code.
+fn to_wgsl(expr: &ExprWriterEditor) -> String {
+    let writer = EffectWriter::new();
+    let handle = expr.produce(&writer).expr();
+    let module = writer.finish();
+    let property_layout = PropertyLayout::default();
+    let particle_layout = ParticleLayout::default();
+    let mut context =
+        ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+    context.eval(&module, handle).unwrap()
+}
+
+#[test]
+fn conditional_expression_generates_code() {
+    // if time > 1.0 then red else blue
+    let expr = op(ExprOperatorEditor::Select(
+        op(ExprOperatorEditor::Gt(
+            ExprWriterEditor::Time,
+            ExprWriterEditor::Float(1.0),
+        )),
+        ExprWriterEditor::Vec3(Vec3::X),
+        ExprWriterEditor::Vec3(Vec3::Z),
+    ));
+    assert_eq!(
+        expr.value_type(),
+        Some(ValueType::Vector(VectorType::VEC3F))
+    );
+    assert!(to_wgsl(&expr).contains("mix("));
+}
+
+#[test]
+fn boolean_operators_produce_bools() {
+    let comparison = || {
+        op(ExprOperatorEditor::Le(
+            ExprWriterEditor::Float(0.0),
+            ExprWriterEditor::Time,
+        ))
+    };
+    let exprs = [
+        comparison(),
+        op(ExprOperatorEditor::Eq(
+            ExprWriterEditor::U32(1),
+            ExprWriterEditor::U32(2),
+        )),
+        op(ExprOperatorEditor::Neq(
+            ExprWriterEditor::Vec3(Vec3::ONE),
+            ExprWriterEditor::Vec3(Vec3::ZERO),
+        )),
+        op(ExprOperatorEditor::And(
+            comparison(),
+            ExprWriterEditor::Bool(true),
+        )),
+        op(ExprOperatorEditor::Or(comparison(), comparison())),
+        op(ExprOperatorEditor::Not(comparison())),
+    ];
+    for expr in exprs.iter() {
+        assert_eq!(expr.value_type(), Some(ValueType::Scalar(ScalarType::Bool)));
+        to_wgsl(expr);
+    }
+
+    let vector_comparison = op(ExprOperatorEditor::Lt(
+        ExprWriterEditor::Vec3(Vec3::ONE),
+        ExprWriterEditor::Vec3(Vec3::splat(0.5)),
+    ));
+    assert_eq!(
+        vector_comparison.value_type(),
+        Some(ValueType::Vector(VectorType::VEC3B))
+    );
+}
+
+#[test]
+fn select_between_integers() {
+    let expr = op(ExprOperatorEditor::Select(
+        ExprWriterEditor::Bool(true),
+        ExprWriterEditor::U32(7),
+        ExprWriterEditor::U32(3),
+    ));
+    assert_eq!(expr.value_type(), Some(ValueType::Scalar(ScalarType::Uint)));
+    assert!(!to_wgsl(&expr).contains("mix("));
+
+    let signed = parse_expr("select(age > 1.0, as_i32(-2.0), as_i32(3.0))").unwrap();
+    assert_eq!(
+        signed.value_type(),
+        Some(ValueType::Scalar(ScalarType::Int))
+    );
+    let code = to_wgsl(&signed);
+    assert!(!code.contains("mix("), "{}", code);
+    assert!(code.contains("i32("), "{}", code);
+}
+
+#[test]
+fn type_errors_are_reported_on_the_offending_node() {
+    let mismatch = op(ExprOperatorEditor::Multiply(
+        ExprWriterEditor::Vec3(Vec3::ONE),
+        ExprWriterEditor::U32(2),
+    ));
+    let ExprWriterEditor::Operator(o, _) = &mismatch else {
+        unreachable!()
+    };
+    assert!(o.type_error().is_some());
+    assert_eq!(mismatch.value_type(), None);
+
+    // The parent of an ill-typed node does not report it again.
+    let parent = ExprOperatorEditor::Sin(mismatch);
+    assert!(parent.type_error().is_none());
+
+    let scaled = ExprOperatorEditor::Multiply(
+        ExprWriterEditor::Vec3(Vec3::ONE),
+        ExprWriterEditor::Float(2.0),
+    );
+    assert!(scaled.type_error().is_none());
+    assert_eq!(
+        scaled.value_type(),
+        Some(ValueType::Vector(VectorType::VEC3F))
+    );
+
+    let incomplete =
+        ExprOperatorEditor::Cross(ExprWriterEditor::Placeholder, ExprWriterEditor::Float(1.0));
+    assert!(incomplete.type_error().is_some());
+}
+
+#[test]
+fn attribute_type_mismatch_is_an_error() {
+    let text = patched(
+        &example("explode"),
+        "attr: \"age\",\n                    attr_expr: Float(0.0)",
+        "attr: \"age\",\n                    attr_expr: Vec3((0.0, 0.0, 0.0))",
+    );
+    let project = project_from_str(&text).unwrap();
+    let errors: Vec<String> = project
+        .validate()
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.to_string())
+        .collect();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(
+        errors[0].contains("SetAttributeModifier(age)"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn swizzles_and_casts_have_types_and_code() {
+    let swizzle = parse_expr("attr(velocity).xz").unwrap();
+    assert_eq!(
+        swizzle.value_type(),
+        Some(ValueType::Vector(VectorType::VEC2F))
+    );
+    to_wgsl(&swizzle);
+
+    let cast = parse_expr("as_f32(attr(particle_counter))").unwrap();
+    assert_eq!(
+        cast.value_type(),
+        Some(ValueType::Scalar(ScalarType::Float))
+    );
+    to_wgsl(&cast);
+
+    let color = parse_expr("vec4_xyz_w(rand_vec3(), 1.0)").unwrap();
+    assert_eq!(
+        color.value_type(),
+        Some(ValueType::Vector(VectorType::VEC4F))
+    );
+    let code = to_wgsl(&color);
+    assert!(code.starts_with("vec4("), "{}", code);
+
+    let ExprWriterEditor::Operator(out_of_range, _) = parse_expr("rand_vec3().w").unwrap() else {
+        unreachable!()
+    };
+    assert_eq!(
+        out_of_range.type_error().as_deref(),
+        Some("vec3<f32> has no w component")
+    );
+}
+
+#[test]
+fn built_in_leaves_generate_code() {
+    let flicker = parse_expr("sin(real_time * 20.0) * delta_time").unwrap();
+    assert_eq!(
+        flicker.value_type(),
+        Some(ValueType::Scalar(ScalarType::Float))
+    );
+    let code = to_wgsl(&flicker);
+    assert!(code.contains("real_time"), "{}", code);
+
+    let variation = parse_expr("as_f32(particle_id % 4u) + real_delta_time").unwrap();
+    assert_eq!(
+        variation.value_type(),
+        Some(ValueType::Scalar(ScalarType::Float))
+    );
+    to_wgsl(&variation);
+}
+
+#[test]
+fn whole_powers_are_expanded_into_multiplications() {
+    let cube = to_wgsl(&parse_expr("pow(attr(velocity), 3.0)").unwrap());
+    assert!(!cube.contains("exp("), "{}", cube);
+    assert!(cube.contains(" * "), "{}", cube);
+    let inverse = to_wgsl(&parse_expr("pow(age, -2.0)").unwrap());
+    assert!(
+        inverse.starts_with("(1.") && !inverse.contains("exp("),
+        "{}",
+        inverse
+    );
+    let root = to_wgsl(&parse_expr("pow(age, 0.5)").unwrap());
+    assert!(root.contains("abs("), "{}", root);
+}
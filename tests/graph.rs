@@ -0,0 +1,31 @@
+use omagari::editor_prelude::*;
+
+#[test]
+fn nodes_are_addressed_by_operand_paths() {
+    let expr = parse_expr("pack4x8unorm(vec4(rand_f32(), 0.5, rand_f32(), 1.0))").unwrap();
+    assert_eq!(node_at(&expr, &[]).unwrap().to_string(), expr.to_string());
+    assert_eq!(node_at(&expr, &[0, 1]).unwrap().to_string(), "0.5");
+    assert!(node_at(&expr, &[0, 4]).is_none());
+    assert!(node_at(&expr, &[0, 1, 0]).is_none());
+}
+
+#[test]
+fn connecting_an_output_moves_its_subtree() {
+    let mut expr = parse_expr("mix(age * 2.0, vec3(1.0, 0.0, 0.0), 0.5)").unwrap();
+    assert!(move_subtree(&mut expr, &[0, 1], &[2]));
+    assert_eq!(expr.to_string(), "mix(age * _, vec3(1.0, 0.0, 0.0), 2.0)");
+
+    // A subtree cannot be connected inside itself, nor to a missing input.
+    assert!(!move_subtree(&mut expr, &[0], &[0, 1]));
+    assert!(!move_subtree(&mut expr, &[1, 0], &[3]));
+    assert_eq!(expr.to_string(), "mix(age * _, vec3(1.0, 0.0, 0.0), 2.0)");
+
+    // Connecting a node to an input above it replaces that input.
+    assert!(move_subtree(&mut expr, &[0, 0], &[0]));
+    assert_eq!(expr.to_string(), "mix(age, vec3(1.0, 0.0, 0.0), 2.0)");
+
+    // The tree is still the serialized enum, so it round-trips unchanged.
+    let text = ron::ser::to_string(&expr).unwrap();
+    let loaded: ExprWriterEditor = ron::de::from_str(&text).unwrap();
+    assert_eq!(loaded.to_string(), expr.to_string());
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use omagari::editor_prelude::*;
+
+/// Draws `ui` for one frame without any input.
+fn draw_frame<R>(mut draw: impl FnMut(&mut egui::Ui) -> R) -> R {
+    let ctx = egui::Context::default();
+    let mut result = None;
+    let _ = ctx.run(egui::RawInput::default(), |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| result = Some(draw(ui)));
+    });
+    result.unwrap()
+}
+
+#[test]
+fn hdr_colors_are_not_rewritten_when_untouched() {
+    // Splitting and applying the intensity again does not give these back.
+    let colors: Vec<Vec4> = (1..200)
+        .map(|i| Vec4::new(i as f32 * 0.37, 0.7, 0.1, 0.5))
+        .filter(|v| {
+            let (color, intensity) = split_hdr_color(*v);
+            apply_intensity(color, intensity) != *v
+        })
+        .collect();
+    assert!(!colors.is_empty());
+    for v in colors {
+        assert_eq!(draw_frame(|ui| ui_for_hdr_vec4(ui, v)), v);
+    }
+}
+
+#[test]
+fn literals_take_the_range_of_their_slot() {
+    let draw = |text: &str, range: FieldRange| {
+        let mut expr = parse_expr(text).unwrap();
+        let mut app = AppContext::default();
+        draw_frame(|ui| expr.draw_ui(&mut app, ui, 0, range));
+        expr.to_string()
+    };
+    assert_eq!(draw("-3.0", FieldRange::ANY), "-3.0");
+    assert_eq!(draw("-3.0", FieldRange::POSITIVE), "0.0");
+    assert_eq!(
+        draw("vec4(50.0, 2.0, -1.0, 1.0)", FieldRange::COLOR),
+        "vec4(50.0, 2.0, 0.0, 1.0)"
+    );
+    // Operands are not values of the slot.
+    assert_eq!(draw("2.0 - 3.0", FieldRange::POSITIVE), "2.0 - 3.0");
+}
@@ -0,0 +1,45 @@
+use bevy_egui::egui;
+use omagari::editor_prelude::*;
+
+/// Runs one frame with `events` as input and tracks its edits.
+fn frame(edits: &mut EditGeneration, events: Vec<egui::Event>) {
+    let ctx = egui::Context::default();
+    let input = egui::RawInput {
+        events,
+        ..Default::default()
+    };
+    let _ = ctx.run(input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| ui.label("idle"));
+        edits.track_frame(ctx);
+    });
+}
+
+#[test]
+fn idle_frames_do_not_snapshot_the_project() {
+    let mut edits = EditGeneration::default();
+    let start = edits;
+    frame(&mut edits, Vec::new());
+    assert_eq!(edits, start);
+    frame(
+        &mut edits,
+        vec![egui::Event::Key {
+            key: egui::Key::Delete,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: egui::Modifiers::NONE,
+        }],
+    );
+    assert_ne!(edits, start);
+
+    let mut history = ProjectHistory::default();
+    let mut project = OmagariProject::default();
+    history.track(&project, true, edits);
+    project.effects.push(EffectEditor::default());
+    // The project is not compared again until the generation moves.
+    history.track(&project, true, edits);
+    assert!(!history.can_undo());
+    edits.bump();
+    history.track(&project, true, edits);
+    assert!(history.can_undo());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{example, patched};
+use omagari::editor_prelude::*;
+use omagari::migrations::{PROJECT_VERSION, project_from_str};
+
+fn all_ids(project: &mut OmagariProject) -> Vec<NodeId> {
+    let mut ids = Vec::new();
+    for effect in project.effects.iter_mut() {
+        effect.for_each_id_mut(&mut |id| ids.push(*id));
+    }
+    ids
+}
+
+#[test]
+fn ids_are_kept_and_missing_ones_generated() {
+    let mut project = project_from_str(&example("magicburst")).unwrap();
+    let modifiers = project.effects[0].init_modifiers();
+    assert_eq!(modifiers[0].id(), NodeId::from(1));
+    assert_eq!(modifiers[1].id(), NodeId::from(2));
+    // The `Uniform` operator was written without an id.
+    let (_, lifetime, _) = modifiers[1].exprs()[0];
+    let operator_id = lifetime.node_id().unwrap();
+
+    let text = ron::ser::to_string(&project).unwrap();
+    let mut reloaded = project_from_str(&text).unwrap();
+    let (_, lifetime, _) = reloaded.effects[0].init_modifiers()[1].exprs()[0];
+    assert_eq!(lifetime.node_id(), Some(operator_id));
+
+    let mut ids = all_ids(&mut reloaded);
+    let n_ids = ids.len();
+    ids.sort_by_key(|id| id.value());
+    ids.dedup();
+    assert_eq!(ids.len(), n_ids);
+    assert_eq!(all_ids(&mut project).len(), n_ids);
+}
+
+#[test]
+fn duplicated_ids_are_replaced_on_load() {
+    let text = patched(&example("magicburst"), "id: 2,", "id: 1,");
+    let project = project_from_str(&text).unwrap();
+    let modifiers = project.effects[0].init_modifiers();
+    assert_eq!(modifiers[0].id(), NodeId::from(1));
+    assert_ne!(modifiers[1].id(), NodeId::from(1));
+
+    let mut expr = parse_expr("sin(age * 2.0)").unwrap();
+    let copy = expr.clone();
+    expr.renew_ids();
+    assert_ne!(expr.node_id(), copy.node_id());
+}
+
+#[test]
+fn ids_follow_rewired_subtrees_and_locate_diagnostics() {
+    let mut expr = parse_expr("mix(age * 2.0, vec3(1.0, 0.0, 0.0), 0.5)").unwrap();
+    let multiply = node_at(&expr, &[0]).unwrap().node_id();
+    assert!(move_subtree(&mut expr, &[0], &[1]));
+    assert_eq!(node_at(&expr, &[1]).unwrap().node_id(), multiply);
+
+    let text = patched(
+        &example("magicburst"),
+        "attr_expr: Operator(Uniform(Float(0.6), Float(4.3)))",
+        "attr_expr: Operator(Uniform(Float(0.6), U32(4)), 42)",
+    );
+    let project = project_from_str(&text).unwrap();
+    let diagnostic = project
+        .validate()
+        .into_iter()
+        .find(|d| d.severity == Severity::Error)
+        .unwrap();
+    assert_eq!(diagnostic.node, Some(NodeId::from(42)));
+}
+
+#[test]
+fn effects_have_ids_and_diagnostics_locate_leaves() {
+    let text = format!(
+        "(version: {}, effects: [(
+            id: 7,
+            name: \"Leaves\",
+            parent: None,
+            capacity: 1,
+            spawner_settings: (count: Single(1.0), spawn_duration: Single(0.0), period: Single(0.0),
+                cycle_count: 1, starts_active: true, emit_on_start: true),
+            init_modifiers: [SetAttribute((id: 1, attr: \"lifetime\",
+                attr_expr: Operator(Add(Float(1.0), Property(\"missing\")), 42)))],
+            update_modifiers: [SetAttribute((id: 2, attr: \"age\", attr_expr: Placeholder))],
+            render_modifiers: [],
+        )])",
+        PROJECT_VERSION
+    );
+    let project = project_from_str(&text).unwrap();
+    assert_eq!(project.effects[0].id(), NodeId::from(7));
+
+    let locations: Vec<_> = project
+        .validate()
+        .into_iter()
+        .map(|d| (d.node, d.operand))
+        .collect();
+    assert!(locations.contains(&(Some(NodeId::from(42)), Some(1))));
+    assert!(locations.contains(&(Some(NodeId::from(2)), Some(0))));
+
+    // Effects read from files written before ids get one.
+    let mut project = project_from_str(&example("magicburst")).unwrap();
+    let effect_ids: Vec<_> = project.effects.iter().map(|e| e.id()).collect();
+    let ids = all_ids(&mut project);
+    assert!(effect_ids.iter().all(|id| ids.contains(id)));
+}
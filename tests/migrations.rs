@@ -0,0 +1,76 @@
+use omagari::document::Document;
+use omagari::migrations::{PROJECT_VERSION, project_from_str};
+use omagari::prelude::*;
+
+#[test]
+fn unversioned_projects_are_upgraded() {
+    let project = OmagariProject::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/fireworks.omagari.ron"
+    ))
+    .unwrap();
+    assert_eq!(project.version, PROJECT_VERSION);
+    assert!(!project.textures.is_empty());
+    for effect in project.effects.iter() {
+        assert_eq!(effect.texture(), Some("Glow1"));
+    }
+}
+
+#[test]
+fn newer_projects_are_rejected() {
+    let text = format!("(version: {}, effects: [])", PROJECT_VERSION + 1);
+    let error = project_from_str(&text).err().unwrap();
+    assert!(error.to_string().contains("newer"), "{}", error);
+}
+
+#[test]
+fn current_projects_are_loaded_as_is() {
+    let text = format!(
+        "(version: {}, textures: [(name: \"Smoke\", path: \"fx/smoke.png\")], effects: [])",
+        PROJECT_VERSION
+    );
+    let project = project_from_str(&text).unwrap();
+    assert_eq!(project.textures.len(), 1);
+    assert_eq!(project.texture_path("Smoke"), Some("fx/smoke.png"));
+}
+
+#[test]
+fn old_files_are_upgraded_step_by_step() {
+    let text = "#![enable(implicit_some)]
+    (
+        effects: [(
+            name: \"Old\", // written before texture libraries
+            parent: None,
+            capacity: 8,
+            spawner_settings: (count: Single(1.0), spawn_duration: Single(0.0), period: Single(0.0),
+                cycle_count: 1, starts_active: true, emit_on_start: true),
+            texture_index: Some(2),
+            init_modifiers: [SetAttribute((attr: \"lifetime\",
+                attr_expr: Operator(Uniform(Float(0.8), Operator(Add(Float(1.0), Float(-0.5e-1)))))))],
+            update_modifiers: [],
+            render_modifiers: [],
+        )],
+    )";
+    let mut project = project_from_str(text).unwrap();
+    assert_eq!(project.version, PROJECT_VERSION);
+    assert_eq!(project.textures.len(), 7);
+    assert_eq!(project.effects[0].texture(), Some("Spark1"));
+
+    let mut ids = Vec::new();
+    project.effects[0].for_each_id_mut(&mut |id| ids.push(id.value()));
+    // The effect, its modifier and its two operators.
+    assert_eq!(ids.len(), 4);
+    assert!(ids.iter().all(|id| *id != 0), "{:?}", ids);
+}
+
+#[test]
+fn documents_keep_names() {
+    let text = "Some((a: [A(1), B(x: \"\\\"s\\\"\"), C((1, 2))], b: {'k': U}, c: r#\"raw\"#))";
+    let document = Document::parse(text).unwrap();
+    assert_eq!(
+        document.to_string(),
+        "Some((a: [A(1), B(x: \"\\\"s\\\"\"), C((1, 2))], b: {'k': U}, c: r#\"raw\"#))"
+    );
+    let error = Document::parse("(a: [1, 2)").err().unwrap();
+    assert_eq!((error.line, error.column), (1, 10));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::example;
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+fn folded(text: &str) -> String {
+    fold_constants(&parse_expr(text).unwrap(), &[]).to_string()
+}
+
+#[test]
+fn constant_subtrees_become_literals() {
+    assert_eq!(folded("2.0 * 3.0 + age"), "6.0 + age");
+    assert_eq!(folded("vec3(1.0, 2.0, 3.0) * 2.0"), "vec3(2.0, 4.0, 6.0)");
+    assert_eq!(folded("select(1u > 2u, 1u, 2u)"), "2u");
+    // Random values, inputs and values without a WGSL literal stay.
+    assert_eq!(folded("uniform(1.0, 2.0)"), "uniform(1.0, 2.0)");
+    assert_eq!(folded("1.0 / 0.0"), "1.0 / 0.0");
+    assert_eq!(folded("as_i32(1.5)"), "as_i32(1.5)");
+    assert_eq!(folded("vec2(age, 1.0 + 1.0)"), "vec2(age, 2.0)");
+    assert_eq!(folded("vec2(1.0, 2.0) * 2.0"), "vec2(2.0, 4.0)");
+    // Ill-typed constants are kept for validation to report.
+    assert_eq!(folded("cross(1.0, 2.0)"), "cross(1.0, 2.0)");
+    assert_eq!(
+        folded("mix(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0), 0.5)"),
+        "mix(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0), 0.5)"
+    );
+    assert_eq!(
+        folded("dot(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))"),
+        "dot(vec2(1.0, 2.0), vec3(1.0, 2.0, 3.0))"
+    );
+
+    let definitions = vec![ExprDefinition::new(
+        "speed",
+        parse_expr("2.0 * 4.0").unwrap(),
+    )];
+    let expr = parse_expr("ref(speed) * time").unwrap();
+    assert_eq!(
+        fold_constants(&expr, &definitions).to_string(),
+        "8.0 * time"
+    );
+}
+
+#[test]
+fn identical_subtrees_are_produced_once() {
+    let writer = EffectWriter::new().with_optimization(true);
+    let a = parse_expr("sin(age * 2.0)").unwrap();
+    let b = parse_expr("sin(age * (1.0 + 1.0))").unwrap();
+    assert_eq!(writer.produce(&a).expr(), writer.produce(&b).expr());
+
+    let random = parse_expr("rand_f32() * 2.0").unwrap();
+    assert_ne!(
+        writer.produce(&random).expr(),
+        writer.produce(&random).expr()
+    );
+}
+
+#[test]
+fn node_counts_shrink_for_the_examples() {
+    for name in [
+        "explode",
+        "fireworks",
+        "greenvapor",
+        "magicburst",
+        "randomfireworks",
+    ] {
+        let project = project_from_str(&example(name)).unwrap();
+        for effect in project.effects.iter() {
+            let counts = effect.node_counts(&project.definitions);
+            assert!(counts.after <= counts.before, "{}: {:?}", name, counts);
+        }
+    }
+
+    let exprs = [
+        parse_expr("attr(velocity) * 2.0").unwrap(),
+        parse_expr("attr(velocity) * 2.0 + rand_vec3()").unwrap(),
+        parse_expr("rand_f32() + rand_f32()").unwrap(),
+    ];
+    let counts = NodeCounts::of(exprs.iter(), &[]);
+    assert_eq!(
+        counts,
+        NodeCounts {
+            before: 11,
+            after: 8
+        }
+    );
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use common::{example, patched};
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+/// The explode example with a `heat` property scaling the particle size.
+fn project_with_property(referenced: &str) -> OmagariProject {
+    let text = patched(
+        &example("explode"),
+        "update_modifiers: [],",
+        "update_modifiers: [],\n            properties: [(name: \"heat\", default: Float(2.0))],",
+    );
+    let text = patched(
+        &text,
+        "attr_expr: Float(0.45)",
+        &format!(
+            "attr_expr: Operator(Multiply(Float(0.45), Property(\"{}\")))",
+            referenced
+        ),
+    );
+    project_from_str(&text).unwrap()
+}
+
+fn errors(project: &OmagariProject) -> Vec<String> {
+    project
+        .validate()
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.to_string())
+        .collect()
+}
+
+#[test]
+fn properties_are_declared_in_the_exported_effect() {
+    let project = project_with_property("heat");
+    assert!(errors(&project).is_empty(), "{:#?}", errors(&project));
+
+    let exported = ExportedProject::from(&project);
+    let effect = &exported.effects[0];
+    assert_eq!(
+        effect.properties,
+        vec![EffectProperty::new("heat", EffectPropertyValue::Float(2.0))]
+    );
+    let declared: Vec<&str> = effect
+        .effect_asset
+        .module()
+        .properties()
+        .iter()
+        .map(|p| p.name())
+        .collect();
+    assert_eq!(declared, vec!["heat"]);
+
+    let text = ron::ser::to_string(&exported).unwrap();
+    let loaded: ExportedProject = ron::de::from_str(&text).unwrap();
+    assert_eq!(loaded.effects[0].properties, effect.properties);
+}
+
+#[test]
+fn unknown_properties_are_errors() {
+    let project = project_with_property("cold");
+    let errors = errors(&project);
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(errors[0].contains("Property 'cold'"), "{}", errors[0]);
+}
+
+#[test]
+fn property_names_must_be_identifiers() {
+    assert!(is_valid_property_name("spark_color2"));
+    assert!(!is_valid_property_name("2fast"));
+    assert!(!is_valid_property_name("with space"));
+    assert!(!is_valid_property_name("_"));
+}
+
+#[test]
+fn properties_have_a_text_syntax() {
+    let expr = parse_expr("prop(heat) * 2.0").unwrap();
+    assert_eq!(expr.to_string(), "prop(heat) * 2.0");
+}
+
+#[test]
+fn renames_follow_leaves_and_preview_values() {
+    let mut project = project_with_property("heat");
+    let mut preview = PreviewProperties::default();
+    let effect = project.effects[0].name().to_string();
+    preview.set(&effect, "heat", EffectPropertyValue::Float(3.0));
+
+    project.effects[0].rename_property("heat", "warmth");
+    preview.rename(&effect, "heat", "warmth");
+    assert!(errors(&project).is_empty(), "{:#?}", errors(&project));
+    assert_eq!(project.effects[0].properties()[0].name, "warmth");
+    assert_eq!(
+        preview.get(&effect, "warmth"),
+        Some(EffectPropertyValue::Float(3.0))
+    );
+    assert_eq!(preview.get(&effect, "heat"), None);
+}
+
+#[test]
+fn properties_have_their_declared_type() {
+    let expr = parse_expr("prop(heat) * 2.0").unwrap();
+    assert_eq!(expr.value_type(), None);
+    let declared = [EffectProperty::new(
+        "heat",
+        EffectPropertyValue::Vec3(Vec3::ONE),
+    )];
+    let context = TypeContext::default().with_properties(&declared);
+    assert_eq!(
+        expr.value_type_in(&context),
+        Some(ValueType::Vector(VectorType::VEC3F))
+    );
+    let ExprWriterEditor::Operator(o, _) = parse_expr("prop(heat) && true").unwrap() else {
+        panic!();
+    };
+    assert!(o.type_error_in(&context).is_some());
+    assert!(o.type_error().is_none());
+}
+
+#[test]
+fn casts_take_the_shape_of_their_operand() {
+    let mut writer = EffectWriter::new();
+    writer.add_property(&EffectProperty::new(
+        "heat",
+        EffectPropertyValue::Vec3(Vec3::ONE),
+    ));
+    let cast = parse_expr("as_u32(prop(heat))").unwrap();
+    let handle = cast.produce(&writer).expr();
+    let unknown = parse_expr("as_u32(_)").unwrap().produce(&writer).expr();
+    let module = writer.finish();
+
+    let property_layout = PropertyLayout::new(module.properties());
+    let particle_layout = ParticleLayout::default();
+    let mut context =
+        ShaderWriter::new(ModifierContext::Update, &property_layout, &particle_layout);
+    let code = context.eval(&module, handle).unwrap();
+    assert!(code.starts_with("vec3<u32>("), "{}", code);
+    // Without a type there is no shape to convert to.
+    assert_eq!(context.eval(&module, unknown).unwrap(), "0u");
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use common::{example, patched};
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+/// Builds the expression into a module and generates its WGSL code.
+fn to_wgsl(expr: &ExprWriterEditor) -> String {
+    let writer = EffectWriter::new();
+    let handle = expr.produce(&writer).expr();
+    let module = writer.finish();
+    let property_layout = PropertyLayout::default();
+    let particle_layout = ParticleLayout::default();
+    let mut context = ShaderWriter::new(ModifierContext::Init, &property_layout, &particle_layout);
+    context.eval(&module, handle).unwrap()
+}
+
+#[test]
+fn ranges_are_written_as_random_calls() {
+    for (text, value_type) in [
+        ("random(0.8, 1.2)", ValueType::Scalar(ScalarType::Float)),
+        (
+            "random_normal(vec3(-10.0, 140.0, -10.0), vec3(10.0, 160.0, 10.0))",
+            ValueType::Vector(VectorType::VEC3F),
+        ),
+        (
+            "random(color(1.0, 0.5, 0.0, 1.0, 4.0), color(1.0, 1.0, 1.0, 0.0))",
+            ValueType::Vector(VectorType::VEC4F),
+        ),
+        ("random_on_sphere()", ValueType::Vector(VectorType::VEC3F)),
+        ("random_in_disc()", ValueType::Vector(VectorType::VEC2F)),
+    ] {
+        let expr = parse_expr(text).unwrap();
+        assert!(matches!(expr, ExprWriterEditor::RandomRange(_)), "{}", text);
+        assert_eq!(expr.to_string(), text);
+        assert_eq!(expr.value_type(), Some(value_type), "{}", text);
+    }
+
+    let error = parse_expr("random(age, 1.0)").err().unwrap();
+    assert_eq!(error.position, 0);
+    assert_eq!(error.message, "'random' takes two numbers, vec3s or colors");
+    assert!(parse_expr("random_on_sphere(1.0)").is_err());
+}
+
+#[test]
+fn samples_stay_in_range_and_follow_the_seed() {
+    let inputs = EvalInputs::default();
+    let samples = |text: &str| -> Vec<Vec<f32>> {
+        let expr = parse_expr(text).unwrap();
+        (0..200)
+            .map(|seed| expr.eval(&inputs, seed).unwrap().to_f32s())
+            .collect()
+    };
+
+    for text in ["random(2.0, 5.0)", "random_normal(5.0, 2.0)"] {
+        let values = samples(text);
+        assert!(
+            values.iter().all(|v| (2.0..=5.0).contains(&v[0])),
+            "{}",
+            text
+        );
+        assert!(values.iter().any(|v| v != &values[0]), "{}", text);
+        assert_eq!(values, samples(text), "{}", text);
+    }
+    // Normal values gather in the middle of the range.
+    let middle = samples("random_normal(2.0, 5.0)")
+        .iter()
+        .filter(|v| (3.0..=4.0).contains(&v[0]))
+        .count();
+    assert!(middle > 120, "{}", middle);
+
+    for v in samples("random_on_sphere()") {
+        assert!((Vec3::from_slice(&v).length() - 1.0).abs() < 1e-4);
+    }
+    for v in samples("random_in_disc()") {
+        assert!(Vec2::from_slice(&v).length() <= 1.0);
+    }
+    for v in samples("random(color(0.0, 0.0, 0.0, 1.0), color(1.0, 1.0, 1.0, 1.0, 2.0))") {
+        // A single blend factor, so the channels stay equal.
+        assert!(v[0] == v[1] && v[1] == v[2] && (0.0..=2.0).contains(&v[0]));
+        assert_eq!(v[3], 1.0);
+    }
+}
+
+#[test]
+fn ranges_expand_to_hanabi_primitives() {
+    assert!(to_wgsl(&parse_expr("random(0.8, 1.2)").unwrap()).contains("rand_uniform"));
+    assert!(to_wgsl(&parse_expr("random_normal(0.8, 1.2)").unwrap()).contains("rand_normal"));
+    // The draw for z is stored once and reused for the radius.
+    let code = to_wgsl(&parse_expr("random_on_sphere()").unwrap());
+    assert!(code.matches("var0").count() > 1, "{}", code);
+
+    // Each range draws its own values, so identical ranges are not shared.
+    let writer = EffectWriter::new().with_optimization(true);
+    let range = parse_expr("random(vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0))").unwrap();
+    assert_ne!(writer.produce(&range).expr(), writer.produce(&range).expr());
+
+    let text = patched(&example("randomfireworks"), "seed: 0,", "seed: 7,");
+    let project = project_from_str(&text).unwrap();
+    assert_eq!(
+        project.effects[0].produce(&project.definitions).prng_seed,
+        7
+    );
+}
@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+
+use omagari::document::{Document, Node};
+use omagari::prelude::*;
+use ron::ser::PrettyConfig;
+
+/// Fields that older example files carry but the current schema drops or
+/// migrates on load.
+const MIGRATED_FIELDS: [&str; 1] = ["texture_index"];
+
+fn example_projects() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> =
+        std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".omagari.ron"))
+            .collect();
+    files.sort();
+    assert!(!files.is_empty());
+    files
+}
+
+fn to_ron(project: &OmagariProject) -> String {
+    ron::ser::to_string_pretty(project, PrettyConfig::new().new_line("\n".to_string())).unwrap()
+}
+
+/// Collects every place where `original` holds data that `saved` lost or
+/// changed. Fields added by `saved` are fine, and so is the id it appends to
+/// `Operator(op)`.
+fn lost_data(original: &Node, saved: &Node, path: &str, lost: &mut Vec<String>) {
+    match (original, saved) {
+        (Node::Struct(a, original), Node::Struct(b, saved)) if a == b => {
+            for (name, value) in original.iter() {
+                if MIGRATED_FIELDS.contains(&name.as_str()) {
+                    continue;
+                }
+                let path = format!("{}.{}", path, name);
+                match saved.iter().find(|(n, _)| n == name).map(|(_, v)| v) {
+                    Some(saved_value) => lost_data(value, saved_value, &path, lost),
+                    None => lost.push(format!("{} is missing", path)),
+                }
+            }
+        }
+        (Node::Tuple(a, original), Node::Tuple(b, saved)) if a == b => {
+            let operator_id =
+                a.as_deref() == Some("Operator") && original.len() == 1 && saved.len() == 2;
+            lost_in_items(original, saved, operator_id, path, lost);
+        }
+        (Node::List(original), Node::List(saved)) => {
+            lost_in_items(original, saved, false, path, lost)
+        }
+        (Node::Atom(a), Node::Atom(b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(x), Ok(y)) if (x - y).abs() <= 1e-6 => {}
+            _ if a == b => {}
+            _ => lost.push(format!("{} changed from {} to {}", path, a, b)),
+        },
+        (a, b) => {
+            if a != b {
+                lost.push(format!("{} changed from {} to {}", path, a, b));
+            }
+        }
+    }
+}
+
+fn lost_in_items(
+    original: &[Node],
+    saved: &[Node],
+    operator_id: bool,
+    path: &str,
+    lost: &mut Vec<String>,
+) {
+    if original.len() != saved.len() && !operator_id {
+        lost.push(format!(
+            "{} has {} items instead of {}",
+            path,
+            saved.len(),
+            original.len()
+        ));
+    }
+    for (index, (a, b)) in original.iter().zip(saved.iter()).enumerate() {
+        lost_data(a, b, &format!("{}[{}]", path, index), lost);
+    }
+}
+
+#[test]
+fn examples_round_trip_without_losing_data() {
+    for file in example_projects() {
+        let original_text = std::fs::read_to_string(&file).unwrap();
+        let project = OmagariProject::load(&file).unwrap();
+        for effect in project.effects.iter() {
+            effect.produce(&project.definitions);
+        }
+
+        let original = Document::parse(&original_text).unwrap();
+        let saved = Document::parse(&to_ron(&project)).unwrap();
+        let mut lost = Vec::new();
+        lost_data(&original.root, &saved.root, "", &mut lost);
+        assert!(lost.is_empty(), "{}: {:#?}", file.display(), lost);
+    }
+}
+
+#[test]
+fn set_attribute_ids_are_saved() {
+    let file = PathBuf::from(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/fireworks.omagari.ron"
+    ));
+    let original_text = std::fs::read_to_string(&file).unwrap();
+    assert!(original_text.contains("id: 16982844757743701546,"));
+    let saved = to_ron(&OmagariProject::load(&file).unwrap());
+    assert!(saved.contains("id: 16982844757743701546,"), "{}", saved);
+}
+
+#[test]
+fn saving_a_loaded_project_is_stable() {
+    for file in example_projects() {
+        let saved = to_ron(&OmagariProject::load(&file).unwrap());
+        let reloaded: OmagariProject = ron::de::from_str(&saved).unwrap();
+        assert_eq!(saved, to_ron(&reloaded), "{}", file.display());
+    }
+}
+
+#[test]
+fn examples_export_and_reload() {
+    for file in example_projects() {
+        let project = OmagariProject::load(&file).unwrap();
+        let exported = ExportedProject::from(&project);
+        let text = ron::ser::to_string(&exported).unwrap();
+        let reloaded: ExportedProject = ron::de::from_str(&text).unwrap();
+        assert_eq!(reloaded.effects.len(), project.effects.len());
+        for (effect, editor) in reloaded.effects.iter().zip(project.effects.iter()) {
+            assert_eq!(effect.name, editor.name());
+            assert_eq!(effect.parent, editor.parent());
+        }
+    }
+}
+
+#[test]
+fn examples_are_valid() {
+    for file in example_projects() {
+        let project = OmagariProject::load(&file).unwrap();
+        let errors: Vec<String> = project
+            .validate()
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{}: {:#?}", file.display(), errors);
+    }
+}
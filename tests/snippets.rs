@@ -0,0 +1,43 @@
+use omagari::editor_prelude::*;
+use omagari::migrations::project_from_str;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir()
+        .join(format!("omagari-test-{}", std::process::id()))
+        .join(name)
+}
+
+#[test]
+fn user_snippets_roundtrip_through_their_file() {
+    let path = temp_path("snippets.ron");
+    assert!(UserSnippets::load(&path).unwrap().snippets.is_empty());
+
+    let user = UserSnippets {
+        snippets: builtin_snippets(),
+    };
+    user.save(&path).unwrap();
+    let loaded = UserSnippets::load(&path).unwrap();
+    assert_eq!(loaded.snippets.len(), 1);
+    assert_eq!(loaded.snippets[0].name, "Random Normalized Vector");
+    assert_eq!(
+        loaded.snippets[0].expr.to_string(),
+        "normalize(rand_vec3() * 2.0 - 1.0)"
+    );
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn saved_expressions_become_uniquely_named_project_snippets() {
+    let mut project = OmagariProject::default();
+    let mut library = SnippetLibrary::default();
+    library.save(parse_expr("rand_f32() * 2.0").unwrap());
+    library.save(parse_expr("rand_f32() * 2.0").unwrap());
+    library.apply_saved(&mut project);
+
+    let names: Vec<&str> = project.snippets.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["rand_f32() * 2.0", "rand_f32() * 2.0 2"]);
+
+    let text = ron::ser::to_string(&project).unwrap();
+    let loaded = project_from_str(&text).unwrap();
+    assert_eq!(loaded.snippets.len(), 2);
+}
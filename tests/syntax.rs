@@ -0,0 +1,120 @@
+use omagari::editor_prelude::*;
+use omagari::syntax::parse_expr;
+
+fn example_exprs() -> Vec<ExprWriterEditor> {
+    let mut exprs = Vec::new();
+    for entry in std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.to_string_lossy().ends_with(".omagari.ron") {
+            continue;
+        }
+        let project = OmagariProject::load(&path).unwrap();
+        for effect in project.effects.iter() {
+            for modifier in effect
+                .init_modifiers()
+                .iter()
+                .chain(effect.update_modifiers().iter())
+            {
+                for (_, expr, _) in modifier.exprs() {
+                    exprs.push(expr.clone());
+                }
+            }
+        }
+    }
+    assert!(!exprs.is_empty());
+    exprs
+}
+
+#[test]
+fn printed_examples_parse_back() {
+    for expr in example_exprs() {
+        let text = expr.to_string();
+        let parsed = parse_expr(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(parsed.to_string(), text);
+    }
+}
+
+#[test]
+fn non_finite_floats_parse_back() {
+    for text in [
+        "inf",
+        "-inf",
+        "NaN",
+        "age * -inf",
+        "vec3(inf, NaN, 1.0)",
+        "color(1.0, 0.0, 0.0, 1.0, inf)",
+    ] {
+        let parsed = parse_expr(text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(parsed.to_string(), text);
+    }
+    assert!(matches!(parse_expr("-inf"), Ok(ExprWriterEditor::Float(f)) if f == f32::NEG_INFINITY));
+}
+
+#[test]
+fn precedence_and_parentheses() {
+    let expr = parse_expr("normalize(rand_vec3() * 2.0 - 1.0)").unwrap();
+    let ExprWriterEditor::Operator(op, _) = &expr else {
+        panic!("expected an operator");
+    };
+    assert_eq!(op.name(), "Normalized");
+    assert_eq!(expr.to_string(), "normalize(rand_vec3() * 2.0 - 1.0)");
+
+    for (text, printed) in [
+        ("(1 + 2) * 3", "(1.0 + 2.0) * 3.0"),
+        ("1 - (2 - 3)", "1.0 - (2.0 - 3.0)"),
+        ("(1 - 2) - 3", "1.0 - 2.0 - 3.0"),
+        ("-(age * 2)", "-(age * 2.0)"),
+        ("-2 * time", "-2.0 * time"),
+        (
+            "age > 0.5 && !(time < 1) || false",
+            "age > 0.5 && !(time < 1.0) || false",
+        ),
+        (
+            "select(age >= 1.0, vec3(1, 0, 0), vec3(0, 0, 1))",
+            "select(age >= 1.0, vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0))",
+        ),
+        (
+            "attr(position) + parent_attr(velocity)",
+            "attr(position) + parent_attr(velocity)",
+        ),
+        ("clamp(3u, 1u, 2u)", "clamp(3u, 1u, 2u)"),
+        ("vec3(age, 0, 0)", "vec3(age, 0.0, 0.0)"),
+        ("_ + 1e-3", "_ + 0.001"),
+    ] {
+        assert_eq!(parse_expr(text).unwrap().to_string(), printed, "{}", text);
+    }
+}
+
+#[test]
+fn errors_have_positions() {
+    for (text, position) in [
+        ("sin(age", 7),
+        ("1 + * 2", 4),
+        ("age $ 2", 4),
+        ("foo(1)", 0),
+        ("clamp(1, 2)", 0),
+        ("attr(nope)", 5),
+        ("1 2", 2),
+        ("", 0),
+    ] {
+        let error = parse_expr(text).err().unwrap();
+        assert_eq!(error.position, position, "{}: {}", text, error);
+    }
+}
+
+#[test]
+fn swizzles_and_casts() {
+    for text in [
+        "attr(velocity).xz",
+        "-attr(position).y",
+        "(rand_vec3() * 2.0).zyx",
+        "as_f32(attr(particle_counter)) * 0.1",
+        "vec4_xyz_w(rand_vec3(), 1.0)",
+        "vec2(age, 1.0)",
+    ] {
+        let parsed = parse_expr(text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(parsed.to_string(), text);
+    }
+    let error = parse_expr("attr(position).xq").err().unwrap();
+    assert_eq!(error.position, 15);
+}